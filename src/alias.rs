@@ -0,0 +1,500 @@
+use crate::{
+    error::Error,
+    event::Event,
+    trace::Trace,
+    tracer::Tracer,
+    transport::Transport,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+// TTL of the probes sent to the interfaces themselves.
+//
+const DIRECT_TTL: u8 = 64;
+
+// How much faster than estimated a shared counter may grow between two
+// samples, and by how many IDs more, the allowance Ally gives the packets the
+// router sends to others in the meantime.
+//
+const SLACK: f64 = 2.0;
+const FUDGE: f64 = 200.0;
+
+// IP-ID of a reply from an interface and when the router has most likely
+// sent it, halfway through the round trip.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub ip_id: u16,
+}
+
+// Finds which of the interfaces seen in the traces belong to the same router
+// by the IP-ID counters the routers share between their interfaces, the way
+// MIDAR does (Keys et al., "Internet-Scale IPv4 Alias Resolution with MIDAR",
+// IEEE/ACM ToN, 2013). All the interfaces are sampled in rounds, those whose
+// IDs grow monotonically are paired up by the monotonic bounds test on the
+// samples, and every pair left is probed again, alternately as Ally does,
+// and tested once more. Only IPv4 is resolved, IPv6 headers have no IDs.
+//
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    rounds: u16,
+    wait: Duration,
+    ident: u16,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            rounds: 5,
+            wait: Duration::from_secs(1),
+            ident: std::process::id() as u16,
+        }
+    }
+
+    // Number of samples of each interface in each stage.
+    //
+    pub fn rounds(mut self, rounds: u16) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    pub fn ident(mut self, ident: u16) -> Self {
+        self.ident = ident;
+        self
+    }
+
+    // The routers with more than one interface, each with its addresses in
+    // order.
+    //
+    pub fn resolve<T: Transport + ?Sized>(
+        &self,
+        transport: &T,
+        traces: &[Trace],
+    ) -> Result<Vec<Vec<IpAddr>>, Error> {
+        let excluded = exclusions(traces);
+        let mut sequence: u16 = 0;
+
+        let mut addresses: Vec<IpAddr> = traces.iter()
+            .flat_map(|trace| trace.hops.iter())
+            .flat_map(|hop| hop.responders())
+            .filter(IpAddr::is_ipv4)
+            .collect();
+
+        addresses.sort();
+        addresses.dedup();
+
+        let mut samples: HashMap<IpAddr, Vec<Sample>> = HashMap::new();
+
+        for round in 0..self.rounds {
+            for address in &addresses {
+                // Those which have not replied at first are not waited for
+                // again.
+                //
+                if round > 0 && !samples.contains_key(address) {
+                    continue
+                }
+
+                if let Some(sample) = self.sample(transport, *address, &mut sequence)? {
+                    samples.entry(*address).or_default().push(sample);
+                }
+            }
+        }
+
+        let usable: Vec<IpAddr> = addresses.into_iter()
+            .filter(|address| {
+                samples.get(address).and_then(|samples| velocity(samples)).is_some()
+            })
+            .collect();
+
+        let mut routers = Routers::new(&usable);
+
+        for (index, first) in usable.iter().enumerate() {
+            for second in &usable[index + 1..] {
+                if excluded.contains(&(*first, *second)) ||
+                    !shares_counter(&samples[first], &samples[second])
+                {
+                    continue
+                }
+
+                let mut pair = [samples[first].clone(), samples[second].clone()];
+
+                for _ in 0..self.rounds {
+                    for (address, samples) in [first, second].iter().zip(&mut pair) {
+                        samples.extend(self.sample(transport, **address, &mut sequence)?);
+                    }
+                }
+
+                if shares_counter(&pair[0], &pair[1]) {
+                    routers.join(*first, *second);
+                }
+            }
+        }
+
+        Ok(routers.groups())
+    }
+
+    // Sends an echo request to the interface and takes the IP-ID of its reply,
+    // if the interface itself replies.
+    //
+    fn sample<T: Transport + ?Sized>(
+        &self,
+        transport: &T,
+        address: IpAddr,
+        sequence: &mut u16,
+    ) -> Result<Option<Sample>, Error> {
+        let tracer = Tracer::new(address).ident(self.ident).wait(self.wait);
+
+        let probe = tracer.probe_ttl(
+            transport,
+            &mut |_: &Event| {},
+            DIRECT_TTL,
+            *sequence,
+        )?;
+
+        *sequence = sequence.wrapping_add(1);
+
+        Ok(match (probe.response(), probe.rtt()) {
+            (Some(response), Some(rtt))
+                if response.source == address && response.is_echo_reply() =>
+            {
+                Some(Sample { time: probe.sent + rtt / 2, ip_id: response.ip_id })
+            }
+            _ => None,
+        })
+    }
+}
+
+// How fast the counter grows, in IDs a second, if the samples are of a
+// counter which grows slowly enough to be followed: every one of them is
+// ahead of the one before by less than half of the range of the IDs.
+//
+pub fn velocity(samples: &[Sample]) -> Option<f64> {
+    let mut samples = samples.to_vec();
+    samples.sort_by_key(|sample| sample.time);
+
+    let elapsed = samples.last()?.time
+        .duration_since(samples.first()?.time)
+        .ok()?
+        .as_secs_f64();
+
+    if elapsed <= 0.0 {
+        return None
+    }
+
+    let mut total: u64 = 0;
+
+    for pair in samples.windows(2) {
+        total += u64::from(step(pair[0], pair[1])?);
+    }
+
+    Some(total as f64 / elapsed)
+}
+
+// The monotonic bounds test: samples of two interfaces sharing a counter
+// taken together in the order they were sent in still grow monotonically,
+// and not much faster than either of them alone. The samples have to
+// alternate both ways, the test tells nothing otherwise.
+//
+pub fn shares_counter(first: &[Sample], second: &[Sample]) -> bool {
+    let velocity = match (velocity(first), velocity(second)) {
+        (Some(first), Some(second)) => first.max(second),
+        _ => return false,
+    };
+
+    let mut samples: Vec<(Sample, bool)> = first.iter().map(|sample| (*sample, false))
+        .chain(second.iter().map(|sample| (*sample, true)))
+        .collect();
+
+    samples.sort_by_key(|(sample, _)| sample.time);
+
+    let alternations = samples.windows(2)
+        .filter(|pair| pair[0].1 != pair[1].1)
+        .count();
+
+    alternations >= 2 && samples.windows(2).all(|pair| {
+        let ((from, _), (to, _)) = (pair[0], pair[1]);
+
+        let elapsed = to.time.duration_since(from.time)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        step(from, to).is_some_and(|step| {
+            f64::from(step) <= velocity * elapsed * SLACK + FUDGE
+        })
+    })
+}
+
+// By how much the counter has grown from one sample to the next, if it
+// looks like it has.
+//
+fn step(from: Sample, to: Sample) -> Option<u16> {
+    Some(to.ip_id.wrapping_sub(from.ip_id)).filter(|step| *step > 0 && *step < 0x8000)
+}
+
+// A router is on a path once, so the interfaces which have replied at
+// different TTLs of one trace are not its aliases. The pairs are in order.
+//
+fn exclusions(traces: &[Trace]) -> HashSet<(IpAddr, IpAddr)> {
+    let mut result = HashSet::new();
+
+    for trace in traces {
+        let hops: Vec<Vec<IpAddr>> = trace.hops.iter().map(|hop| hop.responders()).collect();
+
+        for (index, hop) in hops.iter().enumerate() {
+            for later in &hops[index + 1..] {
+                for first in hop {
+                    for second in later.iter().filter(|second| *second != first) {
+                        result.insert((*first.min(second), *first.max(second)));
+                    }
+                }
+            }
+        }
+    }
+
+    result
+}
+
+// Union-find of the interfaces found to share counters.
+//
+struct Routers {
+    addresses: Vec<IpAddr>,
+    parents: Vec<usize>,
+}
+
+impl Routers {
+    fn new(addresses: &[IpAddr]) -> Self {
+        Self {
+            addresses: addresses.to_vec(),
+            parents: (0..addresses.len()).collect(),
+        }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+
+        index
+    }
+
+    fn join(&mut self, first: IpAddr, second: IpAddr) {
+        let index = |address| self.addresses.iter().position(|other| *other == address);
+
+        if let (Some(first), Some(second)) = (index(first), index(second)) {
+            let (first, second) = (self.find(first), self.find(second));
+
+            self.parents[second.max(first)] = second.min(first);
+        }
+    }
+
+    fn groups(mut self) -> Vec<Vec<IpAddr>> {
+        let mut groups: HashMap<usize, Vec<IpAddr>> = HashMap::new();
+
+        for index in 0..self.addresses.len() {
+            let root = self.find(index);
+            groups.entry(root).or_default().push(self.addresses[index]);
+        }
+
+        let mut result: Vec<Vec<IpAddr>> = groups.into_values()
+            .filter(|group| group.len() > 1)
+            .collect();
+
+        result.sort();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures,
+        response::TIME_EXCEEDED,
+        sockaddr_inx::SockaddrInx,
+    };
+    use std::{cell::RefCell, collections::VecDeque, net::Ipv4Addr};
+
+    fn address(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))
+    }
+
+    fn samples(start_ms: u64, ids: &[u16]) -> Vec<Sample> {
+        ids.iter().zip(0..)
+            .map(|(ip_id, index)| Sample {
+                time: SystemTime::UNIX_EPOCH
+                    + Duration::from_millis(start_ms + index * 100),
+                ip_id: *ip_id,
+            })
+            .collect()
+    }
+
+    // Routers answering the echo requests from counters shared by their
+    // interfaces, which grow by one with every reply. The clock moves on by
+    // 25 ms with every request.
+    //
+    struct Network {
+        routers: HashMap<IpAddr, usize>,
+        state: RefCell<State>,
+    }
+
+    struct State {
+        now: SystemTime,
+        counters: Vec<u16>,
+        replies: VecDeque<(Vec<u8>, IpAddr)>,
+    }
+
+    impl Network {
+        fn new(routers: &[(&[IpAddr], u16)]) -> Self {
+            Self {
+                routers: routers.iter().enumerate()
+                    .flat_map(|(index, (addresses, _))| {
+                        addresses.iter().map(move |address| (*address, index))
+                    })
+                    .collect(),
+                state: RefCell::new(State {
+                    now: SystemTime::UNIX_EPOCH,
+                    counters: routers.iter().map(|(_, start)| *start).collect(),
+                    replies: VecDeque::new(),
+                }),
+            }
+        }
+    }
+
+    impl Transport for Network {
+        fn now(&self) -> SystemTime {
+            self.state.borrow().now
+        }
+
+        fn set_ttl(&self, _ttl: u8) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn set_tos(&self, _tos: u8) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn send_to(&self, message: &[u8], host: IpAddr) -> Result<(), Error> {
+            let mut state = self.state.borrow_mut();
+
+            state.now += Duration::from_millis(25);
+
+            if let Some(router) = self.routers.get(&host) {
+                let ip_id = state.counters[*router];
+                state.counters[*router] = ip_id.wrapping_add(1);
+
+                let mut packet = vec![0; 20];
+                packet[4..6].copy_from_slice(&ip_id.to_be_bytes());
+                packet[8] = 64;
+                packet.extend_from_slice(&[0, 0, 0, 0]);
+                packet.extend_from_slice(&message[4..8]);
+
+                state.replies.push_back((packet, host));
+            }
+
+            Ok(())
+        }
+
+        fn recv_until(
+            &self,
+            buffer: &mut [u8],
+            deadline: SystemTime,
+        ) -> Result<Option<(usize, SockaddrInx)>, Error> {
+            let mut state = self.state.borrow_mut();
+
+            match state.replies.pop_front() {
+                Some((packet, host)) => {
+                    state.now += Duration::from_millis(1);
+                    buffer[..packet.len()].copy_from_slice(&packet);
+
+                    Ok(Some((packet.len(), SockaddrInx::from_ip_addr(host))))
+                }
+                None => {
+                    state.now = deadline;
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    fn trace(responders: &[IpAddr]) -> Trace {
+        fixtures::trace(
+            *responders.last().unwrap(),
+            responders.iter()
+                .map(|source| vec![fixtures::reply(*source, TIME_EXCEEDED, 1000)])
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn counter_velocity() {
+        assert_eq!(velocity(&samples(0, &[100, 110, 120])), Some(100.0));
+        assert_eq!(velocity(&samples(0, &[65_530, 4, 14])), Some(100.0));
+        assert_eq!(velocity(&samples(0, &[100, 90, 120])), None);
+        assert_eq!(velocity(&samples(0, &[0, 0, 0])), None);
+        assert_eq!(velocity(&samples(0, &[100])), None);
+    }
+
+    #[test]
+    fn monotonic_bounds() {
+        let first = samples(0, &[100, 120, 140, 160]);
+
+        assert!(shares_counter(&first, &samples(50, &[110, 130, 150, 170])));
+        assert!(shares_counter(&first, &samples(50, &[112, 131, 149, 171])));
+
+        // Out of order, too far ahead, and not interleaved.
+        //
+        assert!(!shares_counter(&first, &samples(50, &[130, 115, 150, 170])));
+        assert!(!shares_counter(&first, &samples(50, &[5_110, 5_130, 5_150, 5_170])));
+        assert!(!shares_counter(&first, &samples(1000, &[200, 220, 240])));
+    }
+
+    #[test]
+    fn traces_exclude_aliases() {
+        let excluded = exclusions(&[trace(&[address(1), address(2), address(3)])]);
+
+        assert!(excluded.contains(&(address(1), address(3))));
+        assert!(excluded.contains(&(address(2), address(3))));
+        assert!(!excluded.contains(&(address(1), address(4))));
+    }
+
+    #[test]
+    fn resolve() {
+        let network = Network::new(&[
+            (&[address(1), address(11), address(21)], 1000),
+            (&[address(2), address(12)], 1050),
+            (&[address(3)], 1100),
+        ]);
+
+        // The counters of the first two routers are close, but not close
+        // enough. 192.0.2.13 does not reply.
+        //
+        let traces = [
+            trace(&[address(1), address(2), address(3)]),
+            trace(&[address(11), address(12), address(3)]),
+            trace(&[address(21), address(13)]),
+        ];
+
+        let routers = Resolver::new().resolve(&network, &traces).unwrap();
+
+        assert_eq!(routers, vec![
+            vec![address(1), address(11), address(21)],
+            vec![address(2), address(12)],
+        ]);
+    }
+}
@@ -0,0 +1,445 @@
+use crate::names::Names;
+use rustraceroute::{
+    asn::{AsDatabase, Origin},
+    geoip::{GeoDatabase, Location},
+    hints::{self, Hint, Hints},
+    ranges::RangeDatabase,
+    rpki::{Validity, VrpTable},
+    special,
+    Trace,
+};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+// Difference in hops between the paths there and back from which the
+// routing is taken for asymmetric. The path back is often a hop or two
+// longer or shorter, with the initial TTLs being guessed.
+//
+const ASYMMETRY: u8 = 3;
+
+// What is known about the responders besides their replies: their names, the
+// networks they belong to and whether these may originate them, the clouds
+// and exchanges they are in, where they are and where their names say they
+// are. Cloning gives another handle to the same data.
+//
+#[derive(Clone)]
+pub struct Annotations {
+    pub names: Names,
+    asns: Option<Arc<AsDatabase>>,
+    geo: Option<Arc<GeoDatabase>>,
+    ranges: Option<Arc<RangeDatabase>>,
+    vrps: Option<Arc<VrpTable>>,
+    hints: Option<Arc<Hints>>,
+}
+
+// A responder of the path as the warnings see it: its TTL, its best RTT and
+// the length of the path its replies have come back by.
+//
+#[derive(Clone, Copy, Debug)]
+pub struct Responder {
+    pub ttl: u8,
+    pub address: IpAddr,
+    pub rtt: Option<Duration>,
+    pub return_hops: Option<u8>,
+}
+
+// Marks the responders where the path enters another AS, one responder after
+// another in the order of the path.
+//
+#[derive(Default)]
+pub struct Boundaries {
+    // Origins of the last responder whose origins are known.
+    //
+    previous: Vec<u32>,
+}
+
+impl From<Names> for Annotations {
+    fn from(names: Names) -> Self {
+        Self { names, asns: None, geo: None, ranges: None, vrps: None, hints: None }
+    }
+}
+
+impl Annotations {
+    pub fn with_asns(self, asns: AsDatabase) -> Self {
+        Self { asns: Some(Arc::new(asns)), ..self }
+    }
+
+    pub fn has_asns(&self) -> bool {
+        self.asns.is_some()
+    }
+
+    pub fn with_geo(self, geo: GeoDatabase) -> Self {
+        Self { geo: Some(Arc::new(geo)), ..self }
+    }
+
+    pub fn with_ranges(self, ranges: RangeDatabase) -> Self {
+        Self { ranges: Some(Arc::new(ranges)), ..self }
+    }
+
+    pub fn with_vrps(self, vrps: VrpTable) -> Self {
+        Self { vrps: Some(Arc::new(vrps)), ..self }
+    }
+
+    pub fn with_hints(self, hints: Hints) -> Self {
+        Self { hints: Some(Arc::new(hints)), ..self }
+    }
+
+    pub fn origin(&self, address: IpAddr) -> Option<Origin<'_>> {
+        self.asns.as_ref()?.lookup(address)
+    }
+
+    // The validity of the announcement of the responder's prefix, known with
+    // both the origins and the VRPs.
+    //
+    pub fn validity(&self, address: IpAddr) -> Option<Validity> {
+        Some(self.vrps.as_ref()?.validate_origin(self.origin(address)?))
+    }
+
+    // A record the database fails to read is as good as none, the trace goes
+    // on without it.
+    //
+    pub fn location(&self, address: IpAddr) -> Option<Location> {
+        self.geo.as_ref()?.lookup(address).ok().flatten()
+    }
+
+    // The city and the country of the responder, for the text outputs.
+    //
+    pub fn place(&self, address: IpAddr) -> Option<String> {
+        self.location(address)?.place()
+    }
+
+    // Where the name of the responder says it is, if its name is known.
+    //
+    pub fn hint(&self, address: IpAddr) -> Option<Hint> {
+        let hints = self.hints.as_ref()?;
+
+        hints.locate(&self.names.wait(address)?.name)
+    }
+
+    // The city and the country the name points to, marked with a tilde as
+    // not being more than a guess, for the text outputs.
+    //
+    pub fn hinted_place(&self, address: IpAddr) -> Option<String> {
+        Some(format!("~{}", self.hint(address)?.location.place()?))
+    }
+
+    // Kinds of address space the responder is in, none for the global
+    // unicast addresses, the cloud region or exchange it is in, and whether
+    // its prefix is RPKI-invalid or unknown.
+    //
+    pub fn tags(&self, address: IpAddr) -> Vec<&str> {
+        let range = self.ranges.as_ref()
+            .and_then(|ranges| ranges.lookup(address));
+
+        let validity = self.validity(address)
+            .filter(|validity| *validity != Validity::Valid)
+            .map(|validity| validity.tag());
+
+        special::classify(address).map(|class| class.tag()).into_iter()
+            .chain(range)
+            .chain(validity)
+            .collect()
+    }
+
+    // What looks wrong with the path: the responders in private space past
+    // the public ones, those in the prefixes announced by ASes not authorised
+    // to, those whose names put them farther from the previous named ones
+    // than light in fiber goes within their RTTs, and those whose replies
+    // have come back by a path of a notably different length. The responders
+    // are given in the order of the path, which has been probed from the
+    // first TTL given.
+    //
+    pub fn warnings<I>(&self, first_ttl: u8, responders: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = Responder>,
+    {
+        let responders: Vec<Responder> = responders.into_iter().collect();
+
+        let private = special::private_after_public(responders.iter()
+            .map(|responder| (responder.ttl, responder.address)))
+            .into_iter()
+            .map(|(ttl, address)| format!(
+                "hop {} ({}) is in private address space past public hops",
+                ttl,
+                address,
+            ));
+
+        let invalid = responders.iter()
+            .filter(|responder| {
+                self.validity(responder.address) == Some(Validity::Invalid)
+            })
+            .filter_map(|responder| {
+                let origin = self.origin(responder.address)?;
+
+                Some(format!(
+                    "hop {} ({}) is RPKI-invalid: {} is not authorised for {}",
+                    responder.ttl,
+                    responder.address,
+                    origin.prefix,
+                    origin,
+                ))
+            });
+
+        // The TTL of the probes is only the length of the path there if they
+        // have not reached the responder with one less: a trace starting past
+        // TTL 1 may have reached it in fewer hops at its first TTL.
+        //
+        let asymmetric = responders.iter()
+            .filter(|responder| first_ttl == 1 || responder.ttl > first_ttl)
+            .filter_map(|responder| {
+                let return_hops = responder.return_hops
+                    .filter(|hops| hops.abs_diff(responder.ttl) >= ASYMMETRY)?;
+
+                Some(format!(
+                    "hop {} ({}) has replied with a return path length of {}, \
+                        the paths there and back differ",
+                    responder.ttl,
+                    responder.address,
+                    return_hops,
+                ))
+            });
+
+        private.chain(invalid).chain(self.jumps(&responders)).chain(asymmetric)
+            .collect()
+    }
+
+    pub fn trace_warnings(&self, trace: &Trace) -> Vec<String> {
+        let first_ttl = trace.hops.first().map_or(1, |hop| hop.ttl);
+
+        self.warnings(first_ttl, trace.hops.iter().flat_map(|hop| {
+            hop.responders().into_iter().map(move |address| {
+                let replies = || hop.probes.iter()
+                    .filter(move |probe| {
+                        probe.response()
+                            .is_some_and(|response| response.source == address)
+                    });
+
+                Responder {
+                    ttl: hop.ttl,
+                    address,
+                    rtt: replies().filter_map(|probe| probe.rtt()).min(),
+                    return_hops: replies()
+                        .find_map(|probe| probe.response()?.return_hops()),
+                }
+            })
+        }))
+    }
+
+    // The replies of a responder have gone at least the distance from the
+    // previous named responder, which the probes have passed on their way.
+    //
+    fn jumps(&self, responders: &[Responder]) -> Vec<String> {
+        let mut previous: Option<(&Responder, Hint)> = None;
+        let mut result = vec![];
+
+        for responder in responders {
+            let hint = match self.hint(responder.address) {
+                Some(hint) => hint,
+                None => continue,
+            };
+
+            if let (Some((previous, previous_hint)), Some(rtt)) =
+                (&previous, responder.rtt)
+            {
+                let distance = previous_hint.location.distance(&hint.location)
+                    .filter(|distance| !hints::is_reachable(*distance, rtt));
+
+                if let Some(distance) = distance {
+                    result.push(format!(
+                        "hop {} ({}, {}) is {:.0} km from hop {} ({}, {}), \
+                            too far for its RTT of {:.3} ms",
+                        responder.ttl,
+                        responder.address,
+                        hint.code,
+                        distance,
+                        previous.ttl,
+                        previous.address,
+                        previous_hint.code,
+                        rtt.as_secs_f64() * 1000.0,
+                    ));
+                }
+            }
+
+            previous = Some((responder, hint));
+        }
+
+        result
+    }
+}
+
+impl Boundaries {
+    // The origin ASes of the responder, followed with an asterisk if they are
+    // not those of the previous one.
+    //
+    pub fn label(&mut self, origin: Origin) -> String {
+        let boundary = origin.is_boundary(&self.previous);
+
+        self.previous = origin.asns.to_vec();
+
+        match boundary {
+            true => format!("{}*", origin),
+            false => origin.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustraceroute::asn::AsDatabase;
+
+    fn responder(ttl: u8, address: &str) -> Responder {
+        Responder {
+            ttl,
+            address: address.parse().unwrap(),
+            rtt: None,
+            return_hops: None,
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        let prefix = "192.0.2.0/24".parse().unwrap();
+        let mut boundaries = Boundaries::default();
+
+        let labels: Vec<String> = [&[64500][..], &[64500], &[64501, 64500], &[64502]]
+            .iter()
+            .map(|asns| boundaries.label(Origin { prefix, asns }))
+            .collect();
+
+        assert_eq!(labels, ["AS64500", "AS64500", "AS64501/AS64500", "AS64502*"]);
+    }
+
+    #[test]
+    fn range_tags() {
+        let mut ranges = RangeDatabase::default();
+        ranges.insert("192.0.2.0/25".parse().unwrap(), "IXP Example IX".to_string());
+        ranges.insert("8.8.8.0/24".parse().unwrap(), "GCP".to_string());
+
+        let annotations = Annotations::from(Names::disabled()).with_ranges(ranges);
+        let tags = |address: &str| annotations.tags(address.parse().unwrap())
+            .join(",");
+
+        assert_eq!(tags("192.0.2.1"), "documentation,IXP Example IX");
+        assert_eq!(tags("192.0.2.129"), "documentation");
+        assert_eq!(tags("8.8.8.8"), "GCP");
+    }
+
+    #[test]
+    fn rpki() {
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+        asns.insert("198.51.100.0/24".parse().unwrap(), 64501);
+        asns.insert("203.0.113.0/24".parse().unwrap(), 64502);
+
+        let mut vrps = VrpTable::default();
+        vrps.insert("192.0.2.0/24".parse().unwrap(), 64500, 24);
+        vrps.insert("198.51.100.0/22".parse().unwrap(), 64666, 24);
+
+        let annotations = Annotations::from(Names::disabled())
+            .with_asns(asns)
+            .with_vrps(vrps);
+
+        let tags = |address: &str| annotations.tags(address.parse().unwrap())
+            .join(",");
+
+        assert_eq!(tags("192.0.2.1"), "documentation");
+        assert_eq!(tags("198.51.100.1"), "documentation,rpki-invalid");
+        assert_eq!(tags("203.0.113.1"), "documentation,rpki-unknown");
+
+        assert_eq!(
+            annotations.warnings(1, [
+                responder(1, "192.0.2.1"),
+                responder(2, "198.51.100.1"),
+                responder(3, "203.0.113.1"),
+            ]),
+            ["hop 2 (198.51.100.1) is RPKI-invalid: 198.51.100.0/24 is not \
+                authorised for AS64501"],
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        let address = |text: &str| text.parse::<IpAddr>().unwrap();
+
+        let names = Names::fixed(&[
+            (address("192.0.2.1"), "ae1.cr1.fra2.example.net"),
+            (address("192.0.2.2"), "ae2.cr1.fra2.example.net"),
+            (address("192.0.2.3"), "ae1.cr2.nyc1.example.net"),
+            (address("192.0.2.4"), "ae2.cr2.nyc1.example.net"),
+        ]);
+
+        let annotations = Annotations::from(names).with_hints(Hints::new());
+        let responder = |ttl, address, ms| Responder {
+            rtt: Some(Duration::from_millis(ms)),
+            ..responder(ttl, address)
+        };
+
+        assert_eq!(
+            annotations.hinted_place(address("192.0.2.3")).unwrap(),
+            "~New York, US",
+        );
+
+        assert_eq!(
+            annotations.warnings(1, [
+                responder(1, "192.0.2.1", 1),
+                responder(2, "192.0.2.2", 2),
+                responder(3, "192.0.2.3", 20),
+                responder(4, "192.0.2.4", 21),
+            ]),
+            ["hop 3 (192.0.2.3, nyc) is 6203 km from hop 2 (192.0.2.2, fra), \
+                too far for its RTT of 20.000 ms"],
+        );
+    }
+
+    #[test]
+    fn private_space_warnings() {
+        assert_eq!(
+            Annotations::from(Names::disabled()).warnings(1, [
+                responder(1, "10.0.0.1"),
+                responder(2, "8.8.8.8"),
+                responder(3, "100.64.1.1"),
+            ]),
+            ["hop 3 (100.64.1.1) is in private address space past public hops"],
+        );
+    }
+
+    #[test]
+    fn asymmetry() {
+        let responder = |ttl, return_hops| Responder {
+            return_hops,
+            ..responder(ttl, "8.8.8.8")
+        };
+
+        assert_eq!(
+            Annotations::from(Names::disabled()).warnings(1, [
+                responder(1, Some(1)),
+                responder(2, Some(4)),
+                responder(3, Some(6)),
+                responder(4, None),
+                responder(9, Some(6)),
+            ]),
+            [
+                "hop 3 (8.8.8.8) has replied with a return path length of 6, \
+                    the paths there and back differ",
+                "hop 9 (8.8.8.8) has replied with a return path length of 6, \
+                    the paths there and back differ",
+            ],
+        );
+
+        assert_eq!(
+            Annotations::from(Names::disabled()).warnings(255, [
+                responder(255, Some(1)),
+            ]),
+            Vec::<String>::new(),
+        );
+
+        assert_eq!(
+            Annotations::from(Names::disabled()).warnings(5, [
+                responder(5, Some(1)),
+                responder(6, Some(1)),
+            ]),
+            ["hop 6 (8.8.8.8) has replied with a return path length of 1, the \
+                paths there and back differ"],
+        );
+    }
+}
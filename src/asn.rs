@@ -0,0 +1,459 @@
+use crate::trie::{Prefix, PrefixTrie};
+use std::{
+    convert::TryInto,
+    fmt,
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+const TABLE_DUMP_V2: u16 = 13;
+
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+const RIB_IPV4_UNICAST_ADDPATH: u16 = 8;
+const RIB_IPV6_UNICAST_ADDPATH: u16 = 10;
+
+const AS_PATH: u8 = 2;
+const AS_SET: u8 = 1;
+const EXTENDED_LENGTH: u8 = 0x10;
+
+// Origin ASes of the prefixes, loaded from a local file so that the lookups
+// work without access to any service. Prefixes announced by several ASes have
+// all of them.
+//
+#[derive(Default)]
+pub struct AsDatabase {
+    trie: PrefixTrie<Vec<u32>>,
+}
+
+// The most specific prefix containing an address and the ASes announcing it.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Origin<'a> {
+    pub prefix: Prefix,
+    pub asns: &'a [u32],
+}
+
+impl AsDatabase {
+    // Reads an MRT RIB dump (TABLE_DUMP_V2, RFC 6396), a CAIDA prefix-to-AS
+    // file or CSV lines of a prefix and its origin AS. The files have to be
+    // uncompressed.
+    //
+    pub fn read<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+
+        let mut result = Self::default();
+
+        // The type of the first MRT record follows its timestamp, text files
+        // never have a zero byte there.
+        //
+        if data.get(4..6) == Some(&TABLE_DUMP_V2.to_be_bytes()[..]) {
+            result.read_mrt(&data)?;
+        }
+        else {
+            let text = std::str::from_utf8(&data)
+                .map_err(|_| invalid("neither an MRT dump nor a text file"))?;
+
+            result.read_text(text);
+        }
+
+        if result.trie.is_empty() {
+            return Err(invalid("no prefixes found"))
+        }
+
+        Ok(result)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    pub fn insert(&mut self, prefix: Prefix, asn: u32) {
+        match self.trie.get_mut(prefix) {
+            Some(asns) if !asns.contains(&asn) => asns.push(asn),
+            Some(_) => {}
+            None => { self.trie.insert(prefix, vec![asn]); }
+        }
+    }
+
+    pub fn lookup(&self, address: IpAddr) -> Option<Origin<'_>> {
+        self.trie.lookup(address)
+            .map(|(prefix, asns)| Origin { prefix, asns })
+    }
+
+    // Lines of the CAIDA files have the prefix address, its length and the
+    // origins separated with whitespace, the origins of a prefix announced by
+    // several ASes are separated with underscores and the members of an AS set
+    // with commas. CSV lines have the prefix with its length and the origin
+    // first. Any other line, a header or a comment, is skipped.
+    //
+    fn read_text(&mut self, text: &str) {
+        for line in text.lines() {
+            let line = line.trim();
+
+            let csv = line.split_once(',').and_then(|(prefix, rest)| {
+                Some((prefix.parse().ok()?, rest.split(',').next()?))
+            });
+
+            let (prefix, origins) = match csv {
+                Some((prefix, origins)) => (Some(prefix), origins),
+                None => {
+                    let mut fields = line.split_whitespace();
+
+                    let prefix = match (fields.next(), fields.next()) {
+                        (Some(address), Some(length)) => {
+                            match (address.parse(), length.parse()) {
+                                (Ok(address), Ok(length)) => {
+                                    Prefix::new(address, length)
+                                }
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+
+                    (prefix, fields.next().unwrap_or(""))
+                }
+            };
+
+            let prefix = match prefix {
+                Some(prefix) => prefix,
+                None => continue,
+            };
+
+            for asn in origins.trim().split(['_', ',']).filter_map(parse_asn) {
+                self.insert(prefix, asn);
+            }
+        }
+    }
+
+    // Only the RIB records are read, the origin is the last AS of the path of
+    // every route to the prefix.
+    //
+    fn read_mrt(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let type_ = u16_at(data, offset + 4)?;
+            let subtype = u16_at(data, offset + 6)?;
+            let length = u32_at(data, offset + 8)? as usize;
+            let body = slice(data, offset + 12, length)?;
+
+            if type_ == TABLE_DUMP_V2 {
+                match subtype {
+                    RIB_IPV4_UNICAST | RIB_IPV6_UNICAST => {
+                        self.read_rib(body, subtype == RIB_IPV6_UNICAST, false)?;
+                    }
+                    RIB_IPV4_UNICAST_ADDPATH | RIB_IPV6_UNICAST_ADDPATH => {
+                        self.read_rib(
+                            body,
+                            subtype == RIB_IPV6_UNICAST_ADDPATH,
+                            true,
+                        )?;
+                    }
+                    _ => {}
+                }
+            }
+
+            offset += 12 + length;
+        }
+
+        Ok(())
+    }
+
+    fn read_rib(&mut self, body: &[u8], ipv6: bool, add_path: bool) -> io::Result<()> {
+        let length = *slice(body, 4, 1)?.first().unwrap();
+        let prefix_bytes = slice(body, 5, usize::from(length).div_ceil(8))?;
+
+        let address = match ipv6 {
+            true => {
+                let mut octets = [0; 16];
+                octets.get_mut(..prefix_bytes.len())
+                    .ok_or_else(|| invalid("bad prefix length"))?
+                    .copy_from_slice(prefix_bytes);
+
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            false => {
+                let mut octets = [0; 4];
+                octets.get_mut(..prefix_bytes.len())
+                    .ok_or_else(|| invalid("bad prefix length"))?
+                    .copy_from_slice(prefix_bytes);
+
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+        };
+
+        let prefix = Prefix::new(address, length)
+            .ok_or_else(|| invalid("bad prefix length"))?;
+
+        let mut offset = 5 + prefix_bytes.len();
+        let entries = u16_at(body, offset)?;
+        offset += 2;
+
+        for _ in 0..entries {
+            // Peer index and the time the route has been received, then the
+            // path identifier if there is one.
+            //
+            offset += if add_path { 10 } else { 6 };
+
+            let attributes_length = u16_at(body, offset)? as usize;
+            let attributes = slice(body, offset + 2, attributes_length)?;
+
+            for asn in origins(attributes)? {
+                self.insert(prefix, asn);
+            }
+
+            offset += 2 + attributes_length;
+        }
+
+        Ok(())
+    }
+}
+
+// The ASes are separated with slashes when there are several.
+//
+impl fmt::Display for Origin<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, asn) in self.asns.iter().enumerate() {
+            if index > 0 {
+                write!(formatter, "/")?;
+            }
+
+            write!(formatter, "AS{}", asn)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Origin<'_> {
+    // Whether the address belongs to other ASes than the previous one does.
+    //
+    pub fn is_boundary(&self, previous: &[u32]) -> bool {
+        !previous.is_empty() &&
+            !self.asns.iter().any(|asn| previous.contains(asn))
+    }
+}
+
+// Both "64500" and "AS64500" are accepted.
+//
+pub(crate) fn parse_asn(text: &str) -> Option<u32> {
+    let text = text.trim();
+
+    text.strip_prefix("AS").or_else(|| text.strip_prefix("as"))
+        .unwrap_or(text)
+        .parse().ok()
+}
+
+// Origins from the AS_PATH attribute. The paths of the RIB dumps always have
+// four-byte AS numbers. An AS set at the end of the path makes all of its
+// members the origins.
+//
+fn origins(attributes: &[u8]) -> io::Result<Vec<u32>> {
+    let mut offset = 0;
+
+    while offset < attributes.len() {
+        let flags = *slice(attributes, offset, 1)?.first().unwrap();
+        let type_ = *slice(attributes, offset + 1, 1)?.first().unwrap();
+
+        let (length, header) = match flags & EXTENDED_LENGTH {
+            0 => (usize::from(*slice(attributes, offset + 2, 1)?.first().unwrap()), 3),
+            _ => (usize::from(u16_at(attributes, offset + 2)?), 4),
+        };
+
+        let value = slice(attributes, offset + header, length)?;
+
+        if type_ == AS_PATH {
+            let mut result = vec![];
+            let mut position = 0;
+
+            while position < value.len() {
+                let segment_type = *slice(value, position, 1)?.first().unwrap();
+                let count = usize::from(*slice(value, position + 1, 1)?.first().unwrap());
+                let asns = slice(value, position + 2, count * 4)?;
+
+                result = asns.chunks(4)
+                    .map(|asn| u32::from_be_bytes(asn.try_into().unwrap()))
+                    .collect();
+
+                if segment_type != AS_SET {
+                    result = result.last().copied().into_iter().collect();
+                }
+
+                position += 2 + count * 4;
+            }
+
+            return Ok(result)
+        }
+
+        offset += header + length;
+    }
+
+    Ok(vec![])
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+    offset.checked_add(length)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid("unexpected end of data"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> io::Result<u16> {
+    Ok(u16::from_be_bytes(slice(data, offset, 2)?.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    Ok(u32::from_be_bytes(slice(data, offset, 4)?.try_into().unwrap()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("AS database: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(database: &AsDatabase, address: &str) -> Option<(String, Vec<u32>)> {
+        database.lookup(address.parse().unwrap())
+            .map(|origin| (origin.prefix.to_string(), origin.asns.to_vec()))
+    }
+
+    // RIB entry with an AS_PATH attribute of the segments given.
+    //
+    fn entry(segments: &[(u8, &[u32])], add_path: bool) -> Vec<u8> {
+        let mut path = vec![];
+
+        for (type_, asns) in segments {
+            path.extend([*type_, asns.len() as u8]);
+            path.extend(asns.iter().flat_map(|asn| asn.to_be_bytes()));
+        }
+
+        // ORIGIN first, then AS_PATH with the extended length.
+        //
+        let mut attributes = vec![0x40, 1, 1, 0, 0x50, AS_PATH];
+        attributes.extend((path.len() as u16).to_be_bytes());
+        attributes.extend(path);
+
+        let mut result = vec![0, 0, 0, 0, 0, 0];
+
+        if add_path {
+            result.extend([0, 0, 0, 1]);
+        }
+
+        result.extend((attributes.len() as u16).to_be_bytes());
+        result.extend(attributes);
+        result
+    }
+
+    fn record(subtype: u16, prefix: &[u8], length: u8, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 1, length];
+        body.extend(prefix);
+        body.extend((entries.len() as u16).to_be_bytes());
+        body.extend(entries.concat());
+
+        let mut result = vec![0; 4];
+        result.extend(TABLE_DUMP_V2.to_be_bytes());
+        result.extend(subtype.to_be_bytes());
+        result.extend((body.len() as u32).to_be_bytes());
+        result.extend(body);
+        result
+    }
+
+    #[test]
+    fn pfx2as() {
+        let database = AsDatabase::read(&b"\
+            192.0.2.0\t24\t64500\n\
+            192.0.2.128\t25\t64501_64502\n\
+            198.51.100.0\t24\t64503,64504\n\
+            2001:db8::\t32\t64505\n"[..]).unwrap();
+
+        assert_eq!(lookup(&database, "192.0.2.1"),
+            Some(("192.0.2.0/24".to_string(), vec![64500])));
+        assert_eq!(lookup(&database, "192.0.2.129"),
+            Some(("192.0.2.128/25".to_string(), vec![64501, 64502])));
+        assert_eq!(lookup(&database, "198.51.100.1").unwrap().1, vec![64503, 64504]);
+        assert_eq!(lookup(&database, "2001:db8::1").unwrap().1, vec![64505]);
+        assert_eq!(lookup(&database, "203.0.113.1"), None);
+    }
+
+    #[test]
+    fn csv() {
+        let database = AsDatabase::read(&b"\
+            prefix,asn\n\
+            # comment\n\
+            192.0.2.0/24,AS64500,Example\n\
+            2001:db8::/32,64505\n"[..]).unwrap();
+
+        assert_eq!(database.len(), 2);
+        assert_eq!(lookup(&database, "192.0.2.1").unwrap().1, vec![64500]);
+        assert_eq!(lookup(&database, "2001:db8::1").unwrap().1, vec![64505]);
+    }
+
+    #[test]
+    fn mrt() {
+        let mut data = vec![0, 0, 0, 0, 0, 13, 0, 1, 0, 0, 0, 2, 0, 0];
+
+        data.extend(record(RIB_IPV4_UNICAST, &[192, 0, 2], 24, &[
+            entry(&[(2, &[64496, 64500])], false),
+            entry(&[(2, &[64497, 64501])], false),
+        ]));
+        data.extend(record(RIB_IPV4_UNICAST_ADDPATH, &[198, 51, 100, 128], 25, &[
+            entry(&[(2, &[64496]), (AS_SET, &[64502, 64503])], true),
+        ]));
+        data.extend(record(RIB_IPV6_UNICAST, &[0x20, 0x01, 0x0d, 0xb8], 32, &[
+            entry(&[(2, &[64496, 64505])], false),
+        ]));
+
+        let database = AsDatabase::read(&data[..]).unwrap();
+
+        assert_eq!(lookup(&database, "192.0.2.1"),
+            Some(("192.0.2.0/24".to_string(), vec![64500, 64501])));
+        assert_eq!(lookup(&database, "198.51.100.200"),
+            Some(("198.51.100.128/25".to_string(), vec![64502, 64503])));
+        assert_eq!(lookup(&database, "2001:db8::1").unwrap().1, vec![64505]);
+    }
+
+    #[test]
+    fn truncated_mrt() {
+        let data = record(RIB_IPV4_UNICAST, &[192, 0, 2], 24, &[
+            entry(&[(2, &[64500])], false),
+        ]);
+
+        let error = AsDatabase::read(&data[..data.len() - 1]).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn empty() {
+        assert!(AsDatabase::read(&b"prefix,asn\n"[..]).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let prefix = "192.0.2.0/24".parse().unwrap();
+
+        assert_eq!(Origin { prefix, asns: &[64500] }.to_string(), "AS64500");
+        assert_eq!(
+            Origin { prefix, asns: &[64500, 64501] }.to_string(),
+            "AS64500/AS64501",
+        );
+    }
+
+    #[test]
+    fn boundary() {
+        let prefix = "192.0.2.0/24".parse().unwrap();
+        let origin = Origin { prefix, asns: &[64500, 64501] };
+
+        assert!(!origin.is_boundary(&[]));
+        assert!(!origin.is_boundary(&[64501]));
+        assert!(origin.is_boundary(&[64502]));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    error::Error,
+    event::{Event, Observer, Packet},
+    request::Request,
+    response::Response,
+    socket::Socket,
+    trace::{Hop, ProbeResult, ProbeStatus, Trace},
+    tracer::{Progress, Tracer},
+};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tokio::{
+    io::unix::{AsyncFd, AsyncFdReadyGuard},
+    sync::mpsc,
+    time,
+};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+
+// Response with the time it has been received and the raw packet.
+//
+type Reply = (Response, SystemTime, Vec<u8>);
+
+// Raw socket registered with the tokio reactor. It is cheap to clone and can
+// be shared by any number of concurrent traces: every trace gets its own
+// probe identifier, and whichever trace happens to read a reply from the
+// socket hands it over to the trace it belongs to.
+//
+#[derive(Clone)]
+pub struct AsyncSocket {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    fd: AsyncFd<Socket>,
+    // TTL and TOS are socket options, so they must not be changed by another
+    // trace between setting them and sending the probe.
+    //
+    send_lock: Mutex<()>,
+    traces: Mutex<HashMap<u16, mpsc::UnboundedSender<Reply>>>,
+}
+
+// Identifier reserved by a running trace. It is released when the trace is
+// over or its future is dropped.
+//
+struct Registration {
+    inner: Arc<Inner>,
+    ident: u16,
+    replies: mpsc::UnboundedReceiver<Reply>,
+}
+
+impl AsyncSocket {
+    pub fn new(socket: Socket) -> Result<Self, Error> {
+        socket.set_nonblocking()?;
+
+        // The socket owns its descriptor and closes it only when dropped.
+        //
+        let fd = unsafe { AsyncFd::register(socket) }
+            .map_err(|error| Error::from_socket_error(&error.into_parts().1))?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                fd,
+                send_lock: Mutex::new(()),
+                traces: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    // The identifier of the tracer is used if it is not taken by another
+    // trace, otherwise the next free one is.
+    //
+    pub async fn trace(&self, tracer: &Tracer) -> Result<Trace, Error> {
+        self.trace_with(tracer, &mut |_: &Event| {}).await
+    }
+
+    pub async fn trace_with<O: Observer + ?Sized>(
+        &self,
+        tracer: &Tracer,
+        observer: &mut O,
+    ) -> Result<Trace, Error> {
+        let mut registration = self.register(tracer.ident)?;
+        let tracer = tracer.clone().ident(registration.ident);
+        let mut progress = Progress::new(&tracer, SystemTime::now());
+
+        while let Some((ttl, sequence)) = progress.next_probe(observer) {
+            let probe = self.probe(
+                &tracer,
+                &mut registration,
+                observer,
+                ttl,
+                &tracer.request(sequence),
+            ).await?;
+
+            observer.on_event(&Event::probe_completed(ttl, &probe));
+
+            progress.add(probe);
+        }
+
+        Ok(progress.finish(observer))
+    }
+
+    // Events are yielded as they happen. The trace runs in a separate task, so
+    // this must be called from within a tokio runtime.
+    //
+    pub fn events(
+        &self,
+        tracer: Tracer,
+    ) -> impl Stream<Item = Result<Event, Error>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let socket = self.clone();
+
+        tokio::spawn(async move {
+            let result = socket.trace_with(&tracer, &mut |event: &Event| {
+                let _ = sender.send(Ok(event.clone()));
+            }).await;
+
+            if let Err(error) = result {
+                let _ = sender.send(Err(error));
+            }
+        });
+
+        UnboundedReceiverStream::new(receiver)
+    }
+
+    // Hops are yielded as soon as all of their probes are done.
+    //
+    pub fn hops(
+        &self,
+        tracer: Tracer,
+    ) -> impl Stream<Item = Result<Hop, Error>> {
+        self.events(tracer).filter_map(|event| match event {
+            Ok(Event::HopCompleted(hop)) => Some(Ok(hop)),
+            Ok(_) => None,
+            Err(error) => Some(Err(error)),
+        })
+    }
+
+    // Every identifier is tried once at most, there is none left if they are
+    // all taken by running traces.
+    //
+    fn register(&self, ident: u16) -> Result<Registration, Error> {
+        let (sender, replies) = mpsc::unbounded_channel();
+        let mut traces = self.inner.traces.lock().unwrap();
+
+        let ident = (0..=u16::MAX)
+            .map(|offset| ident.wrapping_add(offset))
+            .find(|ident| !traces.contains_key(ident))
+            .ok_or(Error::Os(libc::EAGAIN))?;
+
+        traces.insert(ident, sender);
+
+        Ok(Registration { inner: self.inner.clone(), ident, replies })
+    }
+
+    async fn probe<O: Observer + ?Sized>(
+        &self,
+        tracer: &Tracer,
+        registration: &mut Registration,
+        observer: &mut O,
+        current_ttl: u8,
+        request: &Request,
+    ) -> Result<ProbeResult, Error> {
+        let packet = request.to_vec();
+        let result = self.send_request(tracer, current_ttl, &packet);
+        let sent = SystemTime::now();
+
+        let status = match result {
+            Err(error) if error.is_transient() => ProbeStatus::Error(error),
+            Err(error) => return Err(error),
+            Ok(()) => {
+                tracer.report_sent(observer, current_ttl, request, &packet, sent);
+
+                match self.recv_response1(
+                    registration,
+                    observer,
+                    current_ttl,
+                    request,
+                    time::Instant::now() + tracer.wait,
+                ).await? {
+                    None => ProbeStatus::Timeout,
+                    Some((response, received)) => ProbeStatus::Reply {
+                        response,
+                        rtt: received.duration_since(sent).unwrap_or_default(),
+                    },
+                }
+            }
+        };
+
+        Ok(ProbeResult { sequence: request.sequence, sent, status })
+    }
+
+    fn send_request(
+        &self,
+        tracer: &Tracer,
+        current_ttl: u8,
+        packet: &[u8],
+    ) -> Result<(), Error> {
+        let _lock = self.inner.send_lock.lock().unwrap();
+        let socket = self.inner.fd.get_ref();
+
+        socket.set_tos(tracer.tos)?;
+        socket.set_ttl(current_ttl)?;
+        socket.send_to(packet, tracer.target)
+    }
+
+    // Only the packets of this trace are reported to the observer, the rest
+    // go to the traces they belong to.
+    //
+    async fn recv_response1<O: Observer + ?Sized>(
+        &self,
+        registration: &mut Registration,
+        observer: &mut O,
+        current_ttl: u8,
+        request: &Request,
+        time_limit: time::Instant,
+    ) -> Result<Option<(Response, SystemTime)>, Error> {
+        loop {
+            tokio::select! {
+                _ = time::sleep_until(time_limit) => return Ok(None),
+                reply = registration.replies.recv() => {
+                    if let Some((response, received, data)) = reply {
+                        let matched = response.does_match_request(request);
+
+                        observer.on_packet(&Packet::Received {
+                            time: received,
+                            data: &data,
+                            probe: if matched {
+                                Some((current_ttl, request.sequence))
+                            }
+                            else {
+                                None
+                            },
+                        });
+
+                        if matched {
+                            return Ok(Some((response, received)))
+                        }
+                    }
+                }
+                guard = self.inner.fd.readable() => {
+                    self.dispatch(guard.map_err(|error| Error::from_socket_error(&error))?)?;
+                }
+            }
+        }
+    }
+
+    // Reads everything available on the socket and passes every reply to the
+    // trace which has sent the corresponding probe.
+    //
+    fn dispatch(
+        &self,
+        mut guard: AsyncFdReadyGuard<'_, Socket>,
+    ) -> Result<(), Error> {
+        let mut response_body_data: [u8; 1024] = [0; 1024];
+
+        loop {
+            // Only running out of packets clears the readiness, a signal
+            // only interrupts the reading.
+            //
+            let received = guard.try_io(|fd| loop {
+                match fd.get_ref().recv(&mut response_body_data) {
+                    Err(Error::Os(libc::EINTR)) => continue,
+                    Err(Error::Os(libc::EAGAIN)) => {
+                        return Err(io::ErrorKind::WouldBlock.into())
+                    }
+                    result => return Ok(result),
+                }
+            });
+
+            let (response_body_size, response_sockaddr_inx) = match received {
+                Err(_would_block) => return Ok(()),
+                Ok(result) => {
+                    match result.map_err(|error| Error::from_socket_error(&error))?? {
+                        (size, Some(sockaddr_inx)) => (size, sockaddr_inx),
+                        // Not from an address the replies can come from.
+                        //
+                        (_, None) => continue,
+                    }
+                }
+            };
+
+            let data = &response_body_data[0..response_body_size];
+            let response = Response::parse(&response_sockaddr_inx, data);
+
+            if let Some(response) = response {
+                let traces = self.inner.traces.lock().unwrap();
+
+                if let Some(sender) = traces.get(&response.ident) {
+                    let _ = sender.send((
+                        response,
+                        SystemTime::now(),
+                        data.to_vec(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.inner.traces.lock().unwrap().remove(&self.ident);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        net::{Ipv4Addr, SocketAddr, UdpSocket},
+        os::unix::io::{FromRawFd, IntoRawFd},
+        time::Duration,
+    };
+
+    // A UDP socket on the loopback stands in for the raw one, the datagrams
+    // sent to it from the other one are received as the IP packets would be.
+    //
+    fn sockets() -> (AsyncSocket, UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = socket.local_addr().unwrap();
+        let socket = unsafe { Socket::from_raw_fd(socket.into_raw_fd()) };
+
+        (
+            AsyncSocket::new(socket).unwrap(),
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap(),
+            address,
+        )
+    }
+
+    // IP header and echo reply to the probe.
+    //
+    fn echo_reply(ident: u16, sequence: u16) -> Vec<u8> {
+        let mut packet = vec![0; 28];
+
+        packet[0] = 0x45;
+        packet[8] = 64;
+        packet[24..26].copy_from_slice(&ident.to_be_bytes());
+        packet[26..28].copy_from_slice(&sequence.to_be_bytes());
+
+        packet
+    }
+
+    fn sequences(registration: &mut Registration) -> Vec<u16> {
+        let mut result = vec![];
+
+        while let Ok((response, _, _)) = registration.replies.try_recv() {
+            result.push(response.sequence);
+        }
+
+        result
+    }
+
+    #[tokio::test]
+    async fn identifiers() {
+        let (socket, _, _) = sockets();
+
+        let first = socket.register(7).unwrap();
+        let second = socket.register(7).unwrap();
+
+        assert_eq!((first.ident, second.ident), (7, 8));
+
+        drop(first);
+
+        assert_eq!(socket.register(7).unwrap().ident, 7);
+        assert!(!socket.inner.traces.lock().unwrap().contains_key(&7));
+        assert!(socket.inner.traces.lock().unwrap().contains_key(&8));
+
+        let last = socket.register(u16::MAX).unwrap();
+
+        assert_eq!(socket.register(u16::MAX).unwrap().ident, 0);
+
+        drop((second, last));
+
+        assert!(socket.inner.traces.lock().unwrap().is_empty());
+
+        let all: Vec<Registration> = (0..=u16::MAX)
+            .map(|ident| socket.register(ident).unwrap())
+            .collect();
+
+        assert!(matches!(socket.register(7), Err(Error::Os(libc::EAGAIN))));
+
+        drop(all);
+
+        assert_eq!(socket.register(7).unwrap().ident, 7);
+    }
+
+    #[tokio::test]
+    async fn demultiplexing() {
+        let (socket, peer, address) = sockets();
+
+        let mut first = socket.register(1).unwrap();
+        let mut second = socket.register(2).unwrap();
+
+        for (ident, sequence) in [(2, 10), (1, 11), (3, 12), (1, 13)] {
+            peer.send_to(&echo_reply(ident, sequence), address).unwrap();
+        }
+
+        // Everything there is, is read at once.
+        //
+        let guard = time::timeout(Duration::from_secs(5), socket.inner.fd.readable())
+            .await
+            .unwrap()
+            .unwrap();
+
+        socket.dispatch(guard).unwrap();
+
+        assert_eq!(sequences(&mut first), vec![11, 13]);
+        assert_eq!(sequences(&mut second), vec![10]);
+
+        // The readiness is cleared only when nothing is left.
+        //
+        peer.send_to(&echo_reply(2, 14), address).unwrap();
+
+        let guard = time::timeout(Duration::from_secs(5), socket.inner.fd.readable())
+            .await
+            .unwrap()
+            .unwrap();
+
+        socket.dispatch(guard).unwrap();
+
+        assert_eq!(sequences(&mut second), vec![14]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{json::milliseconds, options::Options};
+use rustraceroute::{
+    extension::Extension,
+    response::UNREACHABLE,
+    Hop,
+    ProbeResult,
+    ProbeStatus,
+    Response,
+    Trace,
+};
+use serde_json::{json, Value};
+use std::{
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Parsers of the RIPE Atlas results pick the rules by the firmware version of
+// the probe. This is the one which produces the structure written here.
+//
+const FIRMWARE: u32 = 5080;
+
+// IP header and ICMP header of the echo request without payload.
+//
+const PROBE_SIZE: usize = 20 + 8;
+
+pub fn trace(
+    options: &Options,
+    address: IpAddr,
+    source: Option<IpAddr>,
+    trace: &Trace,
+    finished: SystemTime,
+) -> Value {
+    let source = source.map(|source| source.to_string());
+
+    json!({
+        "fw": FIRMWARE,
+        "af": if address.is_ipv4() { 4 } else { 6 },
+        "dst_addr": address.to_string(),
+        "dst_name": options.host(),
+        "src_addr": source,
+        "from": source,
+        "proto": "ICMP",
+        "paris_id": 0,
+        "size": PROBE_SIZE,
+        "timestamp": unix_seconds(trace.started),
+        "endtime": unix_seconds(finished),
+        "type": "traceroute",
+        "result": trace.hops.iter().map(hop).collect::<Value>(),
+    })
+}
+
+// A hop where no probe could be sent has the error instead of the results.
+//
+fn hop(hop: &Hop) -> Value {
+    let error = hop.probes.iter().map(ProbeResult::error)
+        .collect::<Option<Vec<_>>>()
+        .and_then(|errors| errors.first().copied());
+
+    match error {
+        Some(error) => json!({ "hop": hop.ttl, "error": error.to_string() }),
+        None => json!({
+            "hop": hop.ttl,
+            "result": hop.probes.iter().map(probe).collect::<Value>(),
+        }),
+    }
+}
+
+fn probe(probe: &ProbeResult) -> Value {
+    match &probe.status {
+        ProbeStatus::Reply { response, rtt } => reply(response, milliseconds(*rtt)),
+        ProbeStatus::Timeout | ProbeStatus::Error(_) => json!({ "x": "*" }),
+    }
+}
+
+fn reply(response: &Response, rtt: f64) -> Value {
+    let mut result = json!({
+        "from": response.source.to_string(),
+        "rtt": rtt,
+        "size": response.size,
+        "ttl": response.reply_ttl,
+    });
+
+    if let Some(error) = error(response) {
+        result["err"] = error;
+    }
+
+    if let Some(quoted_ttl) = response.quoted_ttl.filter(|ttl| *ttl != 1) {
+        result["ittl"] = quoted_ttl.into();
+    }
+
+    if !response.extensions.is_empty() {
+        result["icmpext"] = json!({
+            "version": 2,
+            "obj": response.extensions.iter().map(object).collect::<Value>(),
+        });
+    }
+
+    result
+}
+
+fn error(response: &Response) -> Option<Value> {
+    if response.type_ != UNREACHABLE {
+        return None
+    }
+
+    Some(match response.code {
+        0  => "N".into(),
+        1  => "H".into(),
+        2  => "P".into(),
+        3  => "p".into(),
+        13 => "A".into(),
+        code => code.into(),
+    })
+}
+
+fn object(extension: &Extension) -> Value {
+    let mut result = json!({
+        "class": extension.class,
+        "type": extension.type_,
+    });
+
+    if let Some(labels) = extension.mpls_labels() {
+        result["mpls"] = labels.iter().map(|label| json!({
+            "exp": label.exp,
+            "label": label.label,
+            "s": if label.bottom { 1 } else { 0 },
+            "ttl": label.ttl,
+        })).collect();
+    }
+
+    result
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{ROUTER, SOURCE, TARGET};
+    use clap::Clap;
+    use rustraceroute::{
+        extension::{MPLS_CLASS, MPLS_INCOMING_STACK},
+        response::TIME_EXCEEDED,
+        Error,
+    };
+    use std::time::Duration;
+
+    fn options() -> Options {
+        Options::parse_from(["rustraceroute", "example.com"])
+    }
+
+    fn result(status: ProbeStatus) -> ProbeResult {
+        crate::fixtures::probe(0, status)
+    }
+
+    fn response(type_: u8, code: u8) -> Response {
+        Response {
+            source: ROUTER,
+            reply_ttl: 250,
+            type_,
+            code,
+            size: 36,
+            quoted_ttl: Some(1),
+            ..Default::default()
+        }
+    }
+
+    fn reply(response: Response) -> ProbeResult {
+        result(ProbeStatus::Reply { response, rtt: Duration::from_micros(2500) })
+    }
+
+    #[test]
+    fn document() {
+        let value = trace(&options(), TARGET, Some(SOURCE), &Trace {
+            target: TARGET,
+            started: UNIX_EPOCH + Duration::from_secs(100),
+            hops: vec![Hop {
+                ttl: 1,
+                probes: vec![
+                    reply(response(TIME_EXCEEDED, 0)),
+                    result(ProbeStatus::Timeout),
+                ],
+            }],
+        }, UNIX_EPOCH + Duration::from_secs(103));
+
+        assert_eq!(value, json!({
+            "fw": FIRMWARE,
+            "af": 4,
+            "dst_addr": "198.51.100.1",
+            "dst_name": "example.com",
+            "src_addr": "192.0.2.100",
+            "from": "192.0.2.100",
+            "proto": "ICMP",
+            "paris_id": 0,
+            "size": 28,
+            "timestamp": 100,
+            "endtime": 103,
+            "type": "traceroute",
+            "result": [{
+                "hop": 1,
+                "result": [
+                    { "from": "192.0.2.1", "rtt": 2.5, "size": 36, "ttl": 250 },
+                    { "x": "*" },
+                ],
+            }],
+        }));
+    }
+
+    #[test]
+    fn hop_error() {
+        let value = hop(&Hop {
+            ttl: 3,
+            probes: vec![result(ProbeStatus::Error(Error::NetworkUnreachable))],
+        });
+
+        assert_eq!(value, json!({
+            "hop": 3,
+            "error": "network is unreachable",
+        }));
+    }
+
+    #[test]
+    fn reply_unreachable() {
+        assert_eq!(probe(&reply(response(UNREACHABLE, 1)))["err"], "H");
+        assert_eq!(probe(&reply(response(UNREACHABLE, 13)))["err"], "A");
+        assert_eq!(probe(&reply(response(UNREACHABLE, 9)))["err"], 9);
+        assert_eq!(probe(&reply(response(TIME_EXCEEDED, 0)))["err"], Value::Null);
+    }
+
+    #[test]
+    fn reply_quoted_ttl() {
+        let mut response = response(TIME_EXCEEDED, 0);
+        response.quoted_ttl = Some(3);
+
+        assert_eq!(probe(&reply(response))["ittl"], 3);
+    }
+
+    #[test]
+    fn reply_mpls() {
+        let mut response = response(TIME_EXCEEDED, 0);
+        response.extensions = vec![Extension {
+            class: MPLS_CLASS,
+            type_: MPLS_INCOMING_STACK,
+            payload: vec![0x01, 0x23, 0x4B, 0xFE],
+        }];
+
+        assert_eq!(probe(&reply(response))["icmpext"], json!({
+            "version": 2,
+            "obj": [{
+                "class": 1,
+                "type": 1,
+                "mpls": [{ "exp": 5, "label": 4660, "s": 1, "ttl": 254 }],
+            }],
+        }));
+    }
+}
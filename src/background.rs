@@ -0,0 +1,75 @@
+use rustraceroute::{Event, Observer};
+use std::{sync::mpsc, thread};
+
+// Runs the trace with the events handed over to the observer on a thread of
+// its own, so that the probing never waits for the observer, e.g. for the
+// names of the hops it prints. The packets are not handed over, the outputs
+// rendered this way do not look at them. Returns once the observer has seen
+// all the events.
+//
+pub fn observe<O, F, T>(observer: &mut O, trace: F) -> T
+where
+    O: Observer + Send + ?Sized,
+    F: FnOnce(&mut dyn Observer) -> T,
+{
+    let (sender, receiver) = mpsc::channel::<Event>();
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for event in receiver {
+                observer.on_event(&event);
+            }
+        });
+
+        // The observer stops once the sender is dropped at the end of the
+        // trace.
+        //
+        let mut forward = move |event: &Event| {
+            let _ = sender.send(event.clone());
+        };
+
+        trace(&mut forward)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{probe_sent, reply_received, ROUTER},
+        names::Names,
+        text::Text,
+    };
+    use rustraceroute::{response::TIME_EXCEEDED, Hop};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn slow_names() {
+        let timeout = Duration::from_millis(500);
+        let mut output = vec![];
+        let mut text = Text::new(&mut output, Names::unresolved(timeout).into());
+
+        let started = Instant::now();
+
+        let delay = observe(&mut text, |observer| {
+            observer.on_event(&probe_sent(1));
+            observer.on_event(&reply_received(1, ROUTER, TIME_EXCEEDED, 1000));
+
+            let completed = Instant::now();
+
+            observer.on_event(&Event::HopCompleted(Hop::new(1)));
+            observer.on_event(&probe_sent(2));
+
+            completed.elapsed()
+        });
+
+        // The name has been waited for, but not by the probing.
+        //
+        assert!(delay < timeout / 10, "{:?}", delay);
+        assert!(started.elapsed() >= timeout);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            " 1  192.0.2.1 (192.0.2.1) [documentation]  1.000 ms\n",
+        );
+    }
+}
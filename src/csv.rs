@@ -0,0 +1,272 @@
+use crate::{annotations::Annotations, json::milliseconds, timestamp};
+use rustraceroute::{geoip::Location, Event, Hop, Observer, ProbeResult, Response};
+use std::{
+    io::{self, Write},
+    net::IpAddr,
+};
+
+const COLUMNS: [&str; 18] = [
+    "timestamp",
+    "target",
+    "ttl",
+    "probe",
+    "responder",
+    "rtt_ms",
+    "icmp_type",
+    "icmp_code",
+    "reply_ttl",
+    "initial_ttl",
+    "return_hops",
+    "hostname",
+    "asn",
+    "country",
+    "city",
+    "latitude",
+    "longitude",
+    "tags",
+];
+
+// Prints one row per probe, separated with commas (quoted as in RFC 4180) or
+// with tabs. Rows are printed as soon as their hop is done.
+//
+pub struct Csv<W: Write> {
+    output: W,
+    separator: char,
+    target: String,
+    annotations: Annotations,
+}
+
+impl<W: Write> Csv<W> {
+    pub fn new(
+        output: W,
+        separator: char,
+        target: &str,
+        annotations: Annotations,
+    ) -> Self {
+        Self { output, separator, target: target.to_string(), annotations }
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        self.write_row(&COLUMNS.iter().map(|column| column.to_string())
+            .collect::<Vec<String>>())
+    }
+
+    fn write_hop(&mut self, hop: &Hop) -> io::Result<()> {
+        for (index, probe) in hop.probes.iter().enumerate() {
+            let row = self.row(hop.ttl, index, probe);
+
+            self.write_row(&row)?;
+        }
+
+        self.output.flush()
+    }
+
+    fn row(&self, ttl: u8, index: usize, probe: &ProbeResult) -> Vec<String> {
+        let response = probe.response();
+
+        let mut row = vec![
+            timestamp::rfc3339(probe.sent),
+            self.target.clone(),
+            ttl.to_string(),
+            index.to_string(),
+            response.map(|response| response.source.to_string())
+                .unwrap_or_default(),
+            probe.rtt().map(|rtt| milliseconds(rtt).to_string())
+                .unwrap_or_default(),
+            response.map(|response| response.type_.to_string())
+                .unwrap_or_default(),
+            response.map(|response| response.code.to_string())
+                .unwrap_or_default(),
+            response.map(|response| response.reply_ttl.to_string())
+                .unwrap_or_default(),
+            response.and_then(Response::initial_ttl)
+                .map(|ttl| ttl.to_string())
+                .unwrap_or_default(),
+            response.and_then(Response::return_hops)
+                .map(|hops| hops.to_string())
+                .unwrap_or_default(),
+            response.and_then(|response| {
+                self.annotations.names.wait(response.source)
+            })
+                .map(|name| name.to_string())
+                .unwrap_or_default(),
+            response.map(|response| asn(&self.annotations, response.source))
+                .unwrap_or_default(),
+        ];
+
+        let location = response
+            .and_then(|response| self.annotations.location(response.source))
+            .unwrap_or_default();
+
+        row.extend(location_fields(&location));
+
+        row.push(
+            response.map(|response| tags(&self.annotations, response.source))
+                .unwrap_or_default(),
+        );
+
+        row
+    }
+
+    pub fn write_row(&mut self, row: &[String]) -> io::Result<()> {
+        let separator = self.separator.to_string();
+
+        let fields: Vec<String> = row.iter()
+            .map(|field| self.escape(field))
+            .collect();
+
+        writeln!(self.output, "{}", fields.join(&separator))
+    }
+
+    fn escape(&self, field: &str) -> String {
+        if self.separator == '\t' {
+            return field.replace(['\t', '\n', '\r'], " ")
+        }
+
+        if field.contains([self.separator, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        }
+        else {
+            field.to_string()
+        }
+    }
+}
+
+impl<W: Write> Observer for Csv<W> {
+    fn on_event(&mut self, event: &Event) {
+        self.annotations.names.prefetch(event);
+
+        if let Event::HopCompleted(hop) = event {
+            // There is nobody to report to if the output is gone.
+            //
+            let _ = self.write_hop(hop);
+        }
+    }
+}
+
+// The origin ASes of the responder separated with underscores, as in the
+// CAIDA prefix-to-AS files.
+//
+pub fn asn(annotations: &Annotations, address: IpAddr) -> String {
+    match annotations.origin(address) {
+        Some(origin) => origin.asns.iter().map(|asn| asn.to_string())
+            .collect::<Vec<_>>().join("_"),
+        None => String::new(),
+    }
+}
+
+// Separated with semicolons, the tags of the clouds have spaces in them.
+//
+pub fn tags(annotations: &Annotations, address: IpAddr) -> String {
+    annotations.tags(address).join(";")
+}
+
+// The country code, the city and the coordinates, empty if not known.
+//
+pub fn location_fields(location: &Location) -> Vec<String> {
+    vec![
+        location.country_code.clone().unwrap_or_default(),
+        location.city.clone().unwrap_or_default(),
+        location.latitude.map(|latitude| latitude.to_string())
+            .unwrap_or_default(),
+        location.longitude.map(|longitude| longitude.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{probe, ROUTER},
+        names::Names,
+    };
+    use rustraceroute::{
+        asn::AsDatabase,
+        response::TIME_EXCEEDED,
+        ProbeStatus,
+        Response,
+    };
+    use std::time::Duration;
+
+    fn hop() -> Hop {
+        Hop {
+            ttl: 5,
+            probes: vec![
+                probe(12, ProbeStatus::Reply {
+                    response: Response {
+                        source: ROUTER,
+                        reply_ttl: 250,
+                        type_: TIME_EXCEEDED,
+                        ..Default::default()
+                    },
+                    rtt: Duration::from_micros(1500),
+                }),
+                probe(13, ProbeStatus::Timeout),
+            ],
+        }
+    }
+
+    fn render(separator: char, target: &str) -> String {
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64501);
+
+        let mut csv = Csv::new(
+            vec![],
+            separator,
+            target,
+            Annotations::from(Names::fixed(&[(ROUTER, "router.example.net")]))
+                .with_asns(asns),
+        );
+
+        csv.start().unwrap();
+        csv.on_event(&Event::HopCompleted(hop()));
+
+        String::from_utf8(csv.output).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            render(',', "example.com"),
+            "timestamp,target,ttl,probe,responder,rtt_ms,icmp_type,icmp_code,\
+                reply_ttl,initial_ttl,return_hops,hostname,asn,country,city,\
+                latitude,longitude,tags\n\
+            1970-01-01T00:00:00.000000Z,example.com,5,0,192.0.2.1,1.5,11,0,\
+                250,255,6,router.example.net,64500_64501,,,,,documentation\n\
+            1970-01-01T00:00:00.000000Z,example.com,5,1,,,,,,,,,,,,,,\n",
+        );
+    }
+
+    #[test]
+    fn tsv() {
+        let output = render('\t', "example.com");
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("timestamp\ttarget\tttl\t"));
+        assert_eq!(lines[2].split('\t').count(), COLUMNS.len());
+
+        let output = render('\t', "odd\tname\r\n");
+
+        assert!(output.contains("\todd name  \t5\t0\t"));
+    }
+
+    #[test]
+    fn location() {
+        assert_eq!(location_fields(&Location {
+            country_code: Some("US".to_string()),
+            latitude: Some(41.85),
+            longitude: Some(-87.65),
+            ..Default::default()
+        }), ["US", "", "41.85", "-87.65"]);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let output = render(',', "odd,\"name\"");
+
+        assert!(output.contains(",\"odd,\"\"name\"\"\",5,0,"));
+    }
+}
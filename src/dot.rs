@@ -0,0 +1,549 @@
+use crate::annotations::Annotations;
+use rustraceroute::{Hop, Response, Trace};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    net::IpAddr,
+    time::Duration,
+};
+
+const SOURCE: &str = "source";
+
+// Directed graph of the responders, in the Graphviz DOT language. Any number
+// of traces can be added, the responders they have in common are the same
+// nodes and the hops they have in common are the same edges. The responders
+// are labelled with their names too, unless the lookups are disabled, with the
+// kinds of special address space they are in, and with their origin ASes and
+// locations if they are known. The edges between responders of
+// different ASes are drawn in red. The interfaces found to be of one router
+// are one node, labelled with all of them.
+//
+pub struct Graph {
+    annotations: Annotations,
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    // Interfaces of the routers with more than one, each with all the
+    // interfaces of its router, the first of which stands for the router.
+    //
+    routers: HashMap<IpAddr, Vec<IpAddr>>,
+    node_index: HashMap<String, usize>,
+    edge_index: HashMap<(String, String), usize>,
+}
+
+struct Node {
+    id: String,
+    kind: Kind,
+}
+
+enum Kind {
+    Source,
+    Responder { address: IpAddr, reached: bool },
+    // Nothing has replied at this TTL. Every such hop is a node of its own,
+    // unresponsive routers of different traces are not necessarily the same.
+    //
+    Unresponsive,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    ttls: BTreeSet<u8>,
+    rtts: Vec<Duration>,
+    dashed: bool,
+}
+
+impl Graph {
+    pub fn new(annotations: Annotations) -> Self {
+        Self {
+            annotations,
+            nodes: vec![],
+            edges: vec![],
+            routers: HashMap::new(),
+            node_index: HashMap::new(),
+            edge_index: HashMap::new(),
+        }
+    }
+
+    pub fn with_routers(mut self, routers: &[Vec<IpAddr>]) -> Self {
+        for router in routers {
+            for address in router {
+                self.routers.insert(*address, router.clone());
+            }
+        }
+
+        self
+    }
+
+    // Every responder of a hop is linked to every responder of the previous
+    // one, since which probe went through which router is not known, but a
+    // router answering at two hops in a row is not linked to itself.
+    //
+    pub fn add(&mut self, trace: &Trace) {
+        let mut previous = vec![self.node(SOURCE.to_string(), Kind::Source)];
+
+        for hop in &trace.hops {
+            let responders = hop.responders();
+
+            let current = if responders.is_empty() {
+                let id = format!("* {} {}", trace.target, hop.ttl);
+
+                vec![self.node(id, Kind::Unresponsive)]
+            }
+            else {
+                let mut current = vec![];
+
+                for address in responders {
+                    self.annotations.names.lookup(address);
+
+                    let router = self.router(address);
+
+                    let reached = self.replies(hop, router)
+                        .any(|(response, _)| response.is_echo_reply());
+
+                    let id = self.node(
+                        router.to_string(),
+                        Kind::Responder { address: router, reached },
+                    );
+
+                    if !current.contains(&id) {
+                        current.push(id);
+                    }
+                }
+
+                current
+            };
+
+            for from in &previous {
+                for to in current.iter().filter(|to| *to != from) {
+                    self.edge(from, to, hop);
+                }
+            }
+
+            previous = current;
+        }
+    }
+
+    fn node(&mut self, id: String, kind: Kind) -> String {
+        match self.node_index.get(&id) {
+            Some(index) => {
+                // The destination of one trace may be just a router on the
+                // way for another, but it is still somebody's destination.
+                //
+                if let (
+                    Kind::Responder { reached, .. },
+                    Kind::Responder { reached: true, .. },
+                ) = (&mut self.nodes[*index].kind, kind) {
+                    *reached = true;
+                }
+            }
+            None => {
+                self.node_index.insert(id.clone(), self.nodes.len());
+                self.nodes.push(Node { id: id.clone(), kind });
+            }
+        }
+
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, hop: &Hop) {
+        let key = (from.to_string(), to.to_string());
+
+        let index = match self.edge_index.get(&key) {
+            Some(index) => *index,
+            None => {
+                self.edge_index.insert(key, self.edges.len());
+                self.edges.push(Edge {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    ttls: BTreeSet::new(),
+                    rtts: vec![],
+                    dashed: false,
+                });
+
+                self.edges.len() - 1
+            }
+        };
+
+        let dashed = matches!(
+            self.nodes[self.node_index[from]].kind,
+            Kind::Unresponsive,
+        ) || matches!(self.nodes[self.node_index[to]].kind, Kind::Unresponsive);
+
+        let rtts: Vec<Duration> = match to.parse() {
+            Ok(router) => self.replies(hop, router).map(|(_, rtt)| rtt).collect(),
+            Err(_) => vec![],
+        };
+
+        let edge = &mut self.edges[index];
+
+        edge.ttls.insert(hop.ttl);
+        edge.dashed = dashed;
+        edge.rtts.extend(rtts);
+    }
+
+    // The address which stands for the router of the interface.
+    //
+    fn router(&self, address: IpAddr) -> IpAddr {
+        self.routers.get(&address).map_or(address, |router| router[0])
+    }
+
+    // Replies of the hop from any of the interfaces of the router.
+    //
+    fn replies<'a>(
+        &'a self,
+        hop: &'a Hop,
+        router: IpAddr,
+    ) -> impl Iterator<Item = (&'a Response, Duration)> + 'a {
+        hop.probes.iter()
+            .filter_map(|probe| Some((probe.response()?, probe.rtt()?)))
+            .filter(move |(response, _)| self.router(response.source) == router)
+    }
+
+    fn label(&self, address: IpAddr) -> String {
+        let mut result = address.to_string();
+
+        if let Some(name) = self.annotations.names.wait(address) {
+            result.push_str(&format!("\n{}", name));
+        }
+
+        let tags = self.annotations.tags(address);
+
+        if !tags.is_empty() {
+            result.push_str(&format!("\n{}", tags.join(" ")));
+        }
+
+        if let Some(origin) = self.annotations.origin(address) {
+            result.push_str(&format!("\n{} {}", origin, origin.prefix));
+        }
+
+        if let Some(place) = self.annotations.place(address) {
+            result.push_str(&format!("\n{}", place));
+        }
+
+        if let Some(place) = self.annotations.hinted_place(address) {
+            result.push_str(&format!("\n{}", place));
+        }
+
+        result
+    }
+
+    // Whether the edge leaves one AS for another, as far as it is known.
+    //
+    fn is_boundary(&self, edge: &Edge) -> bool {
+        let origin = |id: &str| {
+            self.annotations.origin(id.parse().ok()?)
+        };
+
+        match (origin(&edge.from), origin(&edge.to)) {
+            (Some(from), Some(to)) => to.is_boundary(from.asns),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Graph {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(formatter, "digraph traceroute {{")?;
+        writeln!(formatter, "    node [shape=box];")?;
+
+        for node in &self.nodes {
+            let attributes = match &node.kind {
+                Kind::Source => {
+                    format!("label={}, shape=ellipse", quote(SOURCE))
+                }
+                Kind::Responder { address, reached } => {
+                    let label = match self.routers.get(address) {
+                        Some(router) => router.iter()
+                            .map(|address| self.label(*address))
+                            .collect::<Vec<String>>()
+                            .join("\n\n"),
+                        None => self.label(*address),
+                    };
+
+                    let mut attributes = format!("label={}", quote(&label));
+
+                    if *reached {
+                        attributes.push_str(", peripheries=2");
+                    }
+
+                    attributes
+                }
+                Kind::Unresponsive => {
+                    format!("label={}, style=dashed", quote("*"))
+                }
+            };
+
+            writeln!(formatter, "    {} [{}];", quote(&node.id), attributes)?;
+        }
+
+        for edge in &self.edges {
+            let mut attributes = format!("label={}", quote(&edge.label()));
+
+            if edge.dashed {
+                attributes.push_str(", style=dashed");
+            }
+
+            if self.is_boundary(edge) {
+                attributes.push_str(", color=red");
+            }
+
+            writeln!(
+                formatter,
+                "    {} -> {} [{}];",
+                quote(&edge.from),
+                quote(&edge.to),
+                attributes,
+            )?;
+        }
+
+        writeln!(formatter, "}}")
+    }
+}
+
+impl Edge {
+    fn label(&self) -> String {
+        let ttls: Vec<String> = self.ttls.iter().map(u8::to_string).collect();
+
+        match median(&self.rtts) {
+            Some(rtt) => format!(
+                "ttl {}\n{:.3} ms",
+                ttls.join(","),
+                rtt.as_secs_f64() * 1000.0,
+            ),
+            None => format!("ttl {}", ttls.join(",")),
+        }
+    }
+}
+
+fn median(values: &[Duration]) -> Option<Duration> {
+    let mut values = values.to_vec();
+    values.sort();
+
+    let middle = values.len() / 2;
+
+    match values.len() {
+        0 => None,
+        length if length % 2 == 1 => Some(values[middle]),
+        _ => Some((values[middle - 1] + values[middle]) / 2),
+    }
+}
+
+// Graphviz IDs and labels as double-quoted strings, in which only the quotes
+// need escaping. Line breaks become the centered line breaks of the labels.
+//
+fn quote(text: &str) -> String {
+    let mut result = String::from("\"");
+
+    for character in text.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            character => result.push(character),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{reply, timeout, trace, OTHER, ROUTER, SECOND, TARGET},
+        names::Names,
+    };
+    use rustraceroute::{
+        asn::AsDatabase,
+        response::{ECHO_REPLY, TIME_EXCEEDED},
+    };
+
+    #[test]
+    fn single_trace() {
+        let mut graph = Graph::new(Names::disabled().into());
+
+        graph.add(&trace(TARGET, vec![
+            vec![
+                reply(ROUTER, TIME_EXCEEDED, 1000),
+                reply(ROUTER, TIME_EXCEEDED, 3000),
+                reply(ROUTER, TIME_EXCEEDED, 2000),
+            ],
+            vec![timeout(), timeout()],
+            vec![reply(TARGET, ECHO_REPLY, 10_000), timeout()],
+        ]));
+
+        assert_eq!(graph.to_string(), concat!(
+            "digraph traceroute {\n",
+            "    node [shape=box];\n",
+            "    \"source\" [label=\"source\", shape=ellipse];\n",
+            "    \"192.0.2.1\" [label=\"192.0.2.1\\ndocumentation\"];\n",
+            "    \"* 198.51.100.1 2\" [label=\"*\", style=dashed];\n",
+            "    \"198.51.100.1\" \
+                [label=\"198.51.100.1\\ndocumentation\", peripheries=2];\n",
+            "    \"source\" -> \"192.0.2.1\" [label=\"ttl 1\\n2.000 ms\"];\n",
+            "    \"192.0.2.1\" -> \"* 198.51.100.1 2\" \
+                [label=\"ttl 2\", style=dashed];\n",
+            "    \"* 198.51.100.1 2\" -> \"198.51.100.1\" \
+                [label=\"ttl 3\\n10.000 ms\", style=dashed];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn load_balancing() {
+        let mut graph = Graph::new(Names::disabled().into());
+
+        graph.add(&trace(TARGET, vec![
+            vec![
+                reply(ROUTER, TIME_EXCEEDED, 1000),
+                reply(OTHER, TIME_EXCEEDED, 1000),
+            ],
+            vec![reply(TARGET, ECHO_REPLY, 2000)],
+        ]));
+
+        let edges: Vec<(&str, &str)> = graph.edges.iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+
+        assert_eq!(edges, vec![
+            ("source", "192.0.2.1"),
+            ("source", "192.0.2.2"),
+            ("192.0.2.1", "198.51.100.1"),
+            ("192.0.2.2", "198.51.100.1"),
+        ]);
+    }
+
+    #[test]
+    fn merged_traces() {
+        let mut graph = Graph::new(Names::disabled().into());
+
+        graph.add(&trace(TARGET, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 1000)],
+            vec![timeout()],
+            vec![reply(TARGET, ECHO_REPLY, 5000)],
+        ]));
+        graph.add(&trace(SECOND, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 2000)],
+            vec![timeout()],
+            vec![reply(TARGET, TIME_EXCEEDED, 6000)],
+            vec![reply(SECOND, ECHO_REPLY, 9000)],
+        ]));
+
+        let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str())
+            .collect();
+
+        assert_eq!(ids, vec![
+            "source",
+            "192.0.2.1",
+            "* 198.51.100.1 2",
+            "198.51.100.1",
+            "* 203.0.113.1 2",
+            "203.0.113.1",
+        ]);
+
+        assert!(matches!(
+            graph.nodes[3].kind,
+            Kind::Responder { reached: true, .. },
+        ));
+
+        assert_eq!(graph.edges.len(), 6);
+        assert_eq!(graph.edges[0].label(), "ttl 1\n1.500 ms");
+        assert_eq!(graph.edges[4].label(), "ttl 3\n6.000 ms");
+    }
+
+    #[test]
+    fn routers() {
+        let mut graph = Graph::new(Names::disabled().into())
+            .with_routers(&[vec![ROUTER, OTHER]]);
+
+        graph.add(&trace(TARGET, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 1000)],
+            vec![reply(TARGET, ECHO_REPLY, 5000)],
+        ]));
+        graph.add(&trace(SECOND, vec![
+            vec![
+                reply(OTHER, TIME_EXCEEDED, 2000),
+                reply(ROUTER, TIME_EXCEEDED, 3000),
+            ],
+            vec![reply(SECOND, ECHO_REPLY, 7000)],
+        ]));
+        graph.add(&trace(TARGET, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 2000)],
+            vec![reply(OTHER, TIME_EXCEEDED, 2000)],
+            vec![reply(TARGET, ECHO_REPLY, 5000)],
+        ]));
+
+        let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+
+        assert_eq!(ids, vec!["source", "192.0.2.1", "198.51.100.1", "203.0.113.1"]);
+        assert_eq!(graph.edges.len(), 3);
+        assert!(graph.edges.iter().all(|edge| edge.from != edge.to));
+        assert_eq!(graph.edges[0].label(), "ttl 1\n2.000 ms");
+        assert!(graph.to_string().contains(
+            "\"192.0.2.1\" [label=\"192.0.2.1\\ndocumentation\\n\\n\
+                192.0.2.2\\ndocumentation\"];",
+        ));
+    }
+
+    #[test]
+    fn names() {
+        let mut graph = Graph::new(Names::fixed(&[(ROUTER, "router")]).into());
+
+        graph.add(&trace(TARGET, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 1000)],
+            vec![reply(TARGET, ECHO_REPLY, 2000)],
+        ]));
+
+        let text = graph.to_string();
+
+        assert!(text.contains("\"192.0.2.1\" [label=\"192.0.2.1\\nrouter\\ndocumentation\"];"));
+        assert!(text.contains("\"198.51.100.1\" [label=\"198.51.100.1\\ndocumentation\", "));
+    }
+
+    #[test]
+    fn origins() {
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+        asns.insert("198.51.100.0/24".parse().unwrap(), 64501);
+
+        let mut graph = Graph::new(
+            Annotations::from(Names::disabled()).with_asns(asns),
+        );
+
+        graph.add(&trace(TARGET, vec![
+            vec![reply(ROUTER, TIME_EXCEEDED, 1000)],
+            vec![reply(OTHER, TIME_EXCEEDED, 1000)],
+            vec![reply(TARGET, ECHO_REPLY, 2000)],
+        ]));
+
+        let text = graph.to_string();
+
+        assert!(text.contains(
+            "\"192.0.2.1\" [label=\"192.0.2.1\\ndocumentation\\nAS64500 192.0.2.0/24\"];",
+        ));
+        assert!(text.contains(
+            "\"192.0.2.1\" -> \"192.0.2.2\" [label=\"ttl 2\\n1.000 ms\"];",
+        ));
+        assert!(text.contains(
+            "\"192.0.2.2\" -> \"198.51.100.1\" \
+                [label=\"ttl 3\\n2.000 ms\", color=red];",
+        ));
+    }
+
+    #[test]
+    fn median_rtt() {
+        let millis = |values: &[u64]| values.iter().copied()
+            .map(Duration::from_millis)
+            .collect::<Vec<_>>();
+
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&millis(&[3, 1, 2])), Some(Duration::from_millis(2)));
+        assert_eq!(median(&millis(&[4, 1, 2, 3])), Some(Duration::from_micros(2500)));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("a \"b\"\\\nc"), "\"a \\\"b\\\"\\\\\\nc\"");
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fmt, io};
+
+// Exit code of the usage errors found by the command-line parser.
+//
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    PermissionDenied,
+    NetworkUnreachable,
+    NoRoute,
+    MessageTooBig,
+    BadHost(String),
+    // Malformed data of a file or some other input.
+    BadInput(String),
+    // Reading or writing a file or the terminal has failed.
+    Io(String),
+    Os(i32),
+}
+
+impl Error {
+    pub fn last_os_error() -> Self {
+        Self::from_errno(io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    }
+
+    // Failures of the socket operations done through the standard library or
+    // tokio, which are told apart by their error numbers like the rest.
+    //
+    pub fn from_socket_error(error: &io::Error) -> Self {
+        Self::from_errno(error.raw_os_error().unwrap_or(libc::EIO))
+    }
+
+    pub fn from_errno(errno: i32) -> Self {
+        match errno {
+            libc::EPERM | libc::EACCES => Self::PermissionDenied,
+            libc::ENETUNREACH => Self::NetworkUnreachable,
+            libc::EHOSTUNREACH => Self::NoRoute,
+            libc::EMSGSIZE => Self::MessageTooBig,
+            errno => Self::Os(errno),
+        }
+    }
+
+    // Exit code 1 is the one of the errors nothing more specific is known
+    // about, and the parser exits with USAGE_EXIT_CODE.
+    //
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Os(_)              => 1,
+            Self::PermissionDenied   => 3,
+            Self::BadHost(_)         => 4,
+            Self::NetworkUnreachable => 5,
+            Self::NoRoute            => 6,
+            Self::MessageTooBig      => 7,
+            Self::BadInput(_)        => 8,
+            Self::Io(_)              => 9,
+        }
+    }
+
+    // Errors which affect a single probe rather than the whole trace.
+    //
+    pub fn is_transient(&self) -> bool {
+        self.annotation().is_some()
+    }
+
+    // Transient errors are reported on the hop line in the same way as
+    // traceroute does.
+    //
+    pub fn annotation(&self) -> Option<&'static str> {
+        match self {
+            Self::NetworkUnreachable => Some("!N"),
+            Self::NoRoute            => Some("!H"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied => write!(
+                f,
+                "permission denied (raw sockets require root or CAP_NET_RAW)",
+            ),
+            Self::NetworkUnreachable => write!(f, "network is unreachable"),
+            Self::NoRoute => write!(f, "no route to host"),
+            Self::MessageTooBig => write!(f, "message too big"),
+            Self::BadHost(host) => write!(f, "cannot resolve host \"{}\"", host),
+            Self::BadInput(message) => write!(f, "{}", message),
+            Self::Io(message) => write!(f, "{}", message),
+            Self::Os(errno) => io::Error::from_raw_os_error(*errno).fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The errors of the files, which are not those of the socket even if their
+// error numbers are the same: a file which cannot be read has nothing to do
+// with the privileges of raw sockets.
+//
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::InvalidData => Self::BadInput(error.to_string()),
+            _ => Self::Io(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_errno() {
+        assert_eq!(Error::from_errno(libc::EPERM), Error::PermissionDenied);
+        assert_eq!(Error::from_errno(libc::EACCES), Error::PermissionDenied);
+        assert_eq!(Error::from_errno(libc::ENETUNREACH), Error::NetworkUnreachable);
+        assert_eq!(Error::from_errno(libc::EHOSTUNREACH), Error::NoRoute);
+        assert_eq!(Error::from_errno(libc::EMSGSIZE), Error::MessageTooBig);
+        assert_eq!(Error::from_errno(libc::EIO), Error::Os(libc::EIO));
+    }
+
+    #[test]
+    fn from_socket_error() {
+        assert_eq!(
+            Error::from_socket_error(&io::Error::from_raw_os_error(libc::ENETUNREACH)),
+            Error::NetworkUnreachable,
+        );
+        assert_eq!(
+            Error::from_socket_error(&io::Error::from_raw_os_error(libc::EPERM)),
+            Error::PermissionDenied,
+        );
+        assert_eq!(
+            Error::from_socket_error(&io::Error::other("other")),
+            Error::Os(libc::EIO),
+        );
+    }
+
+    #[test]
+    fn from_io_error() {
+        let error = io::Error::from_raw_os_error(libc::EACCES);
+
+        assert_eq!(Error::from(error), Error::Io("Permission denied (os error 13)".into()));
+        assert_eq!(
+            Error::from(io::Error::other("other")),
+            Error::Io("other".into()),
+        );
+        assert_eq!(
+            Error::from(io::Error::new(io::ErrorKind::InvalidData, "bad")),
+            Error::BadInput("bad".into()),
+        );
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let errors = [
+            Error::PermissionDenied,
+            Error::NetworkUnreachable,
+            Error::NoRoute,
+            Error::MessageTooBig,
+            Error::BadHost(String::new()),
+            Error::BadInput(String::new()),
+            Error::Io(String::new()),
+            Error::Os(libc::EIO),
+        ];
+
+        for (index, error) in errors.iter().enumerate() {
+            assert_ne!(error.exit_code(), 0);
+            assert_ne!(error.exit_code(), USAGE_EXIT_CODE);
+
+            for other in &errors[(index + 1)..] {
+                assert_ne!(error.exit_code(), other.exit_code());
+            }
+        }
+    }
+
+    #[test]
+    fn annotation() {
+        assert_eq!(Error::NetworkUnreachable.annotation(), Some("!N"));
+        assert_eq!(Error::NoRoute.annotation(), Some("!H"));
+        assert_eq!(Error::PermissionDenied.annotation(), None);
+        assert_eq!(Error::MessageTooBig.annotation(), None);
+    }
+
+    #[test]
+    fn is_transient() {
+        assert!(Error::NetworkUnreachable.is_transient());
+        assert!(Error::NoRoute.is_transient());
+        assert!(!Error::PermissionDenied.is_transient());
+        assert!(!Error::Os(libc::EIO).is_transient());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            format!("{}", Error::BadHost("example.invalid".into())),
+            "cannot resolve host \"example.invalid\"",
+        );
+        assert_eq!(format!("{}", Error::NoRoute), "no route to host");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    error::Error,
+    response::Response,
+    trace::{Hop, ProbeResult, ProbeStatus, Trace},
+};
+use std::{net::IpAddr, time::{Duration, SystemTime}};
+
+// Progress of a trace, in the order it happens. Every probe is either sent or
+// failed to be sent, and every sent probe is then either replied to or timed
+// out.
+//
+#[derive(Clone, Debug)]
+pub enum Event {
+    ProbeSent { ttl: u8, sequence: u16, sent: SystemTime },
+    ProbeFailed { ttl: u8, sequence: u16, error: Error },
+    ReplyReceived { ttl: u8, sequence: u16, response: Response, rtt: Duration },
+    ProbeTimedOut { ttl: u8, sequence: u16 },
+    HopCompleted(Hop),
+    DestinationReached { ttl: u8, address: IpAddr },
+    TraceFinished(Trace),
+}
+
+// Raw packet as it has been sent or received, for capturing. Probes are ICMP
+// messages without the IP header, received packets include it.
+//
+#[derive(Clone, Copy, Debug)]
+pub enum Packet<'a> {
+    Sent {
+        ttl: u8,
+        sequence: u16,
+        time: SystemTime,
+        destination: IpAddr,
+        data: &'a [u8],
+    },
+    Received {
+        time: SystemTime,
+        data: &'a [u8],
+        // TTL and sequence number of the probe the packet is the reply to.
+        probe: Option<(u8, u16)>,
+    },
+}
+
+pub trait Observer {
+    fn on_event(&mut self, event: &Event);
+
+    // Most observers are not interested in the packets themselves.
+    //
+    fn on_packet(&mut self, _packet: &Packet<'_>) {}
+}
+
+impl<F: FnMut(&Event)> Observer for F {
+    fn on_event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+// Reports everything to both observers.
+//
+pub struct Tee<'a, A: ?Sized, B: ?Sized>(pub &'a mut A, pub &'a mut B);
+
+impl<A: Observer + ?Sized, B: Observer + ?Sized> Observer for Tee<'_, A, B> {
+    fn on_event(&mut self, event: &Event) {
+        self.0.on_event(event);
+        self.1.on_event(event);
+    }
+
+    fn on_packet(&mut self, packet: &Packet<'_>) {
+        self.0.on_packet(packet);
+        self.1.on_packet(packet);
+    }
+}
+
+impl Event {
+    // The event which tells how the probe has ended.
+    //
+    pub fn probe_completed(ttl: u8, probe: &ProbeResult) -> Self {
+        let sequence = probe.sequence;
+
+        match &probe.status {
+            ProbeStatus::Reply { response, rtt } => Self::ReplyReceived {
+                ttl,
+                sequence,
+                response: response.clone(),
+                rtt: *rtt,
+            },
+            ProbeStatus::Timeout => Self::ProbeTimedOut { ttl, sequence },
+            ProbeStatus::Error(error) => Self::ProbeFailed {
+                ttl,
+                sequence,
+                error: error.clone(),
+            },
+        }
+    }
+}
+
+pub(crate) fn report_hop<O: Observer + ?Sized>(observer: &mut O, hop: &Hop) {
+    observer.on_event(&Event::HopCompleted(hop.clone()));
+
+    let address = hop.probes.iter().filter_map(ProbeResult::response)
+        .find(|response| response.is_echo_reply())
+        .map(|response| response.source);
+
+    if let Some(address) = address {
+        observer.on_event(&Event::DestinationReached { ttl: hop.ttl, address });
+    }
+}
+
+// Reports the finished trace, e.g. one read from a file, as if it was
+// happening.
+//
+pub fn replay<O: Observer + ?Sized>(trace: &Trace, observer: &mut O) {
+    for hop in &trace.hops {
+        for probe in &hop.probes {
+            if probe.error().is_none() {
+                observer.on_event(&Event::ProbeSent {
+                    ttl: hop.ttl,
+                    sequence: probe.sequence,
+                    sent: probe.sent,
+                });
+            }
+
+            observer.on_event(&Event::probe_completed(hop.ttl, probe));
+        }
+
+        report_hop(observer, hop);
+    }
+
+    observer.on_event(&Event::TraceFinished(trace.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::ROUTER, response::TIME_EXCEEDED};
+
+    fn probe(status: ProbeStatus) -> ProbeResult {
+        crate::fixtures::probe(7, status)
+    }
+
+    #[test]
+    fn probe_completed_reply() {
+        let response = Response {
+            source: ROUTER,
+            type_: TIME_EXCEEDED,
+            ..Default::default()
+        };
+
+        let event = Event::probe_completed(3, &probe(ProbeStatus::Reply {
+            response,
+            rtt: Duration::from_millis(1),
+        }));
+
+        match event {
+            Event::ReplyReceived { ttl, sequence, response, rtt } => {
+                assert_eq!(ttl, 3);
+                assert_eq!(sequence, 7);
+                assert_eq!(response.source, ROUTER);
+                assert_eq!(rtt, Duration::from_millis(1));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn probe_completed_timeout() {
+        assert!(matches!(
+            Event::probe_completed(3, &probe(ProbeStatus::Timeout)),
+            Event::ProbeTimedOut { ttl: 3, sequence: 7 },
+        ));
+    }
+
+    #[test]
+    fn probe_completed_error() {
+        assert!(matches!(
+            Event::probe_completed(3, &probe(ProbeStatus::Error(Error::NoRoute))),
+            Event::ProbeFailed { ttl: 3, sequence: 7, error: Error::NoRoute },
+        ));
+    }
+
+    #[test]
+    fn closure_observer() {
+        let mut count = 0;
+
+        {
+            let mut observer = |_: &Event| count += 1;
+
+            report_hop(&mut observer, &Hop::new(1));
+        }
+
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn replay_trace() {
+        let trace = Trace {
+            target: ROUTER,
+            started: SystemTime::UNIX_EPOCH,
+            hops: vec![Hop {
+                ttl: 1,
+                probes: vec![
+                    probe(ProbeStatus::Timeout),
+                    probe(ProbeStatus::Error(Error::NoRoute)),
+                ],
+            }],
+        };
+
+        let mut events = vec![];
+
+        replay(&trace, &mut |event: &Event| events.push(event.clone()));
+
+        assert!(matches!(events[0], Event::ProbeSent { ttl: 1, .. }));
+        assert!(matches!(events[1], Event::ProbeTimedOut { ttl: 1, .. }));
+        assert!(matches!(events[2], Event::ProbeFailed { ttl: 1, .. }));
+        assert!(matches!(events[3], Event::HopCompleted(_)));
+        assert!(matches!(events[4], Event::TraceFinished(_)));
+        assert_eq!(events.len(), 5);
+    }
+}
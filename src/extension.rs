@@ -0,0 +1,184 @@
+use crate::checksum::checksum;
+
+pub const MPLS_CLASS: u8 = 1;
+pub const MPLS_INCOMING_STACK: u8 = 1;
+
+// Length of the original datagram assumed by RFC 4884 for ICMP messages which
+// do not specify it.
+//
+const COMPAT_ORIGINAL_LENGTH: usize = 128;
+
+// Object of the ICMP extension structure, see RFC 4884.
+//
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Extension {
+    pub class: u8,
+    pub type_: u8,
+    pub payload: Vec<u8>,
+}
+
+// Label stack entry of the MPLS extension object, see RFC 4950.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MplsLabel {
+    pub label: u32,
+    pub exp: u8,
+    pub bottom: bool,
+    pub ttl: u8,
+}
+
+impl Extension {
+    pub fn mpls_labels(&self) -> Option<Vec<MplsLabel>> {
+        if self.class != MPLS_CLASS || self.type_ != MPLS_INCOMING_STACK {
+            return None
+        }
+
+        Some(self.payload.chunks_exact(4).map(|entry| MplsLabel {
+            label: ((entry[0] as u32) << 12) +
+                   ((entry[1] as u32) << 4)  +
+                   ((entry[2] as u32) >> 4),
+            exp: (entry[2] >> 1) & 0x07,
+            bottom: entry[2] & 0x01 == 1,
+            ttl: entry[3],
+        }).collect())
+    }
+}
+
+// Parses the extension structure which follows the original datagram in the
+// ICMP error message. The message starts with the ICMP header.
+//
+pub fn parse(message: &[u8]) -> Vec<Extension> {
+    if message.len() < 8 {
+        return vec![]
+    }
+
+    let original_length = match message[5] as usize * 4 {
+        0 => COMPAT_ORIGINAL_LENGTH,
+        length => length,
+    };
+
+    let structure = match message.get((8 + original_length)..) {
+        Some(structure) if structure.len() >= 4 => structure,
+        _ => return vec![],
+    };
+
+    let version = structure[0] >> 4;
+    let sum = u16::from_be_bytes([structure[2], structure[3]]);
+
+    if version != 2 || (sum != 0 && checksum(structure) != 0) {
+        return vec![]
+    }
+
+    let mut result = vec![];
+    let mut objects = &structure[4..];
+
+    while objects.len() >= 4 {
+        let length = u16::from_be_bytes([objects[0], objects[1]]) as usize;
+
+        if length < 4 || length > objects.len() {
+            break
+        }
+
+        result.push(Extension {
+            class: objects[2],
+            type_: objects[3],
+            payload: objects[4..length].to_vec(),
+        });
+
+        objects = &objects[length..];
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Time exceeded with a 128 bytes long original datagram followed by an
+    // MPLS object with two labels.
+    //
+    fn message(length_field: u8, with_checksum: bool) -> Vec<u8> {
+        let mut result = vec![11, 0, 0, 0, 0, length_field, 0, 0];
+
+        result.extend_from_slice(&[0; 128]);
+
+        let mut structure = vec![
+            0x20, 0, 0, 0,
+            0, 12, MPLS_CLASS, MPLS_INCOMING_STACK,
+            0x01, 0x23, 0x4A, 0xFE, // label 4660, exp 5, not bottom, ttl 254
+            0x00, 0x01, 0x01, 0x01, // label 16, exp 0, bottom, ttl 1
+        ];
+
+        if with_checksum {
+            let sum = checksum(&structure);
+
+            structure[2] = (sum >> 8) as u8;
+            structure[3] = sum as u8;
+        }
+
+        result.extend_from_slice(&structure);
+        result
+    }
+
+    #[test]
+    fn parse_mpls() {
+        let extensions = parse(&message(32, true));
+
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0].class, MPLS_CLASS);
+        assert_eq!(extensions[0].type_, MPLS_INCOMING_STACK);
+
+        assert_eq!(extensions[0].mpls_labels(), Some(vec![
+            MplsLabel { label: 4660, exp: 5, bottom: false, ttl: 254 },
+            MplsLabel { label: 16,   exp: 0, bottom: true,  ttl: 1 },
+        ]));
+    }
+
+    #[test]
+    fn parse_without_length() {
+        assert_eq!(parse(&message(0, true)).len(), 1);
+    }
+
+    #[test]
+    fn parse_without_checksum() {
+        assert_eq!(parse(&message(32, false)).len(), 1);
+    }
+
+    #[test]
+    fn parse_bad_checksum() {
+        let mut message = message(32, true);
+        message[8 + 128 + 2] ^= 0xFF;
+
+        assert!(parse(&message).is_empty());
+    }
+
+    #[test]
+    fn parse_bad_version() {
+        let mut message = message(32, false);
+        message[8 + 128] = 0x10;
+
+        assert!(parse(&message).is_empty());
+    }
+
+    #[test]
+    fn parse_bad_object_length() {
+        let mut message = message(32, false);
+        message[8 + 128 + 5] = 200;
+
+        assert!(parse(&message).is_empty());
+    }
+
+    #[test]
+    fn parse_short() {
+        assert!(parse(&[11, 0, 0, 0]).is_empty());
+        assert!(parse(&[11, 0, 0, 0, 0, 0, 0, 0, 69, 0, 0, 28]).is_empty());
+    }
+
+    #[test]
+    fn not_mpls() {
+        let extension = Extension { class: 2, type_: 1, payload: vec![] };
+
+        assert_eq!(extension.mpls_labels(), None);
+    }
+}
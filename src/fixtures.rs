@@ -0,0 +1,59 @@
+// Addresses, probes and traces the tests are made of. The module is part of
+// the tests of both the library and the program, each of which uses only some
+// of it.
+//
+#![allow(dead_code)]
+
+use rustraceroute::{Event, Hop, ProbeResult, ProbeStatus, Response, Trace};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, SystemTime},
+};
+
+pub const ROUTER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+pub const OTHER:  IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+pub const SOURCE: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 100));
+pub const TARGET: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+pub const SECOND: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+
+// Probe sent at the epoch.
+//
+pub fn probe(sequence: u16, status: ProbeStatus) -> ProbeResult {
+    ProbeResult { sequence, sent: SystemTime::UNIX_EPOCH, status }
+}
+
+pub fn reply(source: IpAddr, type_: u8, micros: u64) -> ProbeResult {
+    probe(0, ProbeStatus::Reply {
+        response: Response { source, type_, ..Default::default() },
+        rtt: Duration::from_micros(micros),
+    })
+}
+
+pub fn timeout() -> ProbeResult {
+    probe(0, ProbeStatus::Timeout)
+}
+
+// Trace started at the epoch, with the hops from TTL 1 on.
+//
+pub fn trace(target: IpAddr, hops: Vec<Vec<ProbeResult>>) -> Trace {
+    Trace {
+        target,
+        started: SystemTime::UNIX_EPOCH,
+        hops: hops.into_iter().zip(1..)
+            .map(|(probes, ttl)| Hop { ttl, probes })
+            .collect(),
+    }
+}
+
+pub fn probe_sent(ttl: u8) -> Event {
+    Event::ProbeSent { ttl, sequence: 0, sent: SystemTime::UNIX_EPOCH }
+}
+
+pub fn reply_received(ttl: u8, source: IpAddr, type_: u8, micros: u64) -> Event {
+    Event::ReplyReceived {
+        ttl,
+        sequence: 0,
+        response: Response { source, type_, ..Default::default() },
+        rtt: Duration::from_micros(micros),
+    }
+}
@@ -0,0 +1,528 @@
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+    net::IpAddr,
+};
+
+// The metadata follows the last occurrence of this marker.
+//
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+// The data section follows the search tree after this many zero bytes.
+//
+const SEPARATOR: usize = 16;
+
+// Deeper pointers than this are a loop.
+//
+const MAX_DEPTH: usize = 32;
+
+const POINTER: u8 = 1;
+const STRING: u8 = 2;
+const DOUBLE: u8 = 3;
+const BYTES: u8 = 4;
+const UINT16: u8 = 5;
+const UINT32: u8 = 6;
+const MAP: u8 = 7;
+const INT32: u8 = 8;
+const UINT64: u8 = 9;
+const UINT128: u8 = 10;
+const ARRAY: u8 = 11;
+const BOOLEAN: u8 = 14;
+const FLOAT: u8 = 15;
+
+// Database in the MaxMind DB format, see
+// https://maxmind.github.io/MaxMind-DB/
+// Only the lookups of the GeoIP2 and GeoLite2 City and Country records are
+// supported.
+//
+pub struct GeoDatabase {
+    data: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u16,
+}
+
+// Where the address is, as far as the database knows. The names are the
+// English ones.
+//
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    pub country_code: Option<String>,
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    String(String),
+    Double(f64),
+    Unsigned(u128),
+    Signed(i32),
+    Boolean(bool),
+    Map(Vec<(String, Value)>),
+    Array(Vec<Value>),
+    Bytes,
+}
+
+impl GeoDatabase {
+    pub fn read<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+
+        let start = data.windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or_else(|| invalid("metadata not found"))? +
+            METADATA_MARKER.len();
+
+        let metadata = Decoder { data: &data[start..] }.value(0, 0)?.0;
+
+        let unsigned = |key: &str| match metadata.get(key) {
+            Some(Value::Unsigned(value)) => Ok(*value),
+            _ => Err(invalid(&format!("no {} in the metadata", key))),
+        };
+
+        let node_count = unsigned("node_count")? as usize;
+        let record_size = unsigned("record_size")? as usize;
+        let ip_version = unsigned("ip_version")? as u16;
+
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(invalid("unsupported record size"))
+        }
+
+        let result = Self { data, node_count, record_size, ip_version };
+
+        if result.data_start() > start {
+            return Err(invalid("search tree beyond the end of data"))
+        }
+
+        Ok(result)
+    }
+
+    // Returns `None` if the database has nothing about the address, and an
+    // empty location if it has no location of it.
+    //
+    pub fn lookup(&self, address: IpAddr) -> io::Result<Option<Location>> {
+        let record = match self.find(address)? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        let offset = (record - self.node_count).checked_sub(SEPARATOR)
+            .ok_or_else(|| invalid("bad data pointer"))?;
+        let decoder = Decoder { data: &self.data[self.data_start()..] };
+        let value = decoder.value(offset, 0)?.0;
+
+        let string = |path: &[&str]| match value.at(path) {
+            Some(Value::String(string)) => Some(string.clone()),
+            _ => None,
+        };
+
+        let number = |path: &[&str]| match value.at(path) {
+            Some(Value::Double(number)) => Some(*number),
+            _ => None,
+        };
+
+        Ok(Some(Location {
+            country_code: string(&["country", "iso_code"]),
+            country: string(&["country", "names", "en"]),
+            city: string(&["city", "names", "en"]),
+            latitude: number(&["location", "latitude"]),
+            longitude: number(&["location", "longitude"]),
+        }))
+    }
+
+    // Walks the search tree along the bits of the address, returns the record
+    // pointing to the data, if any.
+    //
+    fn find(&self, address: IpAddr) -> io::Result<Option<usize>> {
+        let (bits, width) = match address {
+            IpAddr::V4(address) => (u128::from(u32::from(address)), 32),
+            IpAddr::V6(address) if self.ip_version == 6 => {
+                (u128::from(address), 128)
+            }
+            IpAddr::V6(_) => return Ok(None),
+        };
+
+        let mut node = 0;
+
+        // IPv4 addresses are in the IPv4-compatible subtree of the IPv6
+        // databases.
+        //
+        if address.is_ipv4() && self.ip_version == 6 {
+            for _ in 0..96 {
+                if node >= self.node_count {
+                    break
+                }
+
+                node = self.record(node, 0)?;
+            }
+        }
+
+        for bit in (0..width).rev() {
+            if node >= self.node_count {
+                break
+            }
+
+            node = self.record(node, ((bits >> bit) & 1) as usize)?;
+        }
+
+        match node {
+            node if node > self.node_count => Ok(Some(node)),
+            _ => Ok(None),
+        }
+    }
+
+    fn record(&self, node: usize, side: usize) -> io::Result<usize> {
+        let size = self.record_size * 2 / 8;
+        let bytes = slice(&self.data, node * size, size)?;
+
+        let value = match (self.record_size, side) {
+            (24, _) => be(&bytes[side * 3..side * 3 + 3]),
+            (28, 0) => be(&bytes[..3]) | (usize::from(bytes[3] & 0xf0) << 20),
+            (28, _) => be(&bytes[4..]) | (usize::from(bytes[3] & 0x0f) << 24),
+            _ => be(&bytes[side * 4..side * 4 + 4]),
+        };
+
+        Ok(value)
+    }
+
+    fn data_start(&self) -> usize {
+        self.node_count * self.record_size * 2 / 8 + SEPARATOR
+    }
+}
+
+impl Location {
+    // The city and the country, whichever are known.
+    //
+    pub fn place(&self) -> Option<String> {
+        let parts: Vec<&str> = [&self.city, &self.country_code].iter()
+            .filter_map(|part| part.as_deref())
+            .collect();
+
+        match parts.is_empty() {
+            true => None,
+            false => Some(parts.join(", ")),
+        }
+    }
+
+    // Great-circle distance in kilometres, if both coordinates are known.
+    //
+    pub fn distance(&self, other: &Location) -> Option<f64> {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+
+        let latitude1 = self.latitude?.to_radians();
+        let latitude2 = other.latitude?.to_radians();
+        let longitude = (other.longitude? - self.longitude?).to_radians();
+
+        let a = ((latitude2 - latitude1) / 2.0).sin().powi(2) +
+            latitude1.cos() * latitude2.cos() * (longitude / 2.0).sin().powi(2);
+
+        Some(2.0 * EARTH_RADIUS_KM * a.sqrt().asin())
+    }
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Map(entries) => entries.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn at(&self, path: &[&str]) -> Option<&Value> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl Decoder<'_> {
+    // Returns the value and the offset of what follows it.
+    //
+    fn value(&self, offset: usize, depth: usize) -> io::Result<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("too deeply nested data"))
+        }
+
+        let control = slice(self.data, offset, 1)?[0];
+        let mut offset = offset + 1;
+
+        let mut type_ = control >> 5;
+
+        if type_ == POINTER {
+            let (pointer, next) = self.pointer(control, offset)?;
+
+            return Ok((self.value(pointer, depth + 1)?.0, next))
+        }
+
+        if type_ == 0 {
+            type_ = slice(self.data, offset, 1)?[0].checked_add(7)
+                .ok_or_else(|| invalid("unknown data type"))?;
+            offset += 1;
+        }
+
+        let mut size = usize::from(control & 0x1f);
+
+        match size {
+            29 => {
+                size = 29 + be(slice(self.data, offset, 1)?);
+                offset += 1;
+            }
+            30 => {
+                size = 285 + be(slice(self.data, offset, 2)?);
+                offset += 2;
+            }
+            31 => {
+                size = 65_821 + be(slice(self.data, offset, 3)?);
+                offset += 3;
+            }
+            _ => {}
+        }
+
+        let value = match type_ {
+            MAP => {
+                let mut entries = vec![];
+
+                for _ in 0..size {
+                    let (key, next) = self.value(offset, depth + 1)?;
+                    let (value, next) = self.value(next, depth + 1)?;
+
+                    let key = match key {
+                        Value::String(key) => key,
+                        _ => return Err(invalid("map key is not a string")),
+                    };
+
+                    entries.push((key, value));
+                    offset = next;
+                }
+
+                return Ok((Value::Map(entries), offset))
+            }
+            ARRAY => {
+                let mut values = vec![];
+
+                for _ in 0..size {
+                    let (value, next) = self.value(offset, depth + 1)?;
+
+                    values.push(value);
+                    offset = next;
+                }
+
+                return Ok((Value::Array(values), offset))
+            }
+            BOOLEAN => return Ok((Value::Boolean(size != 0), offset)),
+            _ => slice(self.data, offset, size)?,
+        };
+
+        let result = match type_ {
+            STRING => Value::String(
+                std::str::from_utf8(value)
+                    .map_err(|_| invalid("bad UTF-8 string"))?
+                    .to_string(),
+            ),
+            DOUBLE => Value::Double(f64::from_be_bytes(
+                value.try_into().map_err(|_| invalid("bad double size"))?,
+            )),
+            FLOAT => Value::Double(f32::from_be_bytes(
+                value.try_into().map_err(|_| invalid("bad float size"))?,
+            ).into()),
+            UINT16 | UINT32 | UINT64 | UINT128 => {
+                Value::Unsigned(value.iter()
+                    .fold(0, |result, byte| (result << 8) | u128::from(*byte)))
+            }
+            INT32 => Value::Signed(value.iter()
+                .fold(0, |result, byte| (result << 8) | i32::from(*byte))),
+            BYTES => Value::Bytes,
+            _ => return Err(invalid("unknown data type")),
+        };
+
+        Ok((result, offset + size))
+    }
+
+    fn pointer(&self, control: u8, offset: usize) -> io::Result<(usize, usize)> {
+        let high = usize::from(control & 0x07);
+
+        Ok(match (control >> 3) & 0x03 {
+            0 => ((high << 8) | be(slice(self.data, offset, 1)?), offset + 1),
+            1 => (
+                ((high << 16) | be(slice(self.data, offset, 2)?)) + 2048,
+                offset + 2,
+            ),
+            2 => (
+                ((high << 24) | be(slice(self.data, offset, 3)?)) + 526_336,
+                offset + 3,
+            ),
+            _ => (be(slice(self.data, offset, 4)?), offset + 4),
+        })
+    }
+}
+
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |result, byte| (result << 8) | usize::from(*byte))
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+    offset.checked_add(length)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid("unexpected end of data"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("GeoIP database: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut result = vec![(STRING << 5) | text.len() as u8];
+        result.extend(text.as_bytes());
+        result
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut result = vec![(MAP << 5) | entries.len() as u8];
+
+        for (key, value) in entries {
+            result.extend(string(key));
+            result.extend(value);
+        }
+
+        result
+    }
+
+    fn double(value: f64) -> Vec<u8> {
+        let mut result = vec![(DOUBLE << 5) | 8];
+        result.extend(value.to_be_bytes());
+        result
+    }
+
+    fn unsigned(value: u16) -> Vec<u8> {
+        let mut result = vec![(UINT16 << 5) | 2];
+        result.extend(value.to_be_bytes());
+        result
+    }
+
+    // IPv4 database of 24-bit records with a single network, 192.0.2.0/24.
+    // Node n of the path has the next bit of the network on one side and
+    // "not found" on the other, the last one points to the data. The country
+    // of the record is a pointer to the one of the first record.
+    //
+    fn database() -> Vec<u8> {
+        let network: u32 = 0xc000_0200;
+        let node_count = 24;
+
+        // The country map follows the map of one entry and its key.
+        //
+        let country = 1 + string("country").len() as u8;
+
+        let mut data = map(&[("country", map(&[
+            ("iso_code", string("US")),
+            ("names", map(&[("en", string("United States"))])),
+        ]))]);
+        let second = data.len();
+
+        data.extend(map(&[
+            ("city", map(&[("names", map(&[("en", string("Chicago"))]))])),
+            ("country", vec![POINTER << 5, country]),
+            ("location", map(&[
+                ("latitude", double(41.85)),
+                ("longitude", double(-87.65)),
+            ])),
+        ]));
+
+        let mut tree = vec![];
+
+        for node in 0..node_count {
+            let bit = (network >> (31 - node)) & 1;
+
+            let next = match node + 1 {
+                next if next == node_count => {
+                    node_count as usize + SEPARATOR + second
+                }
+                next => next as usize,
+            };
+
+            let mut records = [node_count as usize; 2];
+            records[bit as usize] = next;
+
+            for record in &records {
+                tree.extend(&(*record as u32).to_be_bytes()[1..]);
+            }
+        }
+
+        let mut result = tree;
+        result.extend([0; SEPARATOR]);
+        result.extend(data);
+        result.extend(METADATA_MARKER);
+        result.extend(map(&[
+            ("node_count", unsigned(node_count as u16)),
+            ("record_size", unsigned(24)),
+            ("ip_version", unsigned(4)),
+        ]));
+        result
+    }
+
+    #[test]
+    fn lookup() {
+        let database = GeoDatabase::read(&database()[..]).unwrap();
+
+        let location = database.lookup("192.0.2.77".parse().unwrap())
+            .unwrap().unwrap();
+
+        assert_eq!(location, Location {
+            country_code: Some("US".to_string()),
+            country: Some("United States".to_string()),
+            city: Some("Chicago".to_string()),
+            latitude: Some(41.85),
+            longitude: Some(-87.65),
+        });
+        assert_eq!(location.place().unwrap(), "Chicago, US");
+
+        assert_eq!(database.lookup("192.0.3.1".parse().unwrap()).unwrap(), None);
+        assert_eq!(database.lookup("2001:db8::1".parse().unwrap()).unwrap(), None);
+    }
+
+    #[test]
+    fn pointer_sizes() {
+        let data = [0u8; 8];
+        let decoder = Decoder { data: &data };
+
+        assert_eq!(decoder.pointer(0x21, 0).unwrap(), (0x100, 1));
+        assert_eq!(decoder.pointer(0x29, 0).unwrap(), (0x10000 + 2048, 2));
+        assert_eq!(decoder.pointer(0x31, 0).unwrap(), (0x1000000 + 526_336, 3));
+        assert_eq!(decoder.pointer(0x38, 0).unwrap(), (0, 4));
+    }
+
+    #[test]
+    fn extended_types() {
+        // Array of one unsigned 32-bit integer, then a true boolean.
+        //
+        let data = [0x01, 0x04, 0xc1, 0x05, 0x01, 0x07];
+        let decoder = Decoder { data: &data };
+
+        assert_eq!(
+            decoder.value(0, 0).unwrap(),
+            (Value::Array(vec![Value::Unsigned(5)]), 4),
+        );
+        assert_eq!(decoder.value(4, 0).unwrap(), (Value::Boolean(true), 6));
+
+        // Data cache containers are not expected in the data.
+        //
+        assert!(Decoder { data: &[0x00, 0x05] }.value(0, 0).is_err());
+    }
+
+    #[test]
+    fn no_metadata() {
+        let error = GeoDatabase::read(&b"not a database"[..]).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::geoip::Location;
+use regex_lite::Regex;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    time::Duration,
+};
+
+// Distance light travels in fiber in a millisecond, about two thirds of the
+// speed of light in vacuum.
+//
+pub const FIBER_KM_PER_MS: f64 = 199.86;
+
+// Airport and metro codes the operators name their routers after, with the
+// cities they stand for.
+//
+const SITES: [(&str, &str, &str, f64, f64); 109] = [
+    ("akl", "Auckland",         "NZ", -36.85,  174.76),
+    ("ams", "Amsterdam",        "NL",  52.37,    4.90),
+    ("arn", "Stockholm",        "SE",  59.33,   18.07),
+    ("ath", "Athens",           "GR",  37.98,   23.73),
+    ("atl", "Atlanta",          "US",  33.75,  -84.39),
+    ("bcn", "Barcelona",        "ES",  41.39,    2.17),
+    ("ber", "Berlin",           "DE",  52.52,   13.40),
+    ("bkk", "Bangkok",          "TH",  13.76,  100.50),
+    ("bne", "Brisbane",         "AU", -27.47,  153.03),
+    ("bog", "Bogota",           "CO",   4.71,  -74.07),
+    ("bom", "Mumbai",           "IN",  19.08,   72.88),
+    ("bos", "Boston",           "US",  42.36,  -71.06),
+    ("bru", "Brussels",         "BE",  50.85,    4.35),
+    ("bud", "Budapest",         "HU",  47.50,   19.04),
+    ("buh", "Bucharest",        "RO",  44.43,   26.10),
+    ("cai", "Cairo",            "EG",  30.04,   31.24),
+    ("cdg", "Paris",            "FR",  48.86,    2.35),
+    ("cgk", "Jakarta",          "ID",  -6.21,  106.85),
+    ("chi", "Chicago",          "US",  41.88,  -87.63),
+    ("clt", "Charlotte",        "US",  35.23,  -80.84),
+    ("cph", "Copenhagen",       "DK",  55.68,   12.57),
+    ("cpt", "Cape Town",        "ZA", -33.92,   18.42),
+    ("dca", "Washington",       "US",  38.91,  -77.04),
+    ("del", "New Delhi",        "IN",  28.61,   77.21),
+    ("den", "Denver",           "US",  39.74, -104.99),
+    ("dfw", "Dallas",           "US",  32.78,  -96.80),
+    ("doh", "Doha",             "QA",  25.29,   51.53),
+    ("dub", "Dublin",           "IE",  53.35,   -6.26),
+    ("dus", "Dusseldorf",       "DE",  51.23,    6.77),
+    ("dxb", "Dubai",            "AE",  25.20,   55.27),
+    ("ewr", "Newark",           "US",  40.74,  -74.17),
+    ("eze", "Buenos Aires",     "AR", -34.60,  -58.38),
+    ("fjr", "Fujairah",         "AE",  25.13,   56.33),
+    ("fra", "Frankfurt",        "DE",  50.11,    8.68),
+    ("gru", "Sao Paulo",        "BR", -23.55,  -46.63),
+    ("ham", "Hamburg",          "DE",  53.55,    9.99),
+    ("hel", "Helsinki",         "FI",  60.17,   24.94),
+    ("hkg", "Hong Kong",        "HK",  22.32,  114.17),
+    ("hou", "Houston",          "US",  29.76,  -95.37),
+    ("iad", "Ashburn",          "US",  39.04,  -77.49),
+    ("iah", "Houston",          "US",  29.76,  -95.37),
+    ("icn", "Seoul",            "KR",  37.57,  126.98),
+    ("iev", "Kyiv",             "UA",  50.45,   30.52),
+    ("ist", "Istanbul",         "TR",  41.01,   28.98),
+    ("jfk", "New York",         "US",  40.71,  -74.01),
+    ("jkt", "Jakarta",          "ID",  -6.21,  106.85),
+    ("jnb", "Johannesburg",     "ZA", -26.20,   28.05),
+    ("kix", "Osaka",            "JP",  34.69,  135.50),
+    ("kul", "Kuala Lumpur",     "MY",   3.15,  101.69),
+    ("las", "Las Vegas",        "US",  36.17, -115.14),
+    ("lax", "Los Angeles",      "US",  34.05, -118.24),
+    ("led", "Saint Petersburg", "RU",  59.94,   30.32),
+    ("lhr", "London",           "GB",  51.51,   -0.13),
+    ("lim", "Lima",             "PE", -12.05,  -77.04),
+    ("lis", "Lisbon",           "PT",  38.72,   -9.14),
+    ("lon", "London",           "GB",  51.51,   -0.13),
+    ("los", "Lagos",            "NG",   6.52,    3.38),
+    ("mad", "Madrid",           "ES",  40.42,   -3.70),
+    ("man", "Manchester",       "GB",  53.48,   -2.24),
+    ("mci", "Kansas City",      "US",  39.10,  -94.58),
+    ("mel", "Melbourne",        "AU", -37.81,  144.96),
+    ("mex", "Mexico City",      "MX",  19.43,  -99.13),
+    ("mia", "Miami",            "US",  25.76,  -80.19),
+    ("mil", "Milan",            "IT",  45.46,    9.19),
+    ("mnl", "Manila",           "PH",  14.60,  120.98),
+    ("mow", "Moscow",           "RU",  55.76,   37.62),
+    ("mrs", "Marseille",        "FR",  43.30,    5.37),
+    ("msp", "Minneapolis",      "US",  44.98,  -93.27),
+    ("muc", "Munich",           "DE",  48.14,   11.58),
+    ("mxp", "Milan",            "IT",  45.46,    9.19),
+    ("nbo", "Nairobi",          "KE",  -1.29,   36.82),
+    ("nrt", "Tokyo",            "JP",  35.68,  139.69),
+    ("nyc", "New York",         "US",  40.71,  -74.01),
+    ("ord", "Chicago",          "US",  41.88,  -87.63),
+    ("osa", "Osaka",            "JP",  34.69,  135.50),
+    ("osl", "Oslo",             "NO",  59.91,   10.75),
+    ("otp", "Bucharest",        "RO",  44.43,   26.10),
+    ("pao", "Palo Alto",        "US",  37.44, -122.14),
+    ("par", "Paris",            "FR",  48.86,    2.35),
+    ("pdx", "Portland",         "US",  45.52, -122.68),
+    ("pek", "Beijing",          "CN",  39.90,  116.41),
+    ("per", "Perth",            "AU", -31.95,  115.86),
+    ("phl", "Philadelphia",     "US",  39.95,  -75.17),
+    ("phx", "Phoenix",          "US",  33.45, -112.07),
+    ("prg", "Prague",           "CZ",  50.08,   14.44),
+    ("pvg", "Shanghai",         "CN",  31.23,  121.47),
+    ("scl", "Santiago",         "CL", -33.45,  -70.67),
+    ("sea", "Seattle",          "US",  47.61, -122.33),
+    ("sel", "Seoul",            "KR",  37.57,  126.98),
+    ("sfo", "San Francisco",    "US",  37.77, -122.42),
+    ("sin", "Singapore",        "SG",   1.29,  103.85),
+    ("sjc", "San Jose",         "US",  37.34, -121.89),
+    ("slc", "Salt Lake City",   "US",  40.76, -111.89),
+    ("sof", "Sofia",            "BG",  42.70,   23.32),
+    ("stl", "St. Louis",        "US",  38.63,  -90.20),
+    ("sto", "Stockholm",        "SE",  59.33,   18.07),
+    ("svo", "Moscow",           "RU",  55.76,   37.62),
+    ("syd", "Sydney",           "AU", -33.87,  151.21),
+    ("tlv", "Tel Aviv",         "IL",  32.09,   34.78),
+    ("tor", "Toronto",          "CA",  43.65,  -79.38),
+    ("tpe", "Taipei",           "TW",  25.03,  121.57),
+    ("tyo", "Tokyo",            "JP",  35.68,  139.69),
+    ("vie", "Vienna",           "AT",  48.21,   16.37),
+    ("waw", "Warsaw",           "PL",  52.23,   21.01),
+    ("wdc", "Washington",       "US",  38.91,  -77.04),
+    ("yul", "Montreal",         "CA",  45.50,  -73.57),
+    ("yvr", "Vancouver",        "CA",  49.28, -123.12),
+    ("yyz", "Toronto",          "CA",  43.65,  -79.38),
+    ("zrh", "Zurich",           "CH",  47.38,    8.54),
+];
+
+// Infers where the routers are from their names: the rules given by the user
+// first, regular expressions capturing a code in their group named "code" or
+// in their first group, then the codes found in the labels of the names.
+// The codes are looked up in the built-in table and in the user's ones.
+//
+pub struct Hints {
+    rules: Vec<Regex>,
+    codes: HashMap<String, Location>,
+}
+
+// A location inferred from the name of a router and the code it was found
+// by.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hint {
+    pub code: String,
+    pub location: Location,
+}
+
+impl Default for Hints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hints {
+    pub fn new() -> Self {
+        let codes = SITES.iter()
+            .map(|(code, city, country_code, latitude, longitude)| {
+                (code.to_string(), site(city, country_code, *latitude, *longitude))
+            })
+            .collect();
+
+        Self { rules: vec![], codes }
+    }
+
+    pub fn add_rule(&mut self, pattern: &str) -> io::Result<()> {
+        let rule = Regex::new(pattern)
+            .map_err(|error| invalid(&format!("invalid rule {}: {}", pattern, error)))?;
+
+        if rule.captures_len() < 2 {
+            return Err(invalid(&format!("rule {} captures no code", pattern)))
+        }
+
+        self.rules.push(rule);
+
+        Ok(())
+    }
+
+    // Reads CSV lines of a code, its city, its country code, its latitude and
+    // its longitude, the codes being case-insensitive. The codes of the
+    // built-in table are replaced. Any other line, a header or a comment, is
+    // skipped.
+    //
+    pub fn read_codes<R: Read>(&mut self, mut input: R) -> io::Result<()> {
+        let mut text = String::new();
+
+        input.read_to_string(&mut text)
+            .map_err(|_| invalid("the code table is not a text file"))?;
+
+        let mut count = 0;
+
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+
+            let (code, city, country_code, latitude, longitude) = match fields[..] {
+                [code, city, country_code, latitude, longitude] => {
+                    match (latitude.parse(), longitude.parse()) {
+                        (Ok(latitude), Ok(longitude)) => {
+                            (code, city, country_code, latitude, longitude)
+                        }
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            self.codes.insert(
+                code.to_lowercase(),
+                site(city, country_code, latitude, longitude),
+            );
+
+            count += 1;
+        }
+
+        match count {
+            0 => Err(invalid("no codes found")),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn locate(&self, name: &str) -> Option<Hint> {
+        let name = name.trim_end_matches('.').to_lowercase();
+
+        let found = self.rules.iter()
+            .filter_map(|rule| {
+                let captures = rule.captures(&name)?;
+
+                captures.name("code").or_else(|| captures.get(1))
+            })
+            .map(|code| code.as_str().to_string())
+            .find(|code| self.codes.contains_key(code))
+            .or_else(|| self.find_code(&name))?;
+
+        let location = self.codes.get(&found)?.clone();
+
+        Some(Hint { code: found, location })
+    }
+
+    // The labels of the name are split at dashes and underscores, and the
+    // digits at the ends of the pieces are dropped, so that "ae1.cr1.fra2"
+    // has "fra". The domain of the operator is not looked into, and the
+    // pieces are looked up from its side, where the operators put the sites.
+    //
+    fn find_code(&self, name: &str) -> Option<String> {
+        let labels: Vec<&str> = name.split('.').collect();
+        let host = labels.len().checked_sub(2).filter(|count| *count > 0)?;
+
+        labels[..host].iter().rev()
+            .flat_map(|label| label.split(['-', '_']).rev())
+            .map(|piece| piece.trim_end_matches(|c: char| c.is_ascii_digit()))
+            .filter(|piece| {
+                piece.len() >= 3 && piece.chars().all(|c| c.is_ascii_lowercase())
+            })
+            .find(|piece| self.codes.contains_key(*piece))
+            .map(str::to_string)
+    }
+}
+
+// Whether light in fiber could go the distance and back within the RTT.
+//
+pub fn is_reachable(distance: f64, rtt: Duration) -> bool {
+    distance <= rtt.as_secs_f64() * 1000.0 / 2.0 * FIBER_KM_PER_MS
+}
+
+fn site(city: &str, country_code: &str, latitude: f64, longitude: f64) -> Location {
+    Location {
+        country_code: Some(country_code.to_string()),
+        country: None,
+        city: Some(city.to_string()),
+        latitude: Some(latitude),
+        longitude: Some(longitude),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("hostname hints: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn city(hints: &Hints, name: &str) -> Option<String> {
+        hints.locate(name).and_then(|hint| hint.location.city)
+    }
+
+    #[test]
+    fn codes() {
+        let mut codes: Vec<&str> = SITES.iter()
+            .map(|(code, ..)| *code)
+            .collect();
+
+        codes.sort_unstable();
+        codes.dedup();
+
+        assert_eq!(codes.len(), SITES.len());
+    }
+
+    #[test]
+    fn built_in_rules() {
+        let hints = Hints::new();
+
+        assert_eq!(city(&hints, "ae1.cr1.fra2.example.net"), Some("Frankfurt".into()));
+        assert_eq!(city(&hints, "xe-0-0-1.lhr01-edge.example.net."), Some("London".into()));
+        assert_eq!(city(&hints, "be2.ams.nyc1.example.net"), Some("New York".into()));
+        assert_eq!(city(&hints, "AE1.CR1.SEA1.EXAMPLE.NET"), Some("Seattle".into()));
+        assert_eq!(hints.locate("fra.example.net").unwrap().code, "fra");
+        assert_eq!(city(&hints, "sea.net"), None);
+        assert_eq!(city(&hints, "core1.research.example.net"), None);
+    }
+
+    #[test]
+    fn user_rules() {
+        let mut hints = Hints::new();
+
+        hints.add_rule(r"^[a-z]+\d*\.(?P<code>[a-z]{6})\d*\.example\.com$").unwrap();
+        hints.read_codes("code,city,country,lat,lon\nfrnkge,Frankfurt,DE,50.11,8.68\n"
+            .as_bytes()).unwrap();
+
+        let hint = hints.locate("r20.frnkge13.example.com").unwrap();
+
+        assert_eq!(hint.code, "frnkge");
+        assert_eq!(hint.location.place().unwrap(), "Frankfurt, DE");
+
+        assert!(hints.add_rule("[a-z").is_err());
+        assert!(hints.add_rule("[a-z]+").is_err());
+        assert!(hints.read_codes("code,city\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn speed_of_light() {
+        let frankfurt = Hints::new().locate("fra.example.net").unwrap().location;
+        let new_york = Hints::new().locate("nyc.example.net").unwrap().location;
+        let distance = frankfurt.distance(&new_york).unwrap();
+
+        assert!((6150.0..6250.0).contains(&distance), "{}", distance);
+        assert!(!is_reachable(distance, Duration::from_millis(20)));
+        assert!(is_reachable(distance, Duration::from_millis(80)));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{error::Error, sockaddr_inx::SockaddrInx};
+use std::{
+    ffi::CStr,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket},
+    os::raw::c_char,
+};
+
+// Only IPv4 is supported for now, so the first IPv4 address of the host is
+// returned.
+//
+pub fn resolve(host: &str) -> Result<IpAddr, Error> {
+    if let Ok(ip_addr) = host.parse::<IpAddr>() {
+        return Ok(ip_addr)
+    }
+
+    (host, 0).to_socket_addrs()
+        .map_err(|_| Error::BadHost(host.to_string()))?
+        .map(|socket_addr| socket_addr.ip())
+        .find(|ip_addr| ip_addr.is_ipv4())
+        .ok_or_else(|| Error::BadHost(host.to_string()))
+}
+
+// All the addresses of the host, for checking the names found by `reverse`.
+//
+pub fn addresses(host: &str) -> Vec<IpAddr> {
+    match (host, 0).to_socket_addrs() {
+        Ok(socket_addrs) => {
+            socket_addrs.map(|socket_addr| socket_addr.ip()).collect()
+        }
+        Err(_) => vec![],
+    }
+}
+
+// Name of the host with the address (its PTR record), if it has one. Blocks
+// for as long as the system resolver takes.
+//
+pub fn reverse(address: IpAddr) -> Option<String> {
+    let sockaddr_inx = SockaddrInx::from_ip_addr(address);
+    let mut host = [0 as c_char; libc::NI_MAXHOST as usize];
+
+    let result = unsafe {
+        libc::getnameinfo(
+            sockaddr_inx.sockaddr_ptr(),
+            sockaddr_inx.socklen(),
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+
+    if result != 0 {
+        return None
+    }
+
+    let name = unsafe { CStr::from_ptr(host.as_ptr()) };
+
+    Some(name.to_string_lossy().into_owned())
+}
+
+// Address of the local interface the packets to the target are sent from.
+// Connecting a datagram socket only selects the route, nothing is sent.
+//
+pub fn source_address(target: IpAddr) -> Option<IpAddr> {
+    let unspecified = match target {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+
+    let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+
+    socket.connect((target, 33434)).ok()?;
+
+    Some(socket.local_addr().ok()?.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn resolve_ip_addr() {
+        assert_eq!(
+            resolve("192.0.2.1"),
+            Ok(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))),
+        );
+    }
+
+    #[test]
+    fn source_address_of_loopback() {
+        assert_eq!(
+            source_address(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        );
+    }
+
+    #[test]
+    fn addresses_of_ip_addr() {
+        assert_eq!(
+            addresses("192.0.2.1"),
+            vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))],
+        );
+    }
+
+    #[test]
+    fn resolve_bad_host() {
+        assert_eq!(
+            resolve("bad host"),
+            Err(Error::BadHost("bad host".to_string())),
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    annotations::Annotations,
+    options::Options,
+    timestamp,
+};
+use rustraceroute::{
+    geoip::Location,
+    hints::Hint,
+    mpls,
+    Error,
+    Hop,
+    ProbeResult,
+    ProbeStatus,
+    Response,
+    Trace,
+};
+use serde_json::{json, Value};
+use std::{net::IpAddr, time::{Duration, SystemTime}};
+
+// Incremented on every incompatible change of the document structure. Adding
+// new keys is not considered one.
+//
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub fn trace(
+    options: &Options,
+    address: IpAddr,
+    trace: &Trace,
+    annotations: &Annotations,
+) -> Value {
+    let mut result = metadata(options, address, trace.started);
+
+    result["reached"] = trace.reached().into();
+    result["warnings"] = annotations.trace_warnings(trace).into();
+    result["tunnels"] = tunnels(trace);
+    result["hops"] = trace.hops.iter()
+        .map(|hop| self::hop(hop, annotations))
+        .collect();
+
+    result
+}
+
+pub fn metadata(
+    options: &Options,
+    address: IpAddr,
+    started: SystemTime,
+) -> Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "target": options.host(),
+        "address": address.to_string(),
+        "protocol": "icmp",
+        "started": timestamp::rfc3339(started),
+        "options": self::options(options),
+    })
+}
+
+pub fn options(options: &Options) -> Value {
+    json!({
+        "first_ttl": options.first_ttl,
+        "max_ttl": options.max_ttl,
+        "queries": options.nqueries,
+        "wait": options.waittime,
+    })
+}
+
+pub fn hop(hop: &Hop, annotations: &Annotations) -> Value {
+    json!({
+        "ttl": hop.ttl,
+        "probes": hop.probes.iter()
+            .map(|probe| self::probe(probe, annotations))
+            .collect::<Value>(),
+    })
+}
+
+// Every probe has the same keys, those which do not apply are null. So are the
+// name, the origin ASes and the location of the responder if they are not
+// known. The tags are the kinds of special address space the responder is in.
+//
+pub fn probe(probe: &ProbeResult, annotations: &Annotations) -> Value {
+    let mut result = json!({
+        "sequence": probe.sequence,
+        "sent": timestamp::rfc3339(probe.sent),
+        "status": "timeout",
+        "responder": null,
+        "hostname": null,
+        "hostname_confirmed": null,
+        "asn": null,
+        "prefix": null,
+        "location": null,
+        "hint": null,
+        "tags": [],
+        "rtt_ms": null,
+        "icmp_type": null,
+        "icmp_code": null,
+        "reply_ttl": null,
+        "initial_ttl": null,
+        "return_hops": null,
+        "quoted_ttl": null,
+        "mpls": [],
+        "error": null,
+        "annotations": [],
+    });
+
+    match &probe.status {
+        ProbeStatus::Reply { response, rtt } => {
+            result["status"] = "reply".into();
+            set_reply(&mut result, response, *rtt);
+
+            if let Some(name) = annotations.names.wait(response.source) {
+                result["hostname"] = name.name.into();
+                result["hostname_confirmed"] = name.confirmed.into();
+            }
+
+            if let Some(origin) = annotations.origin(response.source) {
+                result["asn"] = origin.asns.into();
+                result["prefix"] = origin.prefix.to_string().into();
+            }
+
+            if let Some(location) = annotations.location(response.source) {
+                result["location"] = self::location(&location);
+            }
+
+            if let Some(hint) = annotations.hint(response.source) {
+                result["hint"] = self::hint(&hint);
+            }
+
+            result["tags"] = annotations.tags(response.source).into();
+        }
+        ProbeStatus::Timeout => {}
+        ProbeStatus::Error(error) => {
+            result["status"] = "error".into();
+            set_error(&mut result, error);
+        }
+    }
+
+    result
+}
+
+// Whatever the database does not know is null.
+//
+pub fn location(location: &Location) -> Value {
+    json!({
+        "country_code": location.country_code,
+        "country": location.country,
+        "city": location.city,
+        "latitude": location.latitude,
+        "longitude": location.longitude,
+    })
+}
+
+// The code found in the name and the location it stands for.
+//
+pub fn hint(hint: &Hint) -> Value {
+    json!({
+        "code": hint.code,
+        "location": location(&hint.location),
+    })
+}
+
+// The MPLS tunnels found on the path, with the TTLs of their ends and of
+// the routers inside which have replied.
+//
+pub fn tunnels(trace: &Trace) -> Value {
+    mpls::tunnels(trace).iter()
+        .map(|tunnel| json!({
+            "kind": tunnel.kind.name(),
+            "ingress": tunnel.ingress,
+            "egress": tunnel.egress,
+            "routers": tunnel.routers,
+            "hidden": tunnel.hidden,
+        }))
+        .collect()
+}
+
+pub fn set_reply(result: &mut Value, response: &Response, rtt: Duration) {
+    result["responder"] = response.source.to_string().into();
+    result["rtt_ms"] = milliseconds(rtt).into();
+    result["icmp_type"] = response.type_.into();
+    result["icmp_code"] = response.code.into();
+    result["reply_ttl"] = response.reply_ttl.into();
+    result["initial_ttl"] = response.initial_ttl().into();
+    result["return_hops"] = response.return_hops().into();
+    result["quoted_ttl"] = response.quoted_ttl.into();
+    result["mpls"] = response.mpls_labels().iter()
+        .map(|label| json!({
+            "label": label.label,
+            "exp": label.exp,
+            "bottom": label.bottom,
+            "ttl": label.ttl,
+        }))
+        .collect();
+    result["annotations"] = response.annotation().into_iter().collect();
+}
+
+pub fn set_error(result: &mut Value, error: &Error) {
+    result["error"] = error.to_string().into();
+    result["annotations"] = error.annotation().into_iter().collect();
+}
+
+pub fn milliseconds(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::{ROUTER, TARGET}, names::Names};
+    use rustraceroute::{asn::AsDatabase, hints::Hints, response::TIME_EXCEEDED};
+    use clap::Clap;
+    use std::time::UNIX_EPOCH;
+
+    fn options() -> Options {
+        Options::parse_from(["rustraceroute", "--json", "example.com"])
+    }
+
+    fn result(status: ProbeStatus) -> ProbeResult {
+        crate::fixtures::probe(2, status)
+    }
+
+    fn reply() -> ProbeResult {
+        result(ProbeStatus::Reply {
+            response: Response {
+                source: ROUTER,
+                reply_ttl: 254,
+                type_: TIME_EXCEEDED,
+                ..Default::default()
+            },
+            rtt: Duration::from_micros(12_345),
+        })
+    }
+
+    #[test]
+    fn probe_reply() {
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+
+        let annotations = Annotations::from(
+            Names::fixed(&[(ROUTER, "ae1.fra2.example.net")]),
+        ).with_asns(asns).with_hints(Hints::new());
+
+        assert_eq!(probe(&reply(), &annotations), json!({
+            "sequence": 2,
+            "sent": "1970-01-01T00:00:00.000000Z",
+            "status": "reply",
+            "responder": "192.0.2.1",
+            "hostname": "ae1.fra2.example.net",
+            "hostname_confirmed": null,
+            "asn": [64500],
+            "prefix": "192.0.2.0/24",
+            "location": null,
+            "hint": {
+                "code": "fra",
+                "location": {
+                    "country_code": "DE",
+                    "country": null,
+                    "city": "Frankfurt",
+                    "latitude": 50.11,
+                    "longitude": 8.68,
+                },
+            },
+            "tags": ["documentation"],
+            "rtt_ms": 12.345,
+            "icmp_type": 11,
+            "icmp_code": 0,
+            "reply_ttl": 254,
+            "initial_ttl": 255,
+            "return_hops": 2,
+            "quoted_ttl": null,
+            "mpls": [],
+            "error": null,
+            "annotations": [],
+        }));
+    }
+
+    #[test]
+    fn probe_timeout() {
+        let value = probe(
+            &result(ProbeStatus::Timeout),
+            &Names::disabled().into(),
+        );
+
+        assert_eq!(value["status"], "timeout");
+        assert_eq!(value["responder"], Value::Null);
+        assert_eq!(value["rtt_ms"], Value::Null);
+        assert_eq!(value["asn"], Value::Null);
+    }
+
+    #[test]
+    fn probe_error() {
+        let value = probe(
+            &result(ProbeStatus::Error(Error::NoRoute)),
+            &Names::disabled().into(),
+        );
+
+        assert_eq!(value["status"], "error");
+        assert_eq!(value["error"], "no route to host");
+        assert_eq!(value["annotations"], json!(["!H"]));
+    }
+
+    #[test]
+    fn location_keys() {
+        let value = location(&Location {
+            country_code: Some("US".to_string()),
+            city: Some("Chicago".to_string()),
+            latitude: Some(41.85),
+            longitude: Some(-87.65),
+            ..Default::default()
+        });
+
+        assert_eq!(value, json!({
+            "country_code": "US",
+            "country": null,
+            "city": "Chicago",
+            "latitude": 41.85,
+            "longitude": -87.65,
+        }));
+    }
+
+    #[test]
+    fn document() {
+        let value = trace(&options(), TARGET, &Trace {
+            target: TARGET,
+            started: UNIX_EPOCH,
+            hops: vec![Hop { ttl: 1, probes: vec![reply()] }],
+        }, &Names::disabled().into());
+
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["target"], "example.com");
+        assert_eq!(value["address"], "198.51.100.1");
+        assert_eq!(value["protocol"], "icmp");
+        assert_eq!(value["options"]["max_ttl"], 30);
+        assert_eq!(value["reached"], false);
+        assert_eq!(value["warnings"], json!([]));
+        assert_eq!(value["tunnels"], json!([]));
+        assert_eq!(value["hops"][0]["ttl"], 1);
+        assert_eq!(value["hops"][0]["probes"][0]["responder"], "192.0.2.1");
+        assert_eq!(value["hops"][0]["probes"][0]["hostname"], Value::Null);
+    }
+}
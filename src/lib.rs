@@ -0,0 +1,48 @@
+mod checksum;
+
+// The tests share their fixtures with those of the program, which refer to
+// the library by its name.
+//
+#[cfg(test)]
+extern crate self as rustraceroute;
+
+#[cfg(test)]
+mod fixtures;
+
+#[cfg(feature = "tokio")]
+pub mod async_socket;
+
+pub mod alias;
+pub mod asn;
+pub mod error;
+pub mod event;
+pub mod extension;
+pub mod geoip;
+pub mod hints;
+pub mod host;
+pub mod mpls;
+pub mod pcapng;
+pub mod privileges;
+pub mod ranges;
+pub mod replay;
+pub mod request;
+pub mod rpki;
+pub mod response;
+pub mod sockaddr_inx;
+pub mod socket;
+pub mod special;
+pub mod trace;
+pub mod tracer;
+pub mod transport;
+pub mod trie;
+pub mod warts;
+
+#[cfg(feature = "tokio")]
+pub use async_socket::AsyncSocket;
+pub use error::Error;
+pub use event::{Event, Observer, Packet};
+pub use response::Response;
+pub use socket::Socket;
+pub use trace::{Hop, ProbeResult, ProbeStatus, Trace};
+pub use tracer::{ProbeMethod, Tracer};
+pub use transport::Transport;
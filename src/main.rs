@@ -1,176 +1,466 @@
-mod checksum;
+mod annotations;
+mod atlas;
+mod background;
+mod csv;
+mod dot;
+#[cfg(test)]
+mod fixtures;
+mod json;
+mod names;
+mod ndjson;
 mod options;
-mod request;
-mod response;
-mod sockaddr_inx;
-
+mod report;
+mod stats;
+mod terminal;
+mod text;
+mod timestamp;
+mod tui;
+
+use annotations::Annotations;
 use clap::Clap;
-use libc;
-use options::Options;
-use request::Request;
-use response::Response;
-use sockaddr_inx::SockaddrInx;
-use std::{convert::TryInto, net::IpAddr, time::{Duration, SystemTime}};
+use csv::Csv;
+use dot::Graph;
+use names::Names;
+use ndjson::NdJson;
+use options::{Command, Format, Options};
+use report::Report;
+use rustraceroute::{
+    alias::Resolver,
+    asn::AsDatabase,
+    error::USAGE_EXIT_CODE,
+    event::{self, Tee},
+    geoip::GeoDatabase,
+    hints::Hints,
+    host,
+    pcapng,
+    privileges,
+    ranges::RangeDatabase,
+    replay::Replay,
+    rpki::VrpTable,
+    warts,
+    Error,
+    Observer,
+    Socket,
+    Trace,
+    Tracer,
+    Transport,
+};
+use std::{
+    env,
+    fs::File,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+use text::Text;
+use tui::Tui;
 
 fn main() {
-    let options = Options::parse();
-
-    println!("{:?}", options);
+    // The raw socket is the only thing which requires privileges, so it is
+    // opened before anything else and the privileges are dropped right after
+    // that. The rest of the program, including the command-line parser, runs
+    // unprivileged.
+    //
+    let socket = Socket::open();
+
+    if let Err(error) = privileges::drop_privileges() {
+        exit_with_error(error);
+    }
 
-    let host = match options.host.parse::<IpAddr>() {
-        Ok(ip_addr) => ip_addr,
-        Err(_) => unimplemented!(),
+    // Help and version are printed by the parser itself, the usage errors
+    // exit with the code of their own.
+    //
+    let options = match Options::try_parse() {
+        Ok(options) => options,
+        Err(error) if error.use_stderr() => {
+            eprint!("{}", error);
+            process::exit(USAGE_EXIT_CODE)
+        }
+        Err(error) => error.exit(),
     };
 
-    println!("{:?}", host);
-
-    let socket = unsafe {
-        libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP)
+    let annotations = match annotations(&options) {
+        Ok(annotations) => annotations,
+        Err(error) => exit_with_error(error),
     };
 
-    let mut reached_host = false;
-    let mut current_ttl = options.first_ttl;
-
-    while !reached_host && current_ttl <= options.max_ttl {
-        let current_address = iterate_ttl(
-            &options,
-            &host,
-            socket,
-            current_ttl,
-        );
-
-        if let Some(ip_addr) = current_address {
-            println!("{} {}", current_ttl, ip_addr);
+    let result = match (&options.command, &options.read) {
+        (Some(Command::Replay { file }), _) => {
+            replay(&options, &annotations, file)
         }
-        else {
-            println!("{} ***", current_ttl);
+        (None, Some(path)) => read(&options, &annotations, path, socket),
+        (None, None) => {
+            socket.and_then(|socket| run(&options, &annotations, &socket))
         }
+    };
+
+    // The cache only saves time, failing to write it is not worth reporting.
+    //
+    let _ = annotations.names.save();
 
-        current_ttl += 1;
+    if let Err(error) = result {
+        exit_with_error(error);
     }
 }
 
-fn iterate_ttl(
-    options: &Options,
-    host: &IpAddr,
-    socket: libc::c_int,
-    current_ttl: u8,
-) -> Option<IpAddr> {
-    for sequence in 0..options.nqueries {
-        let request = Request::new(0, sequence);
+fn exit_with_error(error: Error) -> ! {
+    eprintln!("rustraceroute: {}", error);
+    process::exit(error.exit_code())
+}
+
+fn tracer(options: &Options, host: IpAddr) -> Tracer {
+    Tracer::new(host)
+        .first_ttl(options.first_ttl)
+        .max_ttl(options.max_ttl)
+        .queries(options.nqueries)
+        .wait(Duration::from_secs(options.waittime.into()))
+}
 
-        send_request(socket, current_ttl, &host, &request);
+// The origin ASes and the locations of the hops are looked up in the files
+// given, if any.
+//
+fn annotations(options: &Options) -> Result<Annotations, Error> {
+    let mut annotations = Annotations::from(names(options)?);
 
-        set_timeout(socket, 2, 0);
+    if let Some(path) = &options.asn_db {
+        annotations = annotations
+            .with_asns(read_file("--asn-db", path, AsDatabase::read)?);
+    }
 
-        if let Some(response) = recv_response1(socket, &request) {
-            if response.type_ != 11 || response.code != 0 {
-                continue
-            }
+    if let Some(path) = &options.geoip {
+        annotations = annotations
+            .with_geo(read_file("--geoip", path, GeoDatabase::read)?);
+    }
+
+    if !options.ranges.is_empty() {
+        let mut ranges = RangeDatabase::default();
 
-            return Some(response.source)
+        for path in &options.ranges {
+            read_file("--ranges", path, |file| ranges.add(file))?;
         }
+
+        annotations = annotations.with_ranges(ranges);
     }
 
-    return None
+    if let Some(path) = &options.vrps {
+        annotations = annotations
+            .with_vrps(read_file("--vrps", path, VrpTable::read)?);
+    }
+
+    if options.hints {
+        let mut hints = Hints::new();
+
+        for rule in &options.hint_rules {
+            hints.add_rule(rule)?;
+        }
+
+        if let Some(path) = &options.hint_codes {
+            read_file("--hint-codes", path, |file| hints.read_codes(file))?;
+        }
+
+        annotations = annotations.with_hints(hints);
+    }
+
+    Ok(annotations)
 }
 
-fn send_request(
-    socket: libc::c_int,
-    current_ttl: u8,
-    host: &IpAddr,
-    request: &Request,
-) {
-    assert_eq!(0, unsafe { libc::setsockopt(
-        socket,
-        libc::IPPROTO_IP,
-        libc::IP_TTL,
-        &current_ttl as *const u8 as *const libc::c_void,
-        std::mem::size_of::<u8>().try_into().unwrap(),
-    ) });
+// Reads the file given with the option. The errors tell which file it was and
+// what it was given with.
+//
+fn read_file<T, F>(option: &str, path: &Path, read: F) -> Result<T, Error>
+where
+    F: FnOnce(File) -> io::Result<T>,
+{
+    File::open(path).and_then(read)
+        .map_err(|error| file_error(option, path, error))
+}
 
-    let traffic_class: u32 = 0;
+fn file_error(option: &str, path: &Path, error: io::Error) -> Error {
+    let message = format!("{} {}: {}", option, path.display(), error);
 
-    assert_eq!(0, unsafe { libc::setsockopt(
-        socket,
-        libc::IPPROTO_IP,
-        libc::IP_TOS,
-        &traffic_class as *const u32 as *const libc::c_void,
-        std::mem::size_of::<i32>().try_into().unwrap(),
-    ) });
+    match Error::from(error) {
+        Error::BadInput(_) => Error::BadInput(message),
+        _ => Error::Io(message),
+    }
+}
+
+// The names of the hops looked up in one run are cached in the user's cache
+// directory for the next ones.
+//
+fn names(options: &Options) -> Result<Names, Error> {
+    if options.numeric {
+        return Ok(Names::disabled())
+    }
 
-    let message = request.to_vec();
+    let timeout = Duration::try_from_secs_f64(options.dns_timeout)
+        .map_err(|_| Error::BadInput(format!(
+            "invalid DNS timeout {}",
+            options.dns_timeout,
+        )))?;
 
-    let sockaddr_inx = SockaddrInx::from_ip_addr(*host);
+    let cache = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|directory| directory.join("rustraceroute").join("names"));
 
-    assert_ne!(-1, unsafe { libc::sendto(
-        socket,
-        message.as_ptr() as *const libc::c_void,
-        message.len(),
-        0,
-        sockaddr_inx.sockaddr_ptr(),
-        sockaddr_inx.socklen(),
-    ) });
+    Ok(Names::new(options.confirm_names, timeout, cache))
 }
 
-fn set_timeout(socket: libc::c_int, sec: i64, usec: i64) {
-    let timeval = libc::timeval { tv_sec: sec, tv_usec: usec };
+fn run(
+    options: &Options,
+    annotations: &Annotations,
+    socket: &Socket,
+) -> Result<(), Error> {
+    let host = host::resolve(options.host())?;
+    let tracer = tracer(options, host);
+
+    if options.tui {
+        return Tui::new(options, host, annotations.clone()).run(&tracer, socket)
+    }
+
+    if options.report {
+        return Report::new(options, host, annotations.clone()).run(&tracer, socket)
+    }
 
-    assert_eq!(0, unsafe { libc::setsockopt(
+    trace(
+        options,
+        annotations,
+        &tracer,
         socket,
-        libc::SOL_SOCKET,
-        libc::SO_RCVTIMEO,
-        &timeval as *const libc::timeval as *const libc::c_void,
-        std::mem::size_of::<libc::timeval>().try_into().unwrap(),
-    ) });
+        host,
+        host::source_address(host),
+    )
 }
 
-fn recv_response1(socket: libc::c_int, request: &Request) -> Option<Response> {
-    let time_limit = SystemTime::now() + Duration::new(2, 0);
+// Runs the trace on the captured packets as if it was happening. The target
+// and the parameters of the trace are taken from the capture.
+//
+fn replay(
+    options: &Options,
+    annotations: &Annotations,
+    path: &Path,
+) -> Result<(), Error> {
+    let replay = Replay::new(read_file("replay", path, pcapng::read)?)?;
+
+    let options = Options {
+        host: Some(replay.target.to_string()),
+        first_ttl: replay.first_ttl,
+        max_ttl: replay.max_ttl,
+        nqueries: replay.queries,
+        ..options.clone()
+    };
+
+    let tracer = replay.tracer()
+        .wait(Duration::from_secs(options.waittime.into()));
+
+    trace(&options, annotations, &tracer, &replay, replay.target, None)
+}
+
+// Packets are captured along the way if asked to.
+//
+fn trace<T: Transport + ?Sized>(
+    options: &Options,
+    annotations: &Annotations,
+    tracer: &Tracer,
+    transport: &T,
+    host: IpAddr,
+    source: Option<IpAddr>,
+) -> Result<(), Error> {
+    let mut capture = match &options.pcap {
+        None => None,
+        Some(path) => Some(
+            File::create(path)
+                .and_then(|file| pcapng::Writer::new(file, source))
+                .map_err(|error| file_error("--pcap", path, error))?,
+        ),
+    };
 
-    while SystemTime::now() < time_limit {
-        if let Some(tmp_response) = recv_response2(socket) {
-            if tmp_response.does_match_request(request) {
-                return Some(tmp_response)
+    render(options, annotations, tracer, host, source, |observer| {
+        match &mut capture {
+            None => tracer.trace_with(transport, observer),
+            Some(capture) => {
+                tracer.trace_with(transport, &mut Tee(observer, capture))
             }
         }
+    })?;
+
+    if let Some(capture) = capture {
+        capture.finish()?;
     }
 
-    return None
+    Ok(())
 }
 
-fn recv_response2(socket: libc::c_int) -> Option<Response> {
-    let response_body_data: [u8; 1024] = [0; 1024];
+// Prints the traces read from the file as if they were happening. Their
+// targets stand in for the host names, which are not stored. The socket is
+// only needed to probe the interfaces for aliases.
+//
+fn read(
+    options: &Options,
+    annotations: &Annotations,
+    path: &Path,
+    socket: Result<Socket, Error>,
+) -> Result<(), Error> {
+    let traces = read_file("--read", path, warts::read)?;
 
-    let mut response_sockaddr_data = libc::sockaddr {
-        sa_family: 0,
-        sa_data: [0i8; 14],
-    };
+    if options.format() == Format::Warts {
+        let mut writer = warts::Writer::new(io::stdout(), SystemTime::now())?;
 
-    let mut response_sockaddr_size: u32 =
-        std::mem::size_of::<libc::sockaddr>().try_into().unwrap();
+        for trace in &traces {
+            writer.write(&tracer(options, trace.target), None, trace)?;
+        }
 
-    let response_body_size: isize = unsafe { libc::recvfrom(
-        socket,
-        response_body_data.as_ptr() as *mut libc::c_void,
-        std::mem::size_of::<[u8; 1024]>(),
-        0,
-        &mut response_sockaddr_data,
-        &mut response_sockaddr_size,
-    ) };
-
-    let response_sockaddr_inx = SockaddrInx::from_sockaddr(
-        response_sockaddr_data,
-    );
-
-    if response_body_size < 0 { None } else {
-        match &response_sockaddr_inx {
-            None => None,
-            Some(response_sockaddr_inx) => Response::parse(
-                &response_sockaddr_inx,
-                &response_body_data
-                    [0..(response_body_size as usize)],
-            ),
+        writer.finish(SystemTime::now())?;
+
+        return Ok(())
+    }
+
+    // All the traces are merged into a single graph.
+    //
+    if options.format() == Format::Dot {
+        let routers = match options.aliases {
+            true => Resolver::new()
+                .wait(Duration::from_secs(options.waittime.into()))
+                .resolve(&socket?, &traces)?,
+            false => vec![],
+        };
+
+        let mut graph = Graph::new(annotations.clone()).with_routers(&routers);
+
+        for trace in &traces {
+            graph.add(trace);
+        }
+
+        print!("{}", graph);
+
+        return Ok(())
+    }
+
+    for trace in traces {
+        let options = Options {
+            host: Some(trace.target.to_string()),
+            ..options.clone()
+        };
+
+        let tracer = tracer(&options, trace.target);
+
+        render(&options, annotations, &tracer, trace.target, None, |observer| {
+            event::replay(&trace, observer);
+            Ok(trace.clone())
+        })?;
+    }
+
+    Ok(())
+}
+
+// Prints the trace in the requested format. The trace is either being done or
+// replayed, the progress is reported to the observer. The outputs printed as
+// the trace goes are rendered on a thread of their own, since they wait for
+// the names of the hops.
+//
+fn render<F>(
+    options: &Options,
+    annotations: &Annotations,
+    tracer: &Tracer,
+    host: IpAddr,
+    source: Option<IpAddr>,
+    trace: F,
+) -> Result<(), Error>
+where
+    F: FnOnce(&mut dyn Observer) -> Result<Trace, Error>,
+{
+    let trace = match options.format() {
+        Format::Text => {
+            println!(
+                "traceroute to {} ({}), {} hops max",
+                options.host(),
+                host,
+                options.max_ttl,
+            );
+
+            let mut text = Text::new(io::stdout(), annotations.clone())
+                .with_mpls(options.mpls);
+
+            background::observe(&mut text, trace)?
+        }
+        Format::Json => {
+            let names = &annotations.names;
+            let trace = trace(&mut |event: &_| names.prefetch(event))?;
+
+            println!("{}", json::trace(options, host, &trace, annotations));
+
+            trace
+        }
+        Format::Ndjson => {
+            let mut ndjson = NdJson::new(io::stdout(), annotations.clone());
+
+            // There is no point in tracing if the events can not be written.
+            //
+            ndjson.start(options, host)?;
+
+            background::observe(&mut ndjson, trace)?
+        }
+        Format::Csv | Format::Tsv => {
+            let separator = match options.format() {
+                Format::Csv => ',',
+                _ => '\t',
+            };
+
+            let mut csv = Csv::new(
+                io::stdout(),
+                separator,
+                options.host(),
+                annotations.clone(),
+            );
+
+            csv.start()?;
+
+            background::observe(&mut csv, trace)?
+        }
+        Format::Atlas => {
+            let trace = trace(&mut |_: &_| {})?;
+
+            println!(
+                "{}",
+                atlas::trace(options, host, source, &trace, SystemTime::now()),
+            );
+
+            trace
+        }
+        Format::Warts => {
+            let started = SystemTime::now();
+            let trace = trace(&mut |_: &_| {})?;
+
+            let mut writer = warts::Writer::new(io::stdout(), started)?;
+
+            writer.write(tracer, source, &trace)?;
+            writer.finish(SystemTime::now())?;
+
+            trace
+        }
+        Format::Dot => {
+            let mut graph = Graph::new(annotations.clone());
+
+            let names = &annotations.names;
+
+            let trace = trace(&mut |event: &_| names.prefetch(event))?;
+
+            graph.add(&trace);
+
+            print!("{}", graph);
+
+            trace
+        }
+    };
+
+    // The JSON documents and events carry the warnings themselves.
+    //
+    if !matches!(options.format(), Format::Json | Format::Ndjson) {
+        for warning in annotations.trace_warnings(&trace) {
+            eprintln!("rustraceroute: warning: {}", warning);
         }
     }
+
+    Ok(())
 }
@@ -0,0 +1,346 @@
+use crate::{
+    trace::{ProbeResult, Trace},
+    Response,
+};
+use std::convert::TryFrom;
+
+// Growth of the return path length over the forward one between two
+// consecutive hops from which an invisible tunnel is suspected.
+//
+const INVISIBLE_JUMP: i16 = 3;
+
+// Kinds of MPLS tunnels as Donnet et al. tell them apart in "Revealing MPLS
+// Tunnels Obscured from Traceroute" (ACM SIGCOMM CCR, 2012).
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    // The routers inside the tunnel propagate the TTL and quote the label
+    // stacks (RFC 4950).
+    //
+    Explicit,
+    // The routers inside propagate the TTL but do not quote the labels,
+    // they are told by the TTLs of the quoted requests, which the routers
+    // inside do not decrease.
+    //
+    Implicit,
+    // The routers inside do not propagate the TTL and are hidden, the
+    // egress quotes the label stack with what the hidden ones have left of
+    // the label TTL.
+    //
+    Opaque,
+    // The routers inside are hidden and nothing is quoted, the tunnel is
+    // only suspected from the path back being longer than the path there by
+    // more than at the hop before.
+    //
+    Invisible,
+}
+
+// A tunnel found on the path, with the TTLs of the hops at its ends and of
+// the routers inside it which have replied.
+//
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tunnel {
+    pub kind: Kind,
+    pub ingress: Option<u8>,
+    pub egress: Option<u8>,
+    pub routers: Vec<u8>,
+    // Estimate of the routers inside the tunnel which have not replied.
+    //
+    pub hidden: Option<u8>,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Explicit  => "explicit",
+            Self::Implicit  => "implicit",
+            Self::Opaque    => "opaque",
+            Self::Invisible => "invisible",
+        }
+    }
+}
+
+// What one reply tells about the tunnels.
+//
+enum Sign {
+    // Inside an explicit or an implicit tunnel, with the quoted TTL.
+    //
+    Inside(Kind, u8),
+    // At the egress of an opaque tunnel, with the routers hidden.
+    //
+    OpaqueEgress(u8),
+    Nothing,
+}
+
+// Finds the tunnels on the path from the first reply of each hop.
+//
+pub fn tunnels(trace: &Trace) -> Vec<Tunnel> {
+    let hops: Vec<(u8, &Response)> = trace.hops.iter()
+        .filter_map(|hop| {
+            Some((hop.ttl, hop.probes.iter().find_map(ProbeResult::response)?))
+        })
+        .collect();
+
+    let mut result: Vec<Tunnel> = vec![];
+    let mut previous: Option<(u8, &Response)> = None;
+
+    for (ttl, response) in hops.iter().copied() {
+        let follows = previous.is_some_and(|(previous, _)| follows(previous, ttl));
+
+        match sign(response) {
+            Sign::Inside(kind, quoted_ttl) => {
+                match result.last_mut() {
+                    Some(tunnel) if tunnel.kind == kind && is_open(tunnel, ttl) => {
+                        tunnel.routers.push(ttl);
+                    }
+                    _ => {
+                        close(&mut result, ttl);
+
+                        // The TTL of the request is not decreased inside the
+                        // tunnel, so the quoted one tells how far behind the
+                        // ingress is.
+                        //
+                        result.push(Tunnel {
+                            kind,
+                            ingress: ttl.checked_sub(quoted_ttl)
+                                .filter(|ingress| *ingress >= 1),
+                            egress: None,
+                            routers: vec![ttl],
+                            hidden: None,
+                        });
+                    }
+                }
+            }
+            Sign::OpaqueEgress(hidden) => {
+                close(&mut result, ttl);
+
+                result.push(Tunnel {
+                    kind: Kind::Opaque,
+                    ingress: previous.filter(|_| follows).map(|(ttl, _)| ttl),
+                    egress: Some(ttl),
+                    routers: vec![],
+                    hidden: Some(hidden),
+                });
+            }
+            Sign::Nothing => {
+                close(&mut result, ttl);
+
+                let jump = previous.filter(|_| follows)
+                    .filter(|(_, previous)| matches!(sign(previous), Sign::Nothing))
+                    .and_then(|(previous_ttl, previous)| {
+                        Some(shift(ttl, response)? - shift(previous_ttl, previous)?)
+                    })
+                    .filter(|jump| *jump >= INVISIBLE_JUMP);
+
+                if let Some(jump) = jump {
+                    result.push(Tunnel {
+                        kind: Kind::Invisible,
+                        ingress: previous.map(|(ttl, _)| ttl),
+                        egress: Some(ttl),
+                        routers: vec![],
+                        hidden: u8::try_from(jump).ok(),
+                    });
+                }
+            }
+        }
+
+        previous = Some((ttl, response));
+    }
+
+    result
+}
+
+fn sign(response: &Response) -> Sign {
+    if !response.is_time_exceeded() {
+        return Sign::Nothing
+    }
+
+    let quoted_ttl = response.quoted_ttl.unwrap_or(1);
+
+    match response.mpls_labels().first() {
+        Some(label) if label.ttl > 1 => Sign::OpaqueEgress(255 - label.ttl),
+        Some(_) => Sign::Inside(Kind::Explicit, quoted_ttl),
+        None if quoted_ttl > 1 => Sign::Inside(Kind::Implicit, quoted_ttl),
+        None => Sign::Nothing,
+    }
+}
+
+// The tunnel being followed ends at the hop after its last router, if that
+// hop has replied.
+//
+fn close(tunnels: &mut [Tunnel], ttl: u8) {
+    if let Some(tunnel) = tunnels.last_mut().filter(|tunnel| is_open(tunnel, ttl)) {
+        tunnel.egress = Some(ttl);
+    }
+}
+
+// Whether the hop is right after the last router of the tunnel, whose end has
+// not been seen yet.
+//
+fn is_open(tunnel: &Tunnel, ttl: u8) -> bool {
+    tunnel.egress.is_none() &&
+        tunnel.routers.last().is_some_and(|last| follows(*last, ttl))
+}
+
+fn follows(previous: u8, ttl: u8) -> bool {
+    previous.checked_add(1) == Some(ttl)
+}
+
+// How much longer the path back is than the path there.
+//
+fn shift(ttl: u8, response: &Response) -> Option<i16> {
+    Some(i16::from(response.return_hops()?) - i16::from(ttl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        extension::{Extension, MPLS_CLASS, MPLS_INCOMING_STACK},
+        fixtures::TARGET,
+        response::{ECHO_REPLY, TIME_EXCEEDED},
+        trace::{Hop, ProbeStatus},
+    };
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, SystemTime},
+    };
+
+    // Replies of the routers at TTLs 1, 2 and so on: the TTL they come back
+    // with, the quoted TTL and the TTL of the label stack entry, if any.
+    //
+    fn trace(replies: &[(u8, u8, Option<u8>)]) -> Trace {
+        let hops = replies.iter().zip(1..)
+            .map(|((reply_ttl, quoted_ttl, label_ttl), ttl)| {
+                let extensions = label_ttl.iter()
+                    .map(|label_ttl| Extension {
+                        class: MPLS_CLASS,
+                        type_: MPLS_INCOMING_STACK,
+                        payload: vec![0x05, 0xdc, 0x11, *label_ttl],
+                    })
+                    .collect();
+
+                let response = Response {
+                    source: IpAddr::V4(Ipv4Addr::new(192, 0, 2, ttl)),
+                    reply_ttl: *reply_ttl,
+                    type_: TIME_EXCEEDED,
+                    quoted_ttl: Some(*quoted_ttl),
+                    extensions,
+                    ..Default::default()
+                };
+
+                Hop {
+                    ttl,
+                    probes: vec![ProbeResult {
+                        sequence: 0,
+                        sent: SystemTime::UNIX_EPOCH,
+                        status: ProbeStatus::Reply {
+                            response,
+                            rtt: Duration::from_millis(1),
+                        },
+                    }],
+                }
+            })
+            .collect();
+
+        Trace {
+            target: TARGET,
+            started: SystemTime::UNIX_EPOCH,
+            hops,
+        }
+    }
+
+    #[test]
+    fn explicit() {
+        let tunnels = tunnels(&trace(&[
+            (64, 1, None),
+            (63, 1, None),
+            (62, 1, Some(1)),
+            (61, 2, Some(1)),
+            (60, 3, Some(1)),
+            (59, 1, None),
+        ]));
+
+        assert_eq!(tunnels, [Tunnel {
+            kind: Kind::Explicit,
+            ingress: Some(2),
+            egress: Some(6),
+            routers: vec![3, 4, 5],
+            hidden: None,
+        }]);
+    }
+
+    #[test]
+    fn implicit() {
+        let tunnels = tunnels(&trace(&[
+            (64, 1, None),
+            (63, 1, None),
+            (62, 1, None),
+            (61, 2, None),
+            (60, 3, None),
+            (59, 1, None),
+        ]));
+
+        // The first router inside can not be told from the ingress.
+        //
+        assert_eq!(tunnels, [Tunnel {
+            kind: Kind::Implicit,
+            ingress: Some(2),
+            egress: Some(6),
+            routers: vec![4, 5],
+            hidden: None,
+        }]);
+    }
+
+    #[test]
+    fn opaque() {
+        let tunnels = tunnels(&trace(&[
+            (64, 1, None),
+            (63, 1, None),
+            (62, 1, Some(252)),
+            (61, 1, None),
+        ]));
+
+        assert_eq!(tunnels, [Tunnel {
+            kind: Kind::Opaque,
+            ingress: Some(2),
+            egress: Some(3),
+            routers: vec![],
+            hidden: Some(3),
+        }]);
+    }
+
+    #[test]
+    fn invisible() {
+        let tunnels = tunnels(&trace(&[
+            (64, 1, None),
+            (63, 1, None),
+            (58, 1, None),
+            (57, 1, None),
+        ]));
+
+        assert_eq!(tunnels, [Tunnel {
+            kind: Kind::Invisible,
+            ingress: Some(2),
+            egress: Some(3),
+            routers: vec![],
+            hidden: Some(4),
+        }]);
+    }
+
+    #[test]
+    fn plain() {
+        let mut trace = trace(&[(64, 1, None), (63, 1, None), (62, 1, None)]);
+
+        assert!(tunnels(&trace).is_empty());
+
+        // The destination quotes nothing.
+        //
+        if let ProbeStatus::Reply { response, .. } = &mut trace.hops[2].probes[0].status {
+            response.type_ = ECHO_REPLY;
+            response.quoted_ttl = None;
+        }
+
+        assert!(tunnels(&trace).is_empty());
+    }
+}
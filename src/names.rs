@@ -0,0 +1,414 @@
+use rustraceroute::{host, Event};
+use std::{
+    collections::{hash_map, HashMap},
+    fmt,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::IpAddr,
+    path::PathBuf,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const WORKERS: usize = 4;
+
+// How long the names are kept in the cache file.
+//
+const CACHE_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Name {
+    pub name: String,
+    // Whether the name resolves back to the address, if it has been checked.
+    //
+    pub confirmed: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
+enum Entry {
+    Pending(Instant),
+    Done { name: Option<Name>, resolved: SystemTime },
+}
+
+struct Shared {
+    entries: Mutex<HashMap<IpAddr, Entry>>,
+    done: Condvar,
+}
+
+// Names of the hops, looked up by worker threads so that the probing does not
+// wait for them. Cloning gives another handle to the same cache.
+//
+#[derive(Clone)]
+pub struct Names {
+    shared: Arc<Shared>,
+    // There are no workers when the lookups are disabled.
+    //
+    queue: Option<mpsc::Sender<IpAddr>>,
+    confirm: bool,
+    timeout: Duration,
+    cache: Option<PathBuf>,
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.confirmed {
+            Some(false) => write!(formatter, "{}?", self.name),
+            _ => write!(formatter, "{}", self.name),
+        }
+    }
+}
+
+impl Names {
+    pub fn disabled() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                entries: Mutex::new(HashMap::new()),
+                done: Condvar::new(),
+            }),
+            queue: None,
+            confirm: false,
+            timeout: Duration::ZERO,
+            cache: None,
+        }
+    }
+
+    // Names which have been looked up recently are taken from the cache file,
+    // if there is one.
+    //
+    pub fn new(confirm: bool, timeout: Duration, cache: Option<PathBuf>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let names = Self {
+            queue: Some(sender),
+            confirm,
+            timeout,
+            cache,
+            ..Self::disabled()
+        };
+
+        if let Some(path) = &names.cache {
+            if let Ok(file) = fs::File::open(path) {
+                names.load(BufReader::new(file), SystemTime::now());
+            }
+        }
+
+        for _ in 0..WORKERS {
+            let shared = Arc::clone(&names.shared);
+            let receiver = Arc::clone(&receiver);
+
+            thread::spawn(move || loop {
+                let address = match receiver.lock().unwrap().recv() {
+                    Ok(address) => address,
+                    Err(_) => break,
+                };
+
+                let name = lookup(address, confirm);
+
+                shared.entries.lock().unwrap().insert(address, Entry::Done {
+                    name,
+                    resolved: SystemTime::now(),
+                });
+
+                shared.done.notify_all();
+            });
+        }
+
+        names
+    }
+
+    // Enabled, but without workers: only the names given are known.
+    //
+    #[cfg(test)]
+    pub fn fixed(names: &[(IpAddr, &str)]) -> Self {
+        let result = Self { queue: Some(mpsc::channel().0), ..Self::disabled() };
+
+        result.shared.entries.lock().unwrap().extend(
+            names.iter().map(|(address, name)| (*address, Entry::Done {
+                name: Some(Name { name: name.to_string(), confirmed: None }),
+                resolved: SystemTime::now(),
+            })),
+        );
+
+        result
+    }
+
+    // Enabled, but the lookups never finish, waiting for them times out.
+    //
+    #[cfg(test)]
+    pub fn unresolved(timeout: Duration) -> Self {
+        Self { timeout, ..Self::fixed(&[]) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    // Starts looking the name up, unless it is known or being looked up.
+    //
+    pub fn lookup(&self, address: IpAddr) {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        let mut entries = self.shared.entries.lock().unwrap();
+
+        if let hash_map::Entry::Vacant(entry) = entries.entry(address) {
+            entry.insert(Entry::Pending(Instant::now()));
+
+            let _ = queue.send(address);
+        }
+    }
+
+    // Starts looking up the names of the responders as they reply.
+    //
+    pub fn prefetch(&self, event: &Event) {
+        if let Event::ReplyReceived { response, .. } = event {
+            self.lookup(response.source);
+        }
+    }
+
+    // The name if it has been looked up already.
+    //
+    pub fn get(&self, address: IpAddr) -> Option<Name> {
+        match self.shared.entries.lock().unwrap().get(&address) {
+            Some(Entry::Done { name, .. }) => name.clone(),
+            _ => None,
+        }
+    }
+
+    // The name, waiting for the lookup to finish unless it has been going on
+    // for longer than the timeout.
+    //
+    pub fn wait(&self, address: IpAddr) -> Option<Name> {
+        self.lookup(address);
+
+        let mut entries = self.shared.entries.lock().unwrap();
+
+        loop {
+            let started = match entries.get(&address)? {
+                Entry::Done { name, .. } => return name.clone(),
+                Entry::Pending(started) => *started,
+            };
+
+            let timeout = (started + self.timeout)
+                .checked_duration_since(Instant::now())?;
+
+            entries = self.shared.done.wait_timeout(entries, timeout).unwrap().0;
+        }
+    }
+
+    // Writes the names looked up so far to the cache file, if there is one.
+    //
+    pub fn save(&self) -> io::Result<()> {
+        let path = match (&self.queue, &self.cache) {
+            (Some(_), Some(path)) => path,
+            _ => return Ok(()),
+        };
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut output = io::BufWriter::new(fs::File::create(path)?);
+
+        self.write(&mut output, SystemTime::now())?;
+
+        output.flush()
+    }
+
+    // One line per address: the address, the time it has been looked up in
+    // seconds since the Unix epoch, the name if it has one and whether it
+    // resolves back to the address if it has been checked, separated with
+    // tabs.
+    //
+    fn load<R: BufRead>(&self, input: R, now: SystemTime) {
+        let mut entries = self.shared.entries.lock().unwrap();
+
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            let (address, resolved, name, confirmed) = match fields[..] {
+                [address, resolved, name, confirmed] => {
+                    (address, resolved, name, confirmed)
+                }
+                _ => continue,
+            };
+
+            let (address, resolved) = match (
+                address.parse(),
+                resolved.parse().map(|seconds| {
+                    UNIX_EPOCH + Duration::from_secs(seconds)
+                }),
+            ) {
+                (Ok(address), Ok(resolved)) => (address, resolved),
+                _ => continue,
+            };
+
+            let confirmed = match confirmed {
+                "yes" => Some(true),
+                "no" => Some(false),
+                _ => None,
+            };
+
+            // Names which have not been checked are looked up again when they
+            // have to be.
+            //
+            if expired(resolved, now) || (self.confirm && !name.is_empty() && confirmed.is_none()) {
+                continue
+            }
+
+            let name = Some(name).filter(|name| !name.is_empty())
+                .map(|name| Name {
+                    name: name.to_string(),
+                    confirmed: confirmed.filter(|_| self.confirm),
+                });
+
+            entries.insert(address, Entry::Done { name, resolved });
+        }
+    }
+
+    fn write<W: Write>(&self, output: &mut W, now: SystemTime) -> io::Result<()> {
+        let entries = self.shared.entries.lock().unwrap();
+
+        for (address, entry) in entries.iter() {
+            let (name, resolved) = match entry {
+                Entry::Done { name, resolved } => (name, resolved),
+                Entry::Pending(_) => continue,
+            };
+
+            if expired(*resolved, now) {
+                continue
+            }
+
+            writeln!(
+                output,
+                "{}\t{}\t{}\t{}",
+                address,
+                resolved.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                name.as_ref().map_or("", |name| &name.name),
+                match name.as_ref().and_then(|name| name.confirmed) {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "",
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn expired(resolved: SystemTime, now: SystemTime) -> bool {
+    now.duration_since(resolved).is_ok_and(|age| age > CACHE_LIFETIME)
+}
+
+fn lookup(address: IpAddr, confirm: bool) -> Option<Name> {
+    let name = host::reverse(address)?;
+
+    let confirmed = match confirm {
+        true => Some(host::addresses(&name).contains(&address)),
+        false => None,
+    };
+
+    Some(Name { name, confirmed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{OTHER, ROUTER, TARGET};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn names(confirm: bool) -> Names {
+        Names {
+            confirm,
+            ..Names::disabled()
+        }
+    }
+
+    #[test]
+    fn display() {
+        let name = |confirmed| Name { name: "router".to_string(), confirmed };
+
+        assert_eq!(name(None).to_string(), "router");
+        assert_eq!(name(Some(true)).to_string(), "router");
+        assert_eq!(name(Some(false)).to_string(), "router?");
+    }
+
+    #[test]
+    fn disabled() {
+        let names = Names::disabled();
+
+        names.lookup(ROUTER);
+
+        assert!(!names.is_enabled());
+        assert_eq!(names.wait(ROUTER), None);
+    }
+
+    #[test]
+    fn cache_file() {
+        let names = names(false);
+
+        names.load(&b"\
+            192.0.2.1\t100\trouter.example.net\t\n\
+            192.0.2.2\t100\t\t\n\
+            198.51.100.1\t0\texpired.example.net\t\n\
+            garbage\n"[..], at(CACHE_LIFETIME.as_secs() + 50));
+
+        assert_eq!(names.get(ROUTER), Some(Name {
+            name: "router.example.net".to_string(),
+            confirmed: None,
+        }));
+        assert_eq!(names.get(OTHER), None);
+        assert!(names.shared.entries.lock().unwrap().contains_key(&OTHER));
+        assert!(!names.shared.entries.lock().unwrap().contains_key(&TARGET));
+
+        let mut output = vec![];
+        names.write(&mut output, at(200)).unwrap();
+
+        let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap()
+            .lines().collect();
+        lines.sort();
+
+        assert_eq!(lines, vec![
+            "192.0.2.1\t100\trouter.example.net\t",
+            "192.0.2.2\t100\t\t",
+        ]);
+    }
+
+    #[test]
+    fn cache_file_confirmed() {
+        let names = names(true);
+
+        names.load(&b"\
+            192.0.2.1\t100\trouter.example.net\tno\n\
+            192.0.2.2\t100\tother.example.net\t\n"[..], at(200));
+
+        assert_eq!(names.get(ROUTER).unwrap().to_string(), "router.example.net?");
+        assert!(!names.shared.entries.lock().unwrap().contains_key(&OTHER));
+    }
+
+    #[test]
+    fn wait_timeout() {
+        let names = Names {
+            timeout: Duration::from_millis(10),
+            ..names(false)
+        };
+
+        names.shared.entries.lock().unwrap()
+            .insert(ROUTER, Entry::Pending(Instant::now()));
+
+        assert_eq!(names.wait(ROUTER), None);
+    }
+}
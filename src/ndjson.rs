@@ -0,0 +1,206 @@
+use crate::{
+    annotations::Annotations,
+    json,
+    options::Options,
+    timestamp,
+};
+use rustraceroute::{Event, Observer};
+use serde_json::{json, Value};
+use std::{
+    io::{self, Write},
+    net::IpAddr,
+    time::SystemTime,
+};
+
+// Prints every event as a JSON object on its own line as soon as it happens.
+//
+pub struct NdJson<W: Write> {
+    output: W,
+    annotations: Annotations,
+}
+
+impl<W: Write> NdJson<W> {
+    pub fn new(output: W, annotations: Annotations) -> Self {
+        Self { output, annotations }
+    }
+
+    pub fn start(
+        &mut self,
+        options: &Options,
+        address: IpAddr,
+    ) -> io::Result<()> {
+        let mut value = json!({ "event": "trace_started" });
+
+        if let (Value::Object(object), Value::Object(metadata)) = (
+            &mut value,
+            json::metadata(options, address, SystemTime::now()),
+        ) {
+            object.extend(metadata);
+        }
+
+        self.write(&value)
+    }
+
+    fn write(&mut self, value: &Value) -> io::Result<()> {
+        writeln!(self.output, "{}", value)?;
+        self.output.flush()
+    }
+}
+
+impl<W: Write> Observer for NdJson<W> {
+    fn on_event(&mut self, event: &Event) {
+        self.annotations.names.prefetch(event);
+
+        let value = self::event(event, SystemTime::now(), &self.annotations);
+
+        // There is nobody to report to if the output is gone.
+        //
+        let _ = self.write(&value);
+    }
+}
+
+pub fn event(
+    event: &Event,
+    time: SystemTime,
+    annotations: &Annotations,
+) -> Value {
+    let mut result = json!({
+        "event": null,
+        "time": timestamp::rfc3339(time),
+    });
+
+    match event {
+        Event::ProbeSent { ttl, sequence, sent } => {
+            result["event"] = "probe_sent".into();
+            result["time"] = timestamp::rfc3339(*sent).into();
+            result["ttl"] = (*ttl).into();
+            result["sequence"] = (*sequence).into();
+        }
+        Event::ProbeFailed { ttl, sequence, error } => {
+            result["event"] = "probe_failed".into();
+            result["ttl"] = (*ttl).into();
+            result["sequence"] = (*sequence).into();
+            json::set_error(&mut result, error);
+        }
+        Event::ReplyReceived { ttl, sequence, response, rtt } => {
+            result["event"] = "reply_received".into();
+            result["ttl"] = (*ttl).into();
+            result["sequence"] = (*sequence).into();
+            json::set_reply(&mut result, response, *rtt);
+        }
+        Event::ProbeTimedOut { ttl, sequence } => {
+            result["event"] = "probe_timed_out".into();
+            result["ttl"] = (*ttl).into();
+            result["sequence"] = (*sequence).into();
+        }
+        Event::HopCompleted(hop) => {
+            result["event"] = "hop_completed".into();
+            result["ttl"] = hop.ttl.into();
+            result["responders"] = hop.responders().iter()
+                .map(|address| address.to_string())
+                .collect();
+            result["probes"] = json::hop(hop, annotations)["probes"].take();
+        }
+        Event::DestinationReached { ttl, address } => {
+            result["event"] = "destination_reached".into();
+            result["ttl"] = (*ttl).into();
+            result["address"] = address.to_string().into();
+        }
+        Event::TraceFinished(trace) => {
+            result["event"] = "trace_finished".into();
+            result["reached"] = trace.reached().into();
+            result["hops"] = trace.hops.len().into();
+            result["warnings"] = annotations.trace_warnings(trace).into();
+            result["tunnels"] = json::tunnels(trace);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::TARGET, names::Names};
+    use rustraceroute::{Error, Hop, Trace};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn probe_sent() {
+        assert_eq!(
+            event(
+                &Event::ProbeSent { ttl: 3, sequence: 7, sent: UNIX_EPOCH },
+                SystemTime::now(),
+                &Names::disabled().into(),
+            ),
+            json!({
+                "event": "probe_sent",
+                "time": "1970-01-01T00:00:00.000000Z",
+                "ttl": 3,
+                "sequence": 7,
+            }),
+        );
+    }
+
+    #[test]
+    fn probe_failed() {
+        let value = event(
+            &Event::ProbeFailed { ttl: 3, sequence: 7, error: Error::NoRoute },
+            UNIX_EPOCH,
+            &Names::disabled().into(),
+        );
+
+        assert_eq!(value["event"], "probe_failed");
+        assert_eq!(value["error"], "no route to host");
+        assert_eq!(value["annotations"], json!(["!H"]));
+    }
+
+    #[test]
+    fn hop_completed() {
+        let value = event(
+            &Event::HopCompleted(Hop::new(4)),
+            UNIX_EPOCH,
+            &Names::disabled().into(),
+        );
+
+        assert_eq!(value["event"], "hop_completed");
+        assert_eq!(value["ttl"], 4);
+        assert_eq!(value["responders"], json!([]));
+        assert_eq!(value["probes"], json!([]));
+    }
+
+    #[test]
+    fn trace_finished() {
+        let value = event(
+            &Event::TraceFinished(Trace {
+                target: TARGET,
+                started: UNIX_EPOCH,
+                hops: vec![Hop::new(1), Hop::new(2)],
+            }),
+            UNIX_EPOCH,
+            &Names::disabled().into(),
+        );
+
+        assert_eq!(value, json!({
+            "event": "trace_finished",
+            "time": "1970-01-01T00:00:00.000000Z",
+            "reached": false,
+            "hops": 2,
+            "warnings": [],
+            "tunnels": [],
+        }));
+    }
+
+    #[test]
+    fn one_line_per_event() {
+        let mut ndjson = NdJson::new(vec![], Names::disabled().into());
+
+        ndjson.on_event(&Event::ProbeTimedOut { ttl: 1, sequence: 0 });
+        ndjson.on_event(&Event::ProbeTimedOut { ttl: 1, sequence: 1 });
+
+        let output = String::from_utf8(ndjson.output).unwrap();
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.ends_with('\n'));
+    }
+}
@@ -1,10 +1,14 @@
-use clap::Clap;
+use clap::{AppSettings, ArgEnum, Clap};
+use std::path::PathBuf;
 
-#[derive(Debug, Clap)]
-#[clap(about, author, version)]
+#[derive(Clone, Debug, Clap)]
+#[clap(about, author, version, setting = AppSettings::SubcommandsNegateReqs)]
 pub struct Options {
-    #[clap(about = "The name or IP address of the destination host")]
-    pub host: String,
+    #[clap(
+        required_unless_present = "read",
+        about = "The name or IP address of the destination host",
+    )]
+    pub host: Option<String>,
 
     #[clap(
         short = 'f',
@@ -37,4 +41,240 @@ pub struct Options {
         about = "The time (in seconds) to wait for a response to a probe",
     )]
     pub waittime: u8,
+
+    #[clap(
+        short = 'n',
+        long = "numeric",
+        about = "Print the addresses of the hops without looking up their \
+            names",
+    )]
+    pub numeric: bool,
+
+    #[clap(
+        long = "confirm-names",
+        conflicts_with = "numeric",
+        about = "Check that the names of the hops resolve back to their \
+            addresses, those which do not are marked with ?",
+    )]
+    pub confirm_names: bool,
+
+    #[clap(
+        long = "dns-timeout",
+        value_name = "SECONDS",
+        default_value = "2",
+        about = "The time to wait for the name of a hop",
+    )]
+    pub dns_timeout: f64,
+
+    #[clap(
+        short = 'A',
+        long = "asn-db",
+        value_name = "FILE",
+        about = "Look up the origin ASes of the hops in the MRT RIB dump, CAIDA \
+            prefix-to-AS file or CSV file of prefixes and ASes, an asterisk \
+            marks where the path enters another AS",
+    )]
+    pub asn_db: Option<PathBuf>,
+
+    #[clap(
+        long = "geoip",
+        value_name = "FILE",
+        about = "Look up the countries, cities and coordinates of the hops in \
+            the MaxMind DB (.mmdb) file",
+    )]
+    pub geoip: Option<PathBuf>,
+
+    #[clap(
+        long = "ranges",
+        value_name = "FILE",
+        number_of_values = 1,
+        about = "Tag the hops in the clouds and Internet exchanges listed in the \
+            AWS, Google Cloud or Azure range file or PeeringDB dump, can be \
+            given several times",
+    )]
+    pub ranges: Vec<PathBuf>,
+
+    #[clap(
+        long = "vrps",
+        value_name = "FILE",
+        requires = "asn-db",
+        about = "Check the origins of the prefixes of the hops against the \
+            RPKI validator's JSON export of VRPs, marking the hops whose \
+            prefixes are RPKI-invalid or unknown",
+    )]
+    pub vrps: Option<PathBuf>,
+
+    #[clap(
+        long = "hints",
+        conflicts_with = "numeric",
+        about = "Guess where the hops are from the airport and city codes in \
+            their names, shown with a tilde, and warn about the hops farther \
+            from the previous ones than light in fiber goes within their RTTs",
+    )]
+    pub hints: bool,
+
+    #[clap(
+        long = "hint-rule",
+        value_name = "REGEX",
+        number_of_values = 1,
+        requires = "hints",
+        about = "Find the code in the lowercase names with the regular \
+            expression, in its group named code or its first group, before \
+            the built-in rules, can be given several times",
+    )]
+    pub hint_rules: Vec<String>,
+
+    #[clap(
+        long = "hint-codes",
+        value_name = "FILE",
+        requires = "hints",
+        about = "Add the codes in the CSV file of codes, cities, country \
+            codes, latitudes and longitudes to the built-in ones",
+    )]
+    pub hint_codes: Option<PathBuf>,
+
+    #[clap(
+        long = "mpls",
+        about = "Print the MPLS label stacks and the quoted TTLs above 1 of \
+            the replies, and the MPLS tunnels found on the path with the hops \
+            they hide",
+    )]
+    pub mpls: bool,
+
+    #[clap(
+        long = "format",
+        arg_enum,
+        default_value = "text",
+        about = "The output format",
+    )]
+    pub format: Format,
+
+    #[clap(
+        long = "json",
+        conflicts_with_all = &["format", "ndjson"],
+        about = "Print the trace as a single JSON document when it is done \
+            (same as --format json)",
+    )]
+    pub json: bool,
+
+    #[clap(
+        long = "ndjson",
+        conflicts_with = "format",
+        about = "Print every event as a JSON object on its own line as it \
+            happens (same as --format ndjson)",
+    )]
+    pub ndjson: bool,
+
+    #[clap(
+        long = "read",
+        value_name = "FILE",
+        conflicts_with = "host",
+        about = "Print the traces from the warts file instead of tracing (merged \
+            into one graph with --format dot)",
+    )]
+    pub read: Option<PathBuf>,
+
+    #[clap(
+        long = "aliases",
+        requires = "read",
+        about = "Probe the IPv4 interfaces of the traces read for the IP-ID \
+            counters they share and draw those of one router as one node, \
+            only for the traces read with --read and drawn with --format dot, \
+            the other formats ignore it",
+    )]
+    pub aliases: bool,
+
+    #[clap(
+        long = "pcap",
+        value_name = "FILE",
+        conflicts_with = "read",
+        about = "Capture the probes and the received packets into the pcapng \
+            file",
+    )]
+    pub pcap: Option<PathBuf>,
+
+    #[clap(
+        long = "tui",
+        conflicts_with_all = &["format", "json", "ndjson", "read", "pcap"],
+        about = "Probe all the hops over and over again, showing the \
+            statistics of every hop on the screen until q is pressed",
+    )]
+    pub tui: bool,
+
+    #[clap(
+        long = "report",
+        conflicts_with_all = &["tui", "read", "pcap"],
+        about = "Probe all the hops the number of times given with --count, \
+            then print the statistics of every hop",
+    )]
+    pub report: bool,
+
+    #[clap(
+        short = 'c',
+        long = "count",
+        default_value = "10",
+        about = "The number of rounds of probes in the report",
+    )]
+    pub count: u32,
+
+    #[clap(
+        long = "jitter",
+        requires = "report",
+        about = "Add the mean difference between consecutive RTTs to the \
+            report",
+    )]
+    pub jitter: bool,
+
+    #[clap(
+        long = "percentiles",
+        requires = "report",
+        about = "Add the 50th, 90th and 99th percentiles of the RTTs to the \
+            report",
+    )]
+    pub percentiles: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Debug, Clap)]
+pub enum Command {
+    #[clap(
+        about = "Trace with the packets from the pcap or pcapng file instead of \
+            the network, the probes and their parameters are taken from it",
+    )]
+    Replay {
+        #[clap(about = "The capture file, e.g. written with --pcap")]
+        file: PathBuf,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Atlas,
+    Warts,
+    Dot,
+}
+
+impl Options {
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or_default()
+    }
+
+    pub fn format(&self) -> Format {
+        if self.json {
+            Format::Json
+        }
+        else if self.ndjson {
+            Format::Ndjson
+        }
+        else {
+            self.format
+        }
+    }
 }
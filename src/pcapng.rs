@@ -0,0 +1,681 @@
+use crate::{
+    checksum::checksum,
+    event::{Event, Observer, Packet},
+};
+use std::{
+    convert::TryInto,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Capture file in the pcapng format, see
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-02.html
+// Blocks are written in the little-endian byte order, readers detect it by
+// the byte-order magic of the section header. Reading also supports the
+// classic pcap format.
+//
+const SECTION_HEADER_BLOCK:        u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK:       u32 = 6;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS:  u32 = 0xA1B2_3C4D;
+
+const LINKTYPE_NULL:      u16 = 0;
+const LINKTYPE_ETHERNET:  u16 = 1;
+// Packets start with the IP header, the version tells IPv4 from IPv6.
+const LINKTYPE_RAW:       u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4:      u16 = 228;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT:  u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_TSRESOL:   u16 = 9;
+
+// IPv4 packet read from a capture file, starting with the IP header.
+//
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captured {
+    pub time: SystemTime,
+    pub data: Vec<u8>,
+}
+
+// Writes the packets reported to it as an observer. The probes are sent
+// without the IP header, so it is made up from what is known about them.
+//
+pub struct Writer<W: Write> {
+    output: W,
+    source: Ipv4Addr,
+    // The first error stops the writing, it is returned by `finish`.
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Writer<W> {
+    // Source is the address of the probes, unspecified if not known.
+    //
+    pub fn new(mut output: W, source: Option<IpAddr>) -> io::Result<Self> {
+        let mut section = vec![];
+        section.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        section.extend_from_slice(&1u16.to_le_bytes());
+        section.extend_from_slice(&0u16.to_le_bytes());
+        // Length of the section is not known in advance.
+        section.extend_from_slice(&(-1i64).to_le_bytes());
+        put_option(&mut section, SHB_USERAPPL, b"rustraceroute");
+        put_option(&mut section, OPT_ENDOFOPT, b"");
+
+        let mut interface = vec![];
+        interface.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes());
+        // No limit of the captured length.
+        interface.extend_from_slice(&0u32.to_le_bytes());
+
+        write_block(&mut output, SECTION_HEADER_BLOCK, &section)?;
+        write_block(&mut output, INTERFACE_DESCRIPTION_BLOCK, &interface)?;
+
+        let source = match source {
+            Some(IpAddr::V4(source)) => source,
+            _ => Ipv4Addr::UNSPECIFIED,
+        };
+
+        Ok(Self { output, source, error: None })
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error)
+        }
+
+        self.output.flush()?;
+
+        Ok(self.output)
+    }
+
+    fn write_packet(&mut self, packet: &Packet<'_>) -> io::Result<()> {
+        let (time, data, comment) = match *packet {
+            Packet::Sent { ttl, sequence, time, destination, data } => {
+                // Only IPv4 is supported for now.
+                //
+                let destination = match destination {
+                    IpAddr::V4(destination) => destination,
+                    IpAddr::V6(_) => return Ok(()),
+                };
+
+                (
+                    time,
+                    ipv4_packet(self.source, destination, ttl, data),
+                    format!("sent: ttl {}, probe {}", ttl, sequence),
+                )
+            }
+            Packet::Received { time, data, probe } => (
+                time,
+                data.to_vec(),
+                match probe {
+                    Some((ttl, sequence)) => format!(
+                        "received: matched ttl {}, probe {}",
+                        ttl,
+                        sequence,
+                    ),
+                    None => "received: unmatched".to_string(),
+                },
+            ),
+        };
+
+        let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut block = vec![];
+        // Interface which has been described in the beginning.
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(micros as u32).to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        put_padded(&mut block, &data);
+        put_option(&mut block, OPT_COMMENT, comment.as_bytes());
+        put_option(&mut block, OPT_ENDOFOPT, b"");
+
+        write_block(&mut self.output, ENHANCED_PACKET_BLOCK, &block)
+    }
+}
+
+impl<W: Write> Observer for Writer<W> {
+    fn on_event(&mut self, _event: &Event) {}
+
+    fn on_packet(&mut self, packet: &Packet<'_>) {
+        if self.error.is_none() {
+            if let Err(error) = self.write_packet(packet) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+// IP header the kernel adds to the probe. Identification is chosen by the
+// kernel, so it is left zero.
+//
+pub(crate) fn ipv4_packet(
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    ttl: u8,
+    data: &[u8],
+) -> Vec<u8> {
+    let length = (20 + data.len()) as u16;
+
+    let mut result = vec![
+        0x45, // version and header length
+        0,    // TOS
+        (length >> 8) as u8,
+        length as u8,
+        0, 0, // identification
+        0, 0, // flags and fragment offset
+        ttl,
+        1,    // protocol, ICMP
+        0, 0, // checksum
+    ];
+    result.extend_from_slice(&source.octets());
+    result.extend_from_slice(&destination.octets());
+
+    let checksum = checksum(&result);
+    result[10] = (checksum >> 8) as u8;
+    result[11] = checksum as u8;
+
+    result.extend_from_slice(data);
+    result
+}
+
+fn put_padded(block: &mut Vec<u8>, data: &[u8]) {
+    block.extend_from_slice(data);
+    block.resize(block.len() + (4 - data.len() % 4) % 4, 0);
+}
+
+fn put_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    put_padded(block, value);
+}
+
+// Blocks are padded to 32 bits and have their total length both before and
+// after the body.
+//
+fn write_block<W: Write>(
+    output: &mut W,
+    type_: u32,
+    body: &[u8],
+) -> io::Result<()> {
+    let length = (12 + body.len()) as u32;
+
+    output.write_all(&type_.to_le_bytes())?;
+    output.write_all(&length.to_le_bytes())?;
+    output.write_all(body)?;
+    output.write_all(&length.to_le_bytes())
+}
+
+// Reads the IPv4 packets from a pcapng or pcap file, skipping everything else.
+//
+pub fn read<R: Read>(mut input: R) -> io::Result<Vec<Captured>> {
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+
+    let magic = Bytes { data: &data, little: true }.u32(0)?;
+
+    if magic == SECTION_HEADER_BLOCK {
+        read_pcapng(&data)
+    }
+    else if magic == PCAP_MAGIC_MICROS || magic == PCAP_MAGIC_NANOS {
+        read_pcap(Bytes { data: &data, little: true })
+    }
+    else if magic.swap_bytes() == PCAP_MAGIC_MICROS ||
+        magic.swap_bytes() == PCAP_MAGIC_NANOS
+    {
+        read_pcap(Bytes { data: &data, little: false })
+    }
+    else {
+        Err(invalid("unknown file format"))
+    }
+}
+
+fn read_pcap(bytes: Bytes) -> io::Result<Vec<Captured>> {
+    let nanos = bytes.u32(0)? == PCAP_MAGIC_NANOS;
+    // The upper bits may carry other information.
+    let linktype = bytes.u32(20)? as u16;
+
+    let mut result = vec![];
+    let mut offset = 24;
+
+    while offset < bytes.data.len() {
+        let seconds = bytes.u32(offset)?;
+        let fraction = bytes.u32(offset + 4)?;
+        let length = bytes.u32(offset + 8)? as usize;
+        let packet = bytes.slice(offset + 16, length)?;
+
+        let time = UNIX_EPOCH + Duration::from_secs(seconds.into()) +
+            if nanos { Duration::from_nanos(fraction.into()) }
+            else { Duration::from_micros(fraction.into()) };
+
+        if let Some(data) = strip_link_layer(linktype, packet, bytes.little)? {
+            result.push(Captured { time, data: data.to_vec() });
+        }
+
+        offset += 16 + length;
+    }
+
+    Ok(result)
+}
+
+fn read_pcapng(data: &[u8]) -> io::Result<Vec<Captured>> {
+    let mut result = vec![];
+    let mut bytes = Bytes { data, little: true };
+    // Link type and timestamp units per second of every interface.
+    let mut interfaces: Vec<(u16, u64)> = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let type_ = bytes.u32(offset)?;
+
+        if type_ == SECTION_HEADER_BLOCK {
+            bytes.little = bytes.u32(offset + 8)? == BYTE_ORDER_MAGIC;
+            interfaces.clear();
+        }
+
+        let length = bytes.u32(offset + 4)? as usize;
+
+        if length < 12 || !length.is_multiple_of(4) {
+            return Err(invalid("bad block length"))
+        }
+
+        let body = Bytes {
+            data: bytes.slice(offset + 8, length - 12)?,
+            little: bytes.little,
+        };
+
+        match type_ {
+            INTERFACE_DESCRIPTION_BLOCK => {
+                interfaces.push((body.u16(0)?, units_per_second(&body)?));
+            }
+            ENHANCED_PACKET_BLOCK => {
+                let &(linktype, units) = interfaces
+                    .get(body.u32(0)? as usize)
+                    .ok_or_else(|| invalid("unknown interface"))?;
+
+                let timestamp =
+                    (u64::from(body.u32(4)?) << 32) + u64::from(body.u32(8)?);
+
+                let packet = body.slice(20, body.u32(12)? as usize)?;
+
+                let nanos = u128::from(timestamp % units) * 1_000_000_000 /
+                    u128::from(units);
+
+                let time = UNIX_EPOCH +
+                    Duration::from_secs(timestamp / units) +
+                    Duration::from_nanos(nanos as u64);
+
+                if let Some(data) =
+                    strip_link_layer(linktype, packet, body.little)?
+                {
+                    result.push(Captured { time, data: data.to_vec() });
+                }
+            }
+            _ => {}
+        }
+
+        offset += length;
+    }
+
+    Ok(result)
+}
+
+// Resolution of the timestamps is a power of 10 or, if the high bit is set,
+// of 2. Microseconds are the default.
+//
+fn units_per_second(interface: &Bytes) -> io::Result<u64> {
+    let mut offset = 8;
+
+    while offset + 4 <= interface.data.len() {
+        let code = interface.u16(offset)?;
+        let length = interface.u16(offset + 2)? as usize;
+
+        if code == OPT_ENDOFOPT {
+            break
+        }
+
+        if code == IF_TSRESOL && length == 1 {
+            let resolution = interface.slice(offset + 4, 1)?[0];
+
+            return if resolution & 0x80 == 0 {
+                10u64.checked_pow(resolution.into())
+            }
+            else {
+                2u64.checked_pow((resolution & 0x7F).into())
+            }.ok_or_else(|| invalid("bad timestamp resolution"))
+        }
+
+        offset += 4 + length.div_ceil(4) * 4;
+    }
+
+    Ok(1_000_000)
+}
+
+// Packets which are not IPv4 are skipped.
+//
+fn strip_link_layer(
+    linktype: u16,
+    packet: &[u8],
+    little: bool,
+) -> io::Result<Option<&[u8]>> {
+    let bytes = Bytes { data: packet, little: false };
+
+    let offset = match linktype {
+        LINKTYPE_RAW | LINKTYPE_IPV4 => Some(0),
+        LINKTYPE_ETHERNET => match bytes.u16(12).ok() {
+            Some(ETHERTYPE_IPV4) => Some(14),
+            Some(ETHERTYPE_VLAN) => {
+                bytes.u16(16).ok().filter(|type_| *type_ == ETHERTYPE_IPV4)
+                    .map(|_| 18)
+            }
+            _ => None,
+        },
+        LINKTYPE_LINUX_SLL => {
+            bytes.u16(14).ok().filter(|type_| *type_ == ETHERTYPE_IPV4)
+                .map(|_| 16)
+        }
+        // Address family in the byte order of the host which has captured.
+        LINKTYPE_NULL => {
+            Bytes { data: packet, little }.u32(0).ok()
+                .filter(|family| *family == libc::AF_INET as u32)
+                .map(|_| 4)
+        }
+        _ => return Err(invalid("unsupported link type")),
+    };
+
+    Ok(offset.and_then(|offset| packet.get(offset..))
+        .filter(|data| data.first().is_some_and(|byte| byte >> 4 == 4)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("pcap: {}", message))
+}
+
+// Data in the byte order of the file.
+//
+#[derive(Clone, Copy)]
+struct Bytes<'a> {
+    data: &'a [u8],
+    little: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn slice(&self, offset: usize, length: usize) -> io::Result<&'a [u8]> {
+        offset.checked_add(length)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| invalid("unexpected end of data"))
+    }
+
+    fn u16(&self, offset: usize) -> io::Result<u16> {
+        let bytes = self.slice(offset, 2)?.try_into().unwrap();
+
+        Ok(if self.little { u16::from_le_bytes(bytes) }
+            else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> io::Result<u32> {
+        let bytes = self.slice(offset, 4)?.try_into().unwrap();
+
+        Ok(if self.little { u32::from_le_bytes(bytes) }
+            else { u32::from_be_bytes(bytes) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures::{SOURCE, TARGET}, request::Request};
+    use std::time::Duration;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    // Types and bodies of the blocks.
+    //
+    fn blocks(mut data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut result = vec![];
+
+        while !data.is_empty() {
+            let length = u32_at(data, 4) as usize;
+
+            assert_eq!(length % 4, 0);
+            assert_eq!(u32_at(data, length - 4) as usize, length);
+
+            result.push((u32_at(data, 0), data[8..(length - 4)].to_vec()));
+            data = &data[length..];
+        }
+
+        result
+    }
+
+    fn capture(packets: &[Packet<'_>]) -> Vec<u8> {
+        let mut writer = Writer::new(vec![], Some(SOURCE)).unwrap();
+
+        for packet in packets {
+            writer.on_packet(packet);
+        }
+
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn header() {
+        let blocks = blocks(&capture(&[]));
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].0, SECTION_HEADER_BLOCK);
+        assert_eq!(&blocks[0].1[..4], &[0x4D, 0x3C, 0x2B, 0x1A]);
+        assert_eq!(blocks[1].0, INTERFACE_DESCRIPTION_BLOCK);
+        assert_eq!(&blocks[1].1[..2], &[101, 0]);
+    }
+
+    #[test]
+    fn sent() {
+        let probe = Request::new(0x1234, 7).to_vec();
+
+        let blocks = blocks(&capture(&[Packet::Sent {
+            ttl: 3,
+            sequence: 7,
+            time: UNIX_EPOCH + Duration::from_micros(0x1_0000_0002),
+            destination: TARGET,
+            data: &probe,
+        }]));
+
+        let body = &blocks[2].1;
+
+        assert_eq!(blocks[2].0, ENHANCED_PACKET_BLOCK);
+        assert_eq!(u32_at(body, 4), 1);
+        assert_eq!(u32_at(body, 8), 2);
+        assert_eq!(u32_at(body, 12), 28);
+        assert_eq!(u32_at(body, 16), 28);
+
+        let packet = &body[20..48];
+
+        assert_eq!(&packet[..4], &[0x45, 0, 0, 28]);
+        assert_eq!(packet[8], 3);
+        assert_eq!(checksum(&packet[..20]), 0);
+        assert_eq!(&packet[12..16], &[192, 0, 2, 100]);
+        assert_eq!(&packet[16..20], &[198, 51, 100, 1]);
+        assert_eq!(&packet[20..], &probe[..]);
+
+        let comment = b"sent: ttl 3, probe 7";
+
+        assert_eq!(&body[48..50], &[1, 0]);
+        assert_eq!(&body[50..52], &[comment.len() as u8, 0]);
+        assert_eq!(&body[52..(52 + comment.len())], comment);
+    }
+
+    #[test]
+    fn received() {
+        let data = [0x45, 0, 0, 0, 1];
+
+        let blocks = blocks(&capture(&[
+            Packet::Received { time: UNIX_EPOCH, data: &data, probe: Some((3, 7)) },
+            Packet::Received { time: UNIX_EPOCH, data: &data, probe: None },
+        ]));
+
+        for (block, comment) in blocks[2..].iter().zip([
+            &b"received: matched ttl 3, probe 7"[..],
+            &b"received: unmatched"[..],
+        ]) {
+            let body = &block.1;
+
+            assert_eq!(u32_at(body, 12), 5);
+            assert_eq!(&body[20..25], &data);
+            assert_eq!(&body[25..28], &[0, 0, 0]);
+            assert_eq!(&body[32..(32 + comment.len())], comment);
+        }
+    }
+
+    #[test]
+    fn write_error() {
+        // Accepts the header, but nothing after it.
+        //
+        struct Full(usize);
+
+        impl Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 < buf.len() {
+                    return Err(io::Error::other("full"))
+                }
+
+                self.0 -= buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = Writer::new(Full(capture(&[]).len()), None).unwrap();
+
+        writer.on_packet(&Packet::Received {
+            time: UNIX_EPOCH,
+            data: &[0x45],
+            probe: None,
+        });
+
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn read_own() {
+        let probe = Request::new(0x1234, 7).to_vec();
+        let reply = [0x45, 0, 0, 0, 1];
+
+        let packets = read(&capture(&[
+            Packet::Sent {
+                ttl: 3,
+                sequence: 7,
+                time: UNIX_EPOCH + Duration::from_micros(1_500_000),
+                destination: TARGET,
+                data: &probe,
+            },
+            Packet::Received {
+                time: UNIX_EPOCH + Duration::from_micros(1_500_001),
+                data: &reply,
+                probe: None,
+            },
+        ])[..]).unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].time, UNIX_EPOCH + Duration::from_micros(1_500_000));
+        assert_eq!(packets[0].data[8], 3);
+        assert_eq!(&packets[0].data[20..], &probe[..]);
+        assert_eq!(packets[1].time, UNIX_EPOCH + Duration::from_micros(1_500_001));
+        assert_eq!(packets[1].data, reply);
+    }
+
+    // Classic pcap header for the link type, in the big-endian byte order
+    // with nanoseconds or in the little-endian one with microseconds.
+    //
+    fn pcap(big_nanos: bool, linktype: u16, packets: &[&[u8]]) -> Vec<u8> {
+        let u32_bytes = |value: u32| if big_nanos { value.to_be_bytes() }
+            else { value.to_le_bytes() };
+
+        let magic = if big_nanos { PCAP_MAGIC_NANOS } else { PCAP_MAGIC_MICROS };
+
+        let mut result = vec![];
+        result.extend_from_slice(&u32_bytes(magic));
+        result.extend_from_slice(&[0; 16]);
+        result.extend_from_slice(&u32_bytes(linktype.into()));
+
+        for packet in packets {
+            result.extend_from_slice(&u32_bytes(2));
+            result.extend_from_slice(&u32_bytes(5));
+            result.extend_from_slice(&u32_bytes(packet.len() as u32));
+            result.extend_from_slice(&u32_bytes(packet.len() as u32));
+            result.extend_from_slice(packet);
+        }
+
+        result
+    }
+
+    #[test]
+    fn read_pcap_ethernet() {
+        let mut ipv4 = vec![0; 12];
+        ipv4.extend_from_slice(&[0x08, 0x00, 0x45, 1]);
+
+        let mut vlan = vec![0; 12];
+        vlan.extend_from_slice(&[0x81, 0x00, 0, 1, 0x08, 0x00, 0x45, 2]);
+
+        let mut arp = vec![0; 12];
+        arp.extend_from_slice(&[0x08, 0x06, 0, 1]);
+
+        let packets = read(&pcap(
+            false,
+            LINKTYPE_ETHERNET,
+            &[&ipv4, &vlan, &arp],
+        )[..]).unwrap();
+
+        assert_eq!(packets, vec![
+            Captured {
+                time: UNIX_EPOCH + Duration::new(2, 5000),
+                data: vec![0x45, 1],
+            },
+            Captured {
+                time: UNIX_EPOCH + Duration::new(2, 5000),
+                data: vec![0x45, 2],
+            },
+        ]);
+    }
+
+    #[test]
+    fn read_pcap_nanoseconds() {
+        let packets = read(&pcap(true, LINKTYPE_RAW, &[&[0x45, 1]])[..])
+            .unwrap();
+
+        assert_eq!(packets[0].time, UNIX_EPOCH + Duration::new(2, 5));
+    }
+
+    #[test]
+    fn read_unsupported() {
+        assert!(read(&pcap(false, 105, &[&[0x45]])[..]).is_err());
+        assert!(read(&b"not a capture"[..]).is_err());
+    }
+
+    #[test]
+    fn read_truncated() {
+        let data = pcap(false, LINKTYPE_RAW, &[&[0x45, 1]]);
+
+        assert!(read(&data[..(data.len() - 1)]).is_err());
+    }
+}
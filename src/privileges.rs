@@ -0,0 +1,160 @@
+use crate::error::Error;
+
+// Version 3 of the capabilities ABI, see capget(2).
+//
+#[cfg(target_os = "linux")]
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+// Permanently gives up everything the process was started with to be able to
+// open a raw socket: the real user and group IDs are restored (reverting a
+// setuid or setgid installation), supplementary groups are cleared, all
+// capabilities are dropped and the process can not gain new privileges by
+// executing other programs. Sockets opened before remain usable.
+//
+pub fn drop_privileges() -> Result<(), Error> {
+    let uid = unsafe { libc::getuid() };
+    let gid = unsafe { libc::getgid() };
+
+    if unsafe { libc::geteuid() } == 0 {
+        check(unsafe { libc::setgroups(0, std::ptr::null()) })?;
+    }
+
+    check(unsafe { libc::setresgid(gid, gid, gid) })?;
+    check(unsafe { libc::setresuid(uid, uid, uid) })?;
+
+    drop_capabilities()
+}
+
+#[cfg(target_os = "linux")]
+fn drop_capabilities() -> Result<(), Error> {
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+
+    let data = [CapUserData::default(); 2];
+
+    if unsafe { libc::syscall(
+        libc::SYS_capset,
+        &mut header as *mut CapUserHeader,
+        data.as_ptr(),
+    ) } != 0 {
+        return Err(Error::last_os_error())
+    }
+
+    check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_capabilities() -> Result<(), Error> {
+    Ok(())
+}
+
+fn check(result: libc::c_int) -> Result<(), Error> {
+    if result == 0 { Ok(()) } else { Err(Error::last_os_error()) }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::{env, process::Command, thread};
+
+    const CHILD: &str = "RUSTRACEROUTE_PRIVILEGES_CHILD";
+    const NOBODY: u32 = 65534;
+
+    fn ids() -> ([libc::uid_t; 3], [libc::gid_t; 3]) {
+        let (mut uids, mut gids) = ([0; 3], [0; 3]);
+
+        unsafe {
+            libc::getresuid(&mut uids[0], &mut uids[1], &mut uids[2]);
+            libc::getresgid(&mut gids[0], &mut gids[1], &mut gids[2]);
+        }
+
+        (uids, gids)
+    }
+
+    // The capabilities and the no-new-privileges flag belong to the thread,
+    // so the other tests keep theirs. The IDs of the test process are all the
+    // real ones already, and they stay so.
+    //
+    #[test]
+    fn drop_privileges() {
+        thread::spawn(|| {
+            let (uids, gids) = ids();
+
+            super::drop_privileges().unwrap();
+
+            assert_eq!(ids(), ([uids[0]; 3], [gids[0]; 3]));
+
+            let mut header = CapUserHeader {
+                version: LINUX_CAPABILITY_VERSION_3,
+                pid: 0,
+            };
+
+            let mut data = [CapUserData { effective: 1, permitted: 1, inheritable: 1 }; 2];
+
+            assert_eq!(unsafe { libc::syscall(
+                libc::SYS_capget,
+                &mut header as *mut CapUserHeader,
+                data.as_mut_ptr(),
+            ) }, 0);
+
+            for data in &data {
+                assert_eq!((data.effective, data.permitted, data.inheritable), (0, 0, 0));
+            }
+
+            assert_eq!(unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) }, 1);
+        }).join().unwrap();
+    }
+
+    // The IDs are those of the whole process, so an unprivileged user is
+    // tried in a process of its own, which is the test binary running only
+    // `unprivileged_child`.
+    //
+    #[test]
+    fn unprivileged() {
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "privileges::tests::unprivileged_child"])
+            .args(["--test-threads", "1", "--quiet"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    }
+
+    #[test]
+    fn unprivileged_child() {
+        if env::var_os(CHILD).is_none() {
+            return
+        }
+
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!(unsafe { libc::setresgid(NOBODY, NOBODY, NOBODY) }, 0);
+            assert_eq!(unsafe { libc::setresuid(NOBODY, NOBODY, NOBODY) }, 0);
+        }
+
+        let (uids, gids) = ids();
+
+        super::drop_privileges().unwrap();
+
+        assert_eq!(ids(), (uids, gids));
+        assert_eq!(ids(), ([uids[0]; 3], [gids[0]; 3]));
+    }
+}
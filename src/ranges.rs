@@ -0,0 +1,339 @@
+use crate::trie::{Prefix, PrefixTrie};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    net::IpAddr,
+};
+
+// Networks of cloud providers and peering LANs of Internet exchanges, loaded
+// from the range files the providers publish and from PeeringDB dumps, so
+// that the lookups work without access to any service. Each prefix has a tag
+// such as "AWS us-east-1" or "IXP DE-CIX Frankfurt". A prefix listed in
+// several files keeps the tag of the first one.
+//
+#[derive(Default)]
+pub struct RangeDatabase {
+    trie: PrefixTrie<String>,
+}
+
+impl RangeDatabase {
+    // Adds the prefixes of one file, which is any of
+    //
+    // * the AWS ip-ranges.json,
+    // * the Google Cloud cloud.json,
+    // * an Azure Service Tags file,
+    // * a PeeringDB dump with its ix, ixlan and ixpfx tables, or the ixpfx
+    //   objects alone with the names of the exchanges in them.
+    //
+    pub fn add<R: Read>(&mut self, input: R) -> io::Result<()> {
+        let document: Value = serde_json::from_reader(input)
+            .map_err(|error| invalid(&error.to_string()))?;
+
+        let count = if document.get("ixpfx").is_some() {
+            self.add_peeringdb(&document)?
+        }
+        else if let Some(values) = document.get("values") {
+            self.add_azure(values)?
+        }
+        else if let Some(prefixes) = document.get("prefixes") {
+            self.add_aws(prefixes, document.get("ipv6_prefixes"))?
+                + self.add_gcp(prefixes)?
+        }
+        else if let Some(data) = document.get("data") {
+            self.add_ixpfx(data, &HashMap::new())?
+        }
+        else {
+            return Err(invalid("unknown format"))
+        };
+
+        match count {
+            0 => Err(invalid("no prefixes found")),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    pub fn insert(&mut self, prefix: Prefix, tag: String) {
+        if self.trie.get_mut(prefix).is_none() {
+            self.trie.insert(prefix, tag);
+        }
+    }
+
+    // The tag of the most specific prefix containing the address.
+    //
+    pub fn lookup(&self, address: IpAddr) -> Option<&str> {
+        self.trie.lookup(address).map(|(_, tag)| tag.as_str())
+    }
+
+    // {"prefixes": [{"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2",
+    // "service": "AMAZON", ...}], "ipv6_prefixes": [{"ipv6_prefix": ...}]},
+    // with every prefix once for each service using it.
+    //
+    fn add_aws(&mut self, ipv4: &Value, ipv6: Option<&Value>) -> io::Result<usize> {
+        let entries = ipv4.as_array().into_iter()
+            .chain(ipv6.and_then(Value::as_array))
+            .flatten();
+
+        let mut count = 0;
+
+        for entry in entries {
+            let prefix = match text(entry, "ip_prefix").or_else(|| text(entry, "ipv6_prefix")) {
+                Some(prefix) => parse(prefix)?,
+                None => continue,
+            };
+
+            let tag = match text(entry, "region") {
+                Some("GLOBAL") | None => "AWS".to_string(),
+                Some(region) => format!("AWS {}", region),
+            };
+
+            self.insert(prefix, tag);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    // {"prefixes": [{"ipv4Prefix": "34.1.208.0/20", "service": "Google
+    // Cloud", "scope": "africa-south1"}, {"ipv6Prefix": ...}]}
+    //
+    fn add_gcp(&mut self, prefixes: &Value) -> io::Result<usize> {
+        let mut count = 0;
+
+        for entry in prefixes.as_array().into_iter().flatten() {
+            let prefix = match text(entry, "ipv4Prefix").or_else(|| text(entry, "ipv6Prefix")) {
+                Some(prefix) => parse(prefix)?,
+                None => continue,
+            };
+
+            let tag = match text(entry, "scope") {
+                Some("") | None => "GCP".to_string(),
+                Some(scope) => format!("GCP {}", scope),
+            };
+
+            self.insert(prefix, tag);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    // {"values": [{"name": "AzureCloud.eastus", "properties": {"region":
+    // "eastus", "addressPrefixes": ["13.68.128.0/17", ...]}}]}. The same
+    // prefixes are in the tags of the services and the regions, the regional
+    // tags go first so that the prefixes get their regions.
+    //
+    fn add_azure(&mut self, values: &Value) -> io::Result<usize> {
+        let mut values: Vec<(&str, &Value)> = values.as_array().into_iter()
+            .flatten()
+            .filter_map(|value| value.get("properties"))
+            .map(|properties| (text(properties, "region").unwrap_or(""), properties))
+            .collect();
+
+        values.sort_by_key(|(region, _)| region.is_empty());
+
+        let mut count = 0;
+
+        for (region, properties) in values {
+            let tag = match region {
+                "" => "Azure".to_string(),
+                region => format!("Azure {}", region),
+            };
+
+            let prefixes = properties.get("addressPrefixes")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str);
+
+            for prefix in prefixes {
+                self.insert(parse(prefix)?, tag.clone());
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    // {"ix": {"data": [{"id": 31, "name": "DE-CIX Frankfurt", ...}]},
+    // "ixlan": {"data": [{"id": 31, "ix_id": 31, ...}]}, "ixpfx": {"data":
+    // [{"ixlan_id": 31, "prefix": "80.81.192.0/21", ...}]}, ...}
+    //
+    fn add_peeringdb(&mut self, dump: &Value) -> io::Result<usize> {
+        let table = |name| dump.get(name)
+            .and_then(|table| table.get("data"))
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        let names: HashMap<u64, &str> = table("ix").iter()
+            .filter_map(|ix| Some((ix.get("id")?.as_u64()?, text(ix, "name")?)))
+            .collect();
+
+        let lans: HashMap<u64, &str> = table("ixlan").iter()
+            .filter_map(|lan| Some((
+                lan.get("id")?.as_u64()?,
+                *names.get(&lan.get("ix_id")?.as_u64()?)?,
+            )))
+            .collect();
+
+        self.add_ixpfx(&dump["ixpfx"]["data"], &lans)
+    }
+
+    // [{"ixlan_id": 31, "prefix": "80.81.192.0/21", ...}], the name of the
+    // exchange found by the peering LAN or in the object itself.
+    //
+    fn add_ixpfx(&mut self, data: &Value, lans: &HashMap<u64, &str>) -> io::Result<usize> {
+        let mut count = 0;
+
+        for entry in data.as_array().into_iter().flatten() {
+            let prefix = match text(entry, "prefix") {
+                Some(prefix) => parse(prefix)?,
+                None => continue,
+            };
+
+            let name = entry.get("ixlan_id")
+                .and_then(Value::as_u64)
+                .and_then(|id| lans.get(&id).copied())
+                .or_else(|| text(entry, "name"));
+
+            let tag = match name {
+                Some(name) => format!("IXP {}", name),
+                None => "IXP".to_string(),
+            };
+
+            self.insert(prefix, tag);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+fn text<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key)?.as_str()
+}
+
+fn parse(prefix: &str) -> io::Result<Prefix> {
+    prefix.parse().map_err(|_| invalid(&format!("invalid prefix {}", prefix)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("range file: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database(files: &[&str]) -> RangeDatabase {
+        let mut database = RangeDatabase::default();
+
+        for file in files {
+            database.add(file.as_bytes()).unwrap();
+        }
+
+        database
+    }
+
+    fn lookup<'a>(database: &'a RangeDatabase, address: &str) -> Option<&'a str> {
+        database.lookup(address.parse().unwrap())
+    }
+
+    #[test]
+    fn aws() {
+        let database = database(&[r#"{
+            "syncToken": "1700000000",
+            "prefixes": [
+                {"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "AMAZON"},
+                {"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "S3"},
+                {"ip_prefix": "52.94.0.0/22", "region": "GLOBAL", "service": "AMAZON"}
+            ],
+            "ipv6_prefixes": [
+                {"ipv6_prefix": "2600:1f18::/33", "region": "us-east-1", "service": "EC2"}
+            ]
+        }"#]);
+
+        assert_eq!(database.len(), 3);
+        assert_eq!(lookup(&database, "3.5.141.1"), Some("AWS ap-northeast-2"));
+        assert_eq!(lookup(&database, "52.94.1.1"), Some("AWS"));
+        assert_eq!(lookup(&database, "2600:1f18::1"), Some("AWS us-east-1"));
+        assert_eq!(lookup(&database, "8.8.8.8"), None);
+    }
+
+    #[test]
+    fn gcp() {
+        let database = database(&[r#"{
+            "prefixes": [
+                {"ipv4Prefix": "34.1.208.0/20", "service": "Google Cloud", "scope": "africa-south1"},
+                {"ipv6Prefix": "2600:1900:8000::/44", "service": "Google Cloud", "scope": "us-central1"}
+            ]
+        }"#]);
+
+        assert_eq!(lookup(&database, "34.1.208.1"), Some("GCP africa-south1"));
+        assert_eq!(lookup(&database, "2600:1900:8000::1"), Some("GCP us-central1"));
+    }
+
+    #[test]
+    fn azure() {
+        let database = database(&[r#"{
+            "cloud": "Public",
+            "values": [
+                {"name": "AzureCloud", "properties": {"region": "",
+                    "addressPrefixes": ["13.64.0.0/11", "13.68.128.0/17"]}},
+                {"name": "AzureCloud.eastus", "properties": {"region": "eastus",
+                    "addressPrefixes": ["13.68.128.0/17", "2603:1030::/45"]}}
+            ]
+        }"#]);
+
+        assert_eq!(lookup(&database, "13.68.128.1"), Some("Azure eastus"));
+        assert_eq!(lookup(&database, "13.64.0.1"), Some("Azure"));
+        assert_eq!(lookup(&database, "2603:1030::1"), Some("Azure eastus"));
+    }
+
+    #[test]
+    fn peeringdb() {
+        let database = database(&[
+            r#"{
+                "ix": {"data": [{"id": 31, "name": "DE-CIX Frankfurt"}]},
+                "ixlan": {"data": [{"id": 7, "ix_id": 31}]},
+                "ixpfx": {"data": [
+                    {"ixlan_id": 7, "protocol": "IPv4", "prefix": "80.81.192.0/21"},
+                    {"ixlan_id": 7, "protocol": "IPv6", "prefix": "2001:7f8::/64"},
+                    {"ixlan_id": 8, "protocol": "IPv4", "prefix": "192.0.2.0/24"}
+                ]}
+            }"#,
+            r#"{"data": [
+                {"prefix": "80.81.192.0/21", "name": "Other"},
+                {"prefix": "198.51.100.0/24", "name": "Example IX"}
+            ]}"#,
+        ]);
+
+        assert_eq!(lookup(&database, "80.81.193.1"), Some("IXP DE-CIX Frankfurt"));
+        assert_eq!(lookup(&database, "2001:7f8::1"), Some("IXP DE-CIX Frankfurt"));
+        assert_eq!(lookup(&database, "192.0.2.1"), Some("IXP"));
+        assert_eq!(lookup(&database, "198.51.100.1"), Some("IXP Example IX"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut database = RangeDatabase::default();
+
+        for file in &[
+            "not json",
+            r#"{"something": []}"#,
+            r#"{"prefixes": []}"#,
+            r#"{"prefixes": [{"ip_prefix": "3.5.140.0/33"}]}"#,
+        ] {
+            assert!(database.add(file.as_bytes()).is_err(), "{}", file);
+        }
+    }
+}
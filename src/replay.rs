@@ -0,0 +1,328 @@
+use crate::{
+    error::Error,
+    pcapng::Captured,
+    sockaddr_inx::SockaddrInx,
+    tracer::Tracer,
+    transport::Transport,
+};
+use std::{
+    cell::RefCell,
+    net::{IpAddr, Ipv4Addr},
+    time::SystemTime,
+};
+
+const ECHO_REQUEST: u8 = 8;
+
+// Transport which plays back the captured packets instead of using the
+// network. Every probe sent takes the place of the next captured one, the
+// time is the time of the capture, and the captured replies are received in
+// the order they have been captured.
+//
+pub struct Replay {
+    pub target: IpAddr,
+    pub ident: u16,
+    pub first_ttl: u8,
+    pub max_ttl: u8,
+    pub queries: u16,
+    probes: Vec<SystemTime>,
+    received: Vec<Captured>,
+    state: RefCell<State>,
+}
+
+struct State {
+    now: SystemTime,
+    next_probe: usize,
+    next_received: usize,
+}
+
+// Echo request parsed from the captured packet.
+//
+struct Probe {
+    time: SystemTime,
+    target: IpAddr,
+    ttl: u8,
+    ident: u16,
+}
+
+impl Replay {
+    // The probes are the echo requests to the same target with the same
+    // identifier as the first one, the trace parameters are guessed from
+    // them. Everything else ICMP is what has been received.
+    //
+    pub fn new(packets: Vec<Captured>) -> Result<Self, Error> {
+        let mut probes = vec![];
+        let mut received = vec![];
+
+        for packet in packets {
+            match icmp_type(&packet.data) {
+                Some(ECHO_REQUEST) => probes.extend(probe(&packet)),
+                Some(_) => received.push(packet),
+                None => {}
+            }
+        }
+
+        let first = probes.first()
+            .ok_or_else(|| Error::BadInput("no probes captured".to_string()))?;
+
+        let (target, ident) = (first.target, first.ident);
+
+        probes.retain(|probe| probe.target == target && probe.ident == ident);
+
+        let first_ttl = probes[0].ttl;
+
+        Ok(Self {
+            target,
+            ident,
+            first_ttl,
+            max_ttl: probes.iter().map(|probe| probe.ttl).max()
+                .unwrap_or(first_ttl),
+            queries: probes.iter().take_while(|probe| probe.ttl == first_ttl)
+                .count() as u16,
+            state: RefCell::new(State {
+                now: probes[0].time,
+                next_probe: 0,
+                next_received: 0,
+            }),
+            probes: probes.iter().map(|probe| probe.time).collect(),
+            received,
+        })
+    }
+
+    pub fn tracer(&self) -> Tracer {
+        Tracer::new(self.target)
+            .ident(self.ident)
+            .first_ttl(self.first_ttl)
+            .max_ttl(self.max_ttl)
+            .queries(self.queries)
+    }
+}
+
+impl Transport for Replay {
+    fn now(&self) -> SystemTime {
+        self.state.borrow().now
+    }
+
+    fn set_ttl(&self, _ttl: u8) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_tos(&self, _tos: u8) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Probes sent after the captured ones are over are sent at the current
+    // time and are never replied to.
+    //
+    fn send_to(&self, _message: &[u8], _host: IpAddr) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+
+        if let Some(time) = self.probes.get(state.next_probe) {
+            state.now = *time;
+            state.next_probe += 1;
+        }
+
+        Ok(())
+    }
+
+    // The tracer sends the next probe only when it is done with this one, so
+    // what has been captured after the next probe is received while waiting
+    // for the later ones.
+    //
+    fn recv_until(
+        &self,
+        buffer: &mut [u8],
+        deadline: SystemTime,
+    ) -> Result<Option<(usize, SockaddrInx)>, Error> {
+        let mut state = self.state.borrow_mut();
+
+        let limit = match self.probes.get(state.next_probe) {
+            Some(next_probe) => deadline.min(*next_probe),
+            None => deadline,
+        };
+
+        let packet = match self.received.get(state.next_received) {
+            Some(packet) if packet.time <= limit => packet,
+            _ => {
+                state.now = state.now.max(deadline);
+                return Ok(None)
+            }
+        };
+
+        state.next_received += 1;
+        state.now = state.now.max(packet.time);
+
+        let size = packet.data.len().min(buffer.len());
+        buffer[..size].copy_from_slice(&packet.data[..size]);
+
+        Ok(Some((size, SockaddrInx::from_ip_addr(source(&packet.data)))))
+    }
+}
+
+fn icmp_type(packet: &[u8]) -> Option<u8> {
+    let header_length = usize::from(packet.first()? & 0x0F) * 4;
+
+    if header_length < 20 || packet.len() < 20 ||
+        packet[9] != libc::IPPROTO_ICMP as u8
+    {
+        return None
+    }
+
+    packet.get(header_length).copied()
+}
+
+fn probe(packet: &Captured) -> Option<Probe> {
+    let data = &packet.data;
+    let header_length = usize::from(data[0] & 0x0F) * 4;
+    let icmp = data.get(header_length..(header_length + 8))?;
+
+    Some(Probe {
+        time: packet.time,
+        target: IpAddr::V4(Ipv4Addr::new(data[16], data[17], data[18], data[19])),
+        ttl: data[8],
+        ident: u16::from_be_bytes([icmp[4], icmp[5]]),
+    })
+}
+
+fn source(packet: &[u8]) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::{Event, Observer, Packet},
+        pcapng::ipv4_packet,
+        request::Request,
+        trace::ProbeStatus,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const IDENT: u16 = 0x1234;
+
+    const LOCAL:  Ipv4Addr = Ipv4Addr::new(192, 0, 2, 100);
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const TARGET: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn probe(micros: u64, ttl: u8, sequence: u16) -> Captured {
+        let message = Request::new(IDENT, sequence).to_vec();
+
+        Captured {
+            time: at(micros),
+            data: ipv4_packet(LOCAL, TARGET, ttl, &message),
+        }
+    }
+
+    fn time_exceeded(micros: u64, ident: u16, sequence: u16) -> Captured {
+        let quoted = Request::new(ident, sequence).to_vec();
+
+        let mut message = vec![11, 0, 0, 0, 0, 0, 0, 0];
+        message.extend(ipv4_packet(LOCAL, TARGET, 1, &quoted));
+
+        Captured {
+            time: at(micros),
+            data: ipv4_packet(ROUTER, LOCAL, 250, &message),
+        }
+    }
+
+    fn echo_reply(micros: u64, sequence: u16) -> Captured {
+        let mut message = Request::new(IDENT, sequence).to_vec();
+        message[0] = 0;
+
+        Captured {
+            time: at(micros),
+            data: ipv4_packet(TARGET, LOCAL, 60, &message),
+        }
+    }
+
+    fn replay() -> Replay {
+        Replay::new(vec![
+            probe(0, 1, 0),
+            time_exceeded(1000, IDENT, 0),
+            probe(2000, 1, 1),
+            // Reply to somebody else.
+            time_exceeded(3000, IDENT + 1, 1),
+            probe(1_002_000, 2, 2),
+            echo_reply(1_002_500, 2),
+            probe(1_003_000, 2, 3),
+            echo_reply(1_003_500, 3),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn parameters() {
+        let replay = replay();
+
+        assert_eq!(replay.target, IpAddr::V4(TARGET));
+        assert_eq!(replay.ident, IDENT);
+        assert_eq!(replay.first_ttl, 1);
+        assert_eq!(replay.max_ttl, 2);
+        assert_eq!(replay.queries, 2);
+    }
+
+    #[test]
+    fn no_probes() {
+        assert!(matches!(
+            Replay::new(vec![echo_reply(0, 0)]),
+            Err(Error::BadInput(_)),
+        ));
+    }
+
+    #[test]
+    fn trace() {
+        let replay = replay();
+        let mut packets = vec![];
+
+        struct Packets<'a>(&'a mut Vec<Option<(u8, u16)>>);
+
+        impl Observer for Packets<'_> {
+            fn on_event(&mut self, _event: &Event) {}
+
+            fn on_packet(&mut self, packet: &Packet<'_>) {
+                if let Packet::Received { probe, .. } = packet {
+                    self.0.push(*probe);
+                }
+            }
+        }
+
+        let trace = replay.tracer()
+            .trace_with(&replay, &mut Packets(&mut packets)).unwrap();
+
+        assert_eq!(trace.started, at(0));
+        assert!(trace.reached());
+        assert_eq!(trace.hops.len(), 2);
+
+        let probes = &trace.hops[0].probes;
+
+        assert_eq!(probes[0].sent, at(0));
+        assert_eq!(probes[0].rtt(), Some(Duration::from_micros(1000)));
+        assert_eq!(probes[0].response().unwrap().source, IpAddr::V4(ROUTER));
+        assert_eq!(probes[1].sent, at(2000));
+        assert!(matches!(probes[1].status, ProbeStatus::Timeout));
+
+        let probes = &trace.hops[1].probes;
+
+        assert_eq!(probes[0].rtt(), Some(Duration::from_micros(500)));
+        assert_eq!(probes[1].rtt(), Some(Duration::from_micros(500)));
+
+        assert_eq!(packets, vec![Some((1, 0)), None, Some((2, 2)), Some((2, 3))]);
+    }
+
+    #[test]
+    fn short_wait() {
+        let replay = replay();
+
+        let trace = replay.tracer().wait(Duration::from_micros(500))
+            .trace(&replay).unwrap();
+
+        assert!(matches!(trace.hops[0].probes[0].status, ProbeStatus::Timeout));
+        assert_eq!(
+            trace.hops[1].probes[0].rtt(),
+            Some(Duration::from_micros(500)),
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use crate::{
+    annotations::{Annotations, Boundaries, Responder},
+    csv::{self, Csv},
+    json::{self, milliseconds},
+    options::{Format, Options},
+    stats::{HopStatistics, Statistics, INTERVAL},
+    timestamp,
+};
+use rustraceroute::{event::Tee, Error, Event, Tracer, Transport};
+use serde_json::{json, Value};
+use std::{io, net::IpAddr, thread, time::{Duration, SystemTime}};
+
+const PERCENTILES: [u8; 3] = [50, 90, 99];
+
+const HOST_WIDTH: usize = 32;
+
+// Summary of every hop after probing all of them the given number of times,
+// as mtr --report prints it.
+//
+pub struct Report<'a> {
+    options: &'a Options,
+    address: IpAddr,
+    started: SystemTime,
+    statistics: Statistics,
+    annotations: Annotations,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(
+        options: &'a Options,
+        address: IpAddr,
+        annotations: Annotations,
+    ) -> Self {
+        Self {
+            options,
+            address,
+            started: SystemTime::now(),
+            statistics: Statistics::new(options.first_ttl, options.max_ttl),
+            annotations,
+        }
+    }
+
+    // Probes every TTL up to the destination once per round.
+    //
+    pub fn run<T: Transport + ?Sized>(
+        mut self,
+        tracer: &Tracer,
+        transport: &T,
+    ) -> Result<(), Error> {
+        let format = self.options.format();
+
+        if !matches!(format, Format::Text | Format::Json | Format::Csv | Format::Tsv) {
+            return Err(Error::BadInput(
+                "the report can only be printed as text, json, csv or tsv"
+                    .to_string(),
+            ))
+        }
+
+        self.started = transport.now();
+
+        let mut sequence: u16 = 0;
+
+        for round in 0..self.options.count {
+            if round > 0 {
+                thread::sleep(INTERVAL);
+            }
+
+            let mut ttl = Some(self.statistics.first_ttl());
+
+            while let Some(current) = ttl {
+                let names = &self.annotations.names;
+
+                tracer.probe_ttl(
+                    transport,
+                    &mut Tee(
+                        &mut self.statistics,
+                        &mut |event: &Event| names.prefetch(event),
+                    ),
+                    current,
+                    sequence,
+                )?;
+
+                sequence = sequence.wrapping_add(1);
+                ttl = self.statistics.next_ttl(current);
+            }
+        }
+
+        // The warnings are a part of the JSON document.
+        //
+        if format != Format::Json {
+            for warning in self.warnings() {
+                eprintln!("rustraceroute: warning: {}", warning);
+            }
+        }
+
+        match format {
+            Format::Json => println!("{}", self.json()),
+            Format::Csv | Format::Tsv => {
+                let separator = match format {
+                    Format::Csv => ',',
+                    _ => '\t',
+                };
+
+                let mut csv = Csv::new(
+                    io::stdout(),
+                    separator,
+                    self.options.host(),
+                    self.annotations.clone(),
+                );
+
+                for row in self.rows() {
+                    csv.write_row(&row)?;
+                }
+            }
+            _ => print!("{}", self.text()),
+        }
+
+        Ok(())
+    }
+
+    fn text(&self) -> String {
+        let mut header = vec!["Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev"]
+            .into_iter().map(str::to_string).collect::<Vec<_>>();
+
+        if self.options.jitter {
+            header.push("Jttr".to_string());
+        }
+
+        if self.options.percentiles {
+            header.extend(PERCENTILES.iter().map(|percent| format!("P{}", percent)));
+        }
+
+        let mut result = format!(
+            "Start: {}\nHOST: {:width$} {}\n",
+            timestamp::rfc3339(self.started),
+            format!("{} ({})", self.options.host(), self.address),
+            header.iter().map(|column| format!("{:>6}", column))
+                .collect::<Vec<_>>().join(" "),
+            width = HOST_WIDTH,
+        );
+
+        let mut boundaries = Boundaries::default();
+
+        for (ttl, hop) in self.statistics.hops() {
+            let mut host = match hop.responders.first() {
+                Some(address) => self.host(*address, &mut boundaries),
+                None => "???".to_string(),
+            };
+
+            if let Some(annotation) = &hop.annotation {
+                host = format!("{} {}", host, annotation);
+            }
+
+            let mut columns = vec![
+                format!("{:>5.1}%", hop.loss()),
+                hop.sent().to_string(),
+            ];
+
+            columns.extend(self.durations(hop).iter()
+                .map(|duration| text_milliseconds(*duration)));
+
+            let line = format!(
+                "{:3}.|-- {:width$} {}",
+                ttl,
+                host,
+                columns.iter().map(|column| format!("{:>6}", column))
+                    .collect::<Vec<_>>().join(" "),
+                width = HOST_WIDTH - 2,
+            );
+
+            result.push_str(line.trim_end());
+            result.push('\n');
+
+            // Every other responder of the hop gets a line of its own.
+            //
+            for responder in hop.responders.iter().skip(1) {
+                result.push_str(&format!(
+                    "    |   {}\n",
+                    self.host(*responder, &mut boundaries),
+                ));
+            }
+        }
+
+        result
+    }
+
+    fn json(&self) -> Value {
+        let mut result = json::metadata(self.options, self.address, self.started);
+
+        result["rounds"] = self.options.count.into();
+        result["warnings"] = self.warnings().into();
+        result["hops"] = self.statistics.hops().map(|(ttl, hop)| {
+            let mut value = json!({
+                "ttl": ttl,
+                "responders": hop.responders.iter()
+                    .map(|address| address.to_string())
+                    .collect::<Value>(),
+                "hostnames": hop.responders.iter()
+                    .map(|address| {
+                        self.annotations.names.wait(*address)
+                            .map(|name| name.to_string())
+                    })
+                    .collect::<Value>(),
+                "asns": hop.responders.iter()
+                    .map(|address| {
+                        self.annotations.origin(*address)
+                            .map(|origin| origin.asns.to_vec())
+                    })
+                    .collect::<Value>(),
+                "prefixes": hop.responders.iter()
+                    .map(|address| {
+                        self.annotations.origin(*address)
+                            .map(|origin| origin.prefix.to_string())
+                    })
+                    .collect::<Value>(),
+                "locations": hop.responders.iter()
+                    .map(|address| {
+                        self.annotations.location(*address)
+                            .map(|location| json::location(&location))
+                    })
+                    .collect::<Value>(),
+                "hints": hop.responders.iter()
+                    .map(|address| {
+                        self.annotations.hint(*address).map(|hint| json::hint(&hint))
+                    })
+                    .collect::<Value>(),
+                "tags": hop.responders.iter()
+                    .map(|address| self.annotations.tags(*address))
+                    .collect::<Value>(),
+                "annotations": hop.annotation.iter().cloned().collect::<Value>(),
+                "sent": hop.sent(),
+                "received": hop.received(),
+                "loss_pct": hop.loss(),
+                "last_ms": json_milliseconds(hop.last()),
+                "avg_ms": json_milliseconds(hop.mean()),
+                "best_ms": json_milliseconds(hop.best()),
+                "worst_ms": json_milliseconds(hop.worst()),
+                "stddev_ms": json_milliseconds(hop.standard_deviation()),
+            });
+
+            if self.options.jitter {
+                value["jitter_ms"] = json_milliseconds(hop.jitter());
+            }
+
+            if self.options.percentiles {
+                for percent in PERCENTILES.iter() {
+                    value[format!("p{}_ms", percent)] =
+                        json_milliseconds(hop.percentile(*percent));
+                }
+            }
+
+            value
+        }).collect();
+
+        result
+    }
+
+    // The header first, then one row per hop.
+    //
+    fn rows(&self) -> Vec<Vec<String>> {
+        let mut header = vec![
+            "target",
+            "ttl",
+            "responders",
+            "hostname",
+            "asn",
+            "country",
+            "city",
+            "latitude",
+            "longitude",
+            "tags",
+            "loss_pct",
+            "sent",
+            "received",
+            "last_ms",
+            "avg_ms",
+            "best_ms",
+            "worst_ms",
+            "stddev_ms",
+        ].into_iter().map(str::to_string).collect::<Vec<_>>();
+
+        if self.options.jitter {
+            header.push("jitter_ms".to_string());
+        }
+
+        if self.options.percentiles {
+            header.extend(PERCENTILES.iter()
+                .map(|percent| format!("p{}_ms", percent)));
+        }
+
+        let mut rows = vec![header];
+
+        for (ttl, hop) in self.statistics.hops() {
+            let mut row = vec![
+                self.options.host().to_string(),
+                ttl.to_string(),
+                hop.responders.iter().map(|address| address.to_string())
+                    .collect::<Vec<_>>().join(" "),
+                hop.responders.first()
+                    .and_then(|address| self.annotations.names.wait(*address))
+                    .map(|name| name.to_string())
+                    .unwrap_or_default(),
+                hop.responders.first()
+                    .map(|address| csv::asn(&self.annotations, *address))
+                    .unwrap_or_default(),
+            ];
+
+            row.extend(csv::location_fields(
+                &hop.responders.first()
+                    .and_then(|address| self.annotations.location(*address))
+                    .unwrap_or_default(),
+            ));
+
+            row.extend(vec![
+                hop.responders.first()
+                    .map(|address| csv::tags(&self.annotations, *address))
+                    .unwrap_or_default(),
+                format!("{:.1}", hop.loss()),
+                hop.sent().to_string(),
+                hop.received().to_string(),
+            ]);
+
+            row.extend(self.durations(hop).iter().map(|duration| {
+                duration.map(|duration| milliseconds(duration).to_string())
+                    .unwrap_or_default()
+            }));
+
+            rows.push(row);
+        }
+
+        rows
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        let hops = self.statistics.hops().flat_map(|(ttl, hop)| {
+            hop.responders.iter().map(move |address| Responder {
+                ttl,
+                address: *address,
+                rtt: hop.best(),
+                return_hops: hop.return_hops.get(address).copied(),
+            })
+        });
+
+        self.annotations.warnings(self.statistics.first_ttl(), hops)
+    }
+
+    // The name of the responder, or its address if it has none, followed with
+    // the kinds of special address space it is in, its origin ASes and its
+    // location.
+    //
+    fn host(&self, address: IpAddr, boundaries: &mut Boundaries) -> String {
+        let mut result = match self.annotations.names.wait(address) {
+            Some(name) => name.to_string(),
+            None => address.to_string(),
+        };
+
+        for tag in self.annotations.tags(address) {
+            result = format!("{} [{}]", result, tag);
+        }
+
+        if let Some(origin) = self.annotations.origin(address) {
+            result = format!("{} [{}]", result, boundaries.label(origin));
+        }
+
+        if let Some(place) = self.annotations.place(address) {
+            result = format!("{} [{}]", result, place);
+        }
+
+        if let Some(place) = self.annotations.hinted_place(address) {
+            result = format!("{} [{}]", result, place);
+        }
+
+        result
+    }
+
+    // The RTT columns, including the optional ones.
+    //
+    fn durations(&self, hop: &HopStatistics) -> Vec<Option<Duration>> {
+        let mut result = vec![
+            hop.last(),
+            hop.mean(),
+            hop.best(),
+            hop.worst(),
+            hop.standard_deviation(),
+        ];
+
+        if self.options.jitter {
+            result.push(hop.jitter());
+        }
+
+        if self.options.percentiles {
+            result.extend(PERCENTILES.iter().map(|percent| hop.percentile(*percent)));
+        }
+
+        result
+    }
+}
+
+fn text_milliseconds(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1}", duration.as_secs_f64() * 1000.0),
+        None => String::new(),
+    }
+}
+
+fn json_milliseconds(duration: Option<Duration>) -> Value {
+    duration.map(milliseconds).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{reply_received, OTHER, ROUTER, TARGET},
+        names::Names,
+    };
+    use clap::Clap;
+    use rustraceroute::{
+        response::{ECHO_REPLY, TIME_EXCEEDED},
+        Event,
+        Observer,
+        sockaddr_inx::SockaddrInx,
+    };
+    use std::{cell::Cell, time::UNIX_EPOCH};
+
+    // Nothing ever replies, the time only runs until the deadlines.
+    //
+    struct Silence(Cell<SystemTime>);
+
+    impl Transport for Silence {
+        fn now(&self) -> SystemTime {
+            self.0.get()
+        }
+
+        fn set_ttl(&self, _ttl: u8) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn set_tos(&self, _tos: u8) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn send_to(&self, _message: &[u8], _host: IpAddr) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn recv_until(
+            &self,
+            _buffer: &mut [u8],
+            deadline: SystemTime,
+        ) -> Result<Option<(usize, SockaddrInx)>, Error> {
+            self.0.set(deadline);
+            Ok(None)
+        }
+    }
+
+    fn options(extra: &[&str]) -> Options {
+        Options::parse_from(
+            ["rustraceroute", "--report", "-c", "2", "example.com"].iter()
+                .chain(extra),
+        )
+    }
+
+    fn report(options: &Options) -> Report<'_> {
+        let mut report = Report::new(
+            options,
+            TARGET,
+            Names::fixed(&[(OTHER, "other.example.net")]).into(),
+        );
+        report.started = UNIX_EPOCH;
+
+        for (ttl, source, type_, micros) in [
+            (1, ROUTER, TIME_EXCEEDED, 1000),
+            (1, OTHER, TIME_EXCEEDED, 3000),
+            (3, TARGET, ECHO_REPLY, 10_000),
+            (3, TARGET, ECHO_REPLY, 12_000),
+        ] {
+            report.statistics.on_event(
+                &reply_received(ttl, source, type_, micros),
+            );
+        }
+
+        for _ in 0..2 {
+            report.statistics.on_event(
+                &Event::ProbeTimedOut { ttl: 2, sequence: 0 },
+            );
+        }
+
+        report
+    }
+
+    #[test]
+    fn text() {
+        let options = options(&[]);
+
+        assert_eq!(report(&options).text(), concat!(
+            "Start: 1970-01-01T00:00:00.000000Z\n",
+            "HOST: example.com (198.51.100.1)       ",
+            " Loss%    Snt   Last    Avg   Best   Wrst  StDev\n",
+            "  1.|-- 192.0.2.1 [documentation]      ",
+            "  0.0%      2    3.0    2.0    1.0    3.0    1.4\n",
+            "    |   other.example.net [documentation]\n",
+            "  2.|-- ???                            ",
+            "100.0%      2\n",
+            "  3.|-- 198.51.100.1 [documentation]   ",
+            "  0.0%      2   12.0   11.0   10.0   12.0    1.4\n",
+        ));
+    }
+
+    #[test]
+    fn text_optional_columns() {
+        let options = options(&["--jitter", "--percentiles"]);
+        let text = report(&options).text();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines[1].ends_with("StDev   Jttr    P50    P90    P99"));
+        assert!(lines[2].ends_with("1.4    2.0    1.0    3.0    3.0"));
+    }
+
+    #[test]
+    fn json() {
+        let options = options(&["--jitter", "--percentiles"]);
+        let value = report(&options).json();
+
+        assert_eq!(value["rounds"], 2);
+        assert_eq!(value["hops"][0], json!({
+            "ttl": 1,
+            "responders": ["192.0.2.1", "192.0.2.2"],
+            "hostnames": [null, "other.example.net"],
+            "asns": [null, null],
+            "prefixes": [null, null],
+            "locations": [null, null],
+            "hints": [null, null],
+            "tags": [["documentation"], ["documentation"]],
+            "annotations": [],
+            "sent": 2,
+            "received": 2,
+            "loss_pct": 0.0,
+            "last_ms": 3.0,
+            "avg_ms": 2.0,
+            "best_ms": 1.0,
+            "worst_ms": 3.0,
+            "stddev_ms": 1.414,
+            "jitter_ms": 2.0,
+            "p50_ms": 1.0,
+            "p90_ms": 3.0,
+            "p99_ms": 3.0,
+        }));
+        assert_eq!(value["hops"][1]["avg_ms"], Value::Null);
+        assert_eq!(value["hops"][1]["loss_pct"], 100.0);
+    }
+
+    #[test]
+    fn csv() {
+        let options = options(&[]);
+        let rows = report(&options).rows();
+
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0][..5], ["target", "ttl", "responders", "hostname", "asn"]);
+        assert_eq!(rows[1], [
+            "example.com", "1", "192.0.2.1 192.0.2.2", "", "", "", "", "", "",
+            "documentation", "0.0", "2", "2", "3", "2", "1", "3", "1.414",
+        ]);
+        assert_eq!(rows[2][9..14], ["", "100.0", "2", "0", ""]);
+    }
+
+    #[test]
+    fn last_ttl_255() {
+        let options = Options::parse_from([
+            "rustraceroute", "--report", "-c", "1", "-f", "255", "-m", "255",
+            "example.com",
+        ]);
+        let tracer = Tracer::new(TARGET).first_ttl(255).max_ttl(255);
+
+        Report::new(&options, TARGET, Names::disabled().into())
+            .run(&tracer, &Silence(Cell::new(UNIX_EPOCH)))
+            .unwrap();
+    }
+}
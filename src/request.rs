@@ -16,16 +16,17 @@ impl Request {
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-
-        result.push(8); // type
-        result.push(0); // code
-        result.push(0); // checksum
-        result.push(0); // checksum
-        result.push(((self.ident & 0xFF00) >> 8) as u8);
-        result.push((self.ident & 0x00FF) as u8);
-        result.push(((self.sequence & 0xFF00) >> 8) as u8);
-        result.push((self.sequence & 0x00FF) as u8);
+        let mut result: Vec<u8> = vec![
+            8, // type
+            0, // code
+            0, // checksum
+            0, // checksum
+            ((self.ident & 0xFF00) >> 8) as u8,
+            (self.ident & 0x00FF) as u8,
+            ((self.sequence & 0xFF00) >> 8) as u8,
+            (self.sequence & 0x00FF) as u8,
+        ];
+
         result.append(&mut self.payload.clone());
 
         let checksum = checksum(&result);
@@ -37,9 +38,9 @@ impl Request {
     }
 }
 
-impl Into<Vec<u8>> for Request {
-    fn into(self) -> Vec<u8> {
-        self.to_vec()
+impl From<Request> for Vec<u8> {
+    fn from(request: Request) -> Self {
+        request.to_vec()
     }
 }
 
@@ -1,29 +1,155 @@
-use crate::{sockaddr_inx::SockaddrInx, request::Request};
-use std::net::IpAddr;
+use crate::{
+    extension::{self, Extension, MplsLabel},
+    sockaddr_inx::SockaddrInx,
+    request::Request,
+};
+use std::net::{IpAddr, Ipv4Addr};
 
-#[derive(Debug)]
+pub const ECHO_REPLY:    u8 = 0;
+pub const UNREACHABLE:   u8 = 3;
+pub const TIME_EXCEEDED: u8 = 11;
+
+// TTLs the operating systems of the routers and the hosts send their packets
+// with.
+//
+const INITIAL_TTLS: [u8; 3] = [64, 128, 255];
+
+#[derive(Clone, Debug)]
 pub struct Response {
     pub source: IpAddr,
+    pub reply_ttl: u8,
+    // Identification field of the IP header of the reply, which many routers
+    // take from a counter shared by all their interfaces.
+    pub ip_id: u16,
     pub type_: u8,
     pub code: u8,
     pub ident: u16,
     pub sequence: u16,
+    // Size of the ICMP message, without the IP header.
+    pub size: usize,
+    // TTL of the original request when it has reached the router which has
+    // replied with an error. Echo reply does not quote the request.
+    pub quoted_ttl: Option<u8>,
+    pub extensions: Vec<Extension>,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self {
+            source: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            reply_ttl: 0,
+            ip_id: 0,
+            type_: 0,
+            code: 0,
+            ident: 0,
+            sequence: 0,
+            size: 0,
+            quoted_ttl: None,
+            extensions: vec![],
+        }
+    }
 }
 
 impl Response {
     // TODO: parse IP header with length > 5 and additional options.
     //
     pub fn parse(source: &SockaddrInx, body: &[u8]) -> Option<Self> {
-        if body.len() < 2 * (20 /* IP header */ + 8 /* ICMP header */) {
+        if body.len() < 20 /* IP header */ + 8 /* ICMP header */ {
             return None
         }
 
+        // Echo reply carries the identifier and the sequence number in its
+        // own header, error messages carry them in the quoted original
+        // request.
+        //
+        let is_echo_reply = body[20] == ECHO_REPLY;
+
+        let offset = if is_echo_reply { 20 } else {
+            if body.len() < 2 * (20 /* IP header */ + 8 /* ICMP header */) {
+                return None
+            }
+
+            20 + 8 + 20
+        };
+
         Some(Self {
             source: source.to_ip_addr(),
+            reply_ttl: body[8],
+            ip_id: u16::from_be_bytes([body[4], body[5]]),
             type_: body[20],
             code:  body[21],
-            ident:    ((body[52] as u16) << 8) + (body[53] as u16),
-            sequence: ((body[54] as u16) << 8) + (body[55] as u16),
+            ident:    u16::from_be_bytes([body[offset + 4], body[offset + 5]]),
+            sequence: u16::from_be_bytes([body[offset + 6], body[offset + 7]]),
+            size: body.len() - 20,
+            quoted_ttl: if is_echo_reply { None } else { Some(body[20 + 8 + 8]) },
+            extensions: if is_echo_reply { vec![] } else {
+                extension::parse(&body[20..])
+            },
+        })
+    }
+
+    // The TTL the responder has most likely sent the reply with: the least of
+    // the usual initial TTLs which is not below the TTL the reply has come
+    // with. Unknown if the reply TTL is, as in the traces read from files
+    // without it.
+    //
+    pub fn initial_ttl(&self) -> Option<u8> {
+        match self.reply_ttl {
+            0 => None,
+            reply_ttl => INITIAL_TTLS.iter().copied()
+                .find(|initial_ttl| *initial_ttl >= reply_ttl),
+        }
+    }
+
+    // Length of the path the reply has come back by, counted the way the TTLs
+    // of the probes count the forward one: the routers which have decreased
+    // the TTL of the reply, and the responder itself.
+    //
+    pub fn return_hops(&self) -> Option<u8> {
+        Some(self.initial_ttl()? - self.reply_ttl + 1)
+    }
+
+    // Label stack entries of the MPLS extension objects, if any.
+    //
+    pub fn mpls_labels(&self) -> Vec<MplsLabel> {
+        self.extensions.iter()
+            .filter_map(Extension::mpls_labels)
+            .flatten()
+            .collect()
+    }
+
+    pub fn is_echo_reply(&self) -> bool {
+        self.type_ == ECHO_REPLY
+    }
+
+    pub fn is_time_exceeded(&self) -> bool {
+        self.type_ == TIME_EXCEEDED
+    }
+
+    pub fn is_unreachable(&self) -> bool {
+        self.type_ == UNREACHABLE
+    }
+
+    // Destination unreachable codes as traceroute prints them. Port
+    // unreachable is what the destination itself replies with, so it is not
+    // annotated.
+    //
+    pub fn annotation(&self) -> Option<String> {
+        if !self.is_unreachable() {
+            return None
+        }
+
+        Some(match self.code {
+            0           => "!N".to_string(),
+            1           => "!H".to_string(),
+            2           => "!P".to_string(),
+            3           => return None,
+            4           => "!F".to_string(),
+            5           => "!S".to_string(),
+            9 | 10 | 13 => "!X".to_string(),
+            14          => "!V".to_string(),
+            15          => "!C".to_string(),
+            code        => format!("!<{}>", code),
         })
     }
 
@@ -42,16 +168,18 @@ mod tests {
 
     fn source() -> SockaddrInx { SockaddrInx::from_ip_addr(IP_ADDR) }
 
+    const IP_ID:    u16 = 4_660;
+    const TTL:      u8  = 57;
     const TYPE:     u8  = 123;
     const CODE:     u8  = 231;
     const IDENT:    u16 = 31_719;
     const SEQUENCE: u16 = 59_259;
 
     const BODY: [u8; 56] = [
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 18, 52 /* IP_ID */, 0, 0, TTL, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         TYPE,
         CODE,
-        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         123, 231, // IDENT
         231, 123, // SEQUENCE
@@ -63,8 +191,9 @@ mod tests {
     fn debug() {
         assert_eq!(
             format!("{:?}", response()),
-            "Response { source: 127.0.0.1, type_: 123, code: 231, ident: \
-                31719, sequence: 59259 }",
+            "Response { source: 127.0.0.1, reply_ttl: 57, ip_id: 4660, type_: \
+                123, code: 231, ident: 31719, sequence: 59259, size: 36, \
+                quoted_ttl: Some(1), extensions: [] }",
         );
     }
 
@@ -87,10 +216,14 @@ mod tests {
     fn parse() {
         let response = response();
 
-        assert_eq!(response.type_,    TYPE);
-        assert_eq!(response.code,     CODE);
-        assert_eq!(response.ident,    IDENT);
-        assert_eq!(response.sequence, SEQUENCE);
+        assert_eq!(response.reply_ttl, TTL);
+        assert_eq!(response.ip_id,     IP_ID);
+        assert_eq!(response.type_,     TYPE);
+        assert_eq!(response.code,      CODE);
+        assert_eq!(response.ident,     IDENT);
+        assert_eq!(response.sequence,  SEQUENCE);
+        assert_eq!(response.size,      36);
+        assert_eq!(response.quoted_ttl, Some(1));
 
         match response.source {
             IpAddr::V6(_) => panic!(),
@@ -98,6 +231,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_echo_reply() {
+        let mut body = [0; 28];
+        body[20] = ECHO_REPLY;
+        body[24..28].copy_from_slice(&[123, 231, 231, 123]);
+
+        let response = Response::parse(&source(), &body).unwrap();
+
+        assert!(response.is_echo_reply());
+        assert!(!response.is_time_exceeded());
+        assert_eq!(response.quoted_ttl, None);
+        assert_eq!(response.ident,    IDENT);
+        assert_eq!(response.sequence, SEQUENCE);
+    }
+
+    #[test]
+    fn parse_echo_reply_almost_enough() {
+        let mut body = [0; 27];
+        body[20] = ECHO_REPLY;
+
+        assert!(Response::parse(&source(), &body).is_none());
+    }
+
+    #[test]
+    fn annotation() {
+        let mut response = response();
+
+        assert_eq!(response.annotation(), None);
+
+        response.type_ = UNREACHABLE;
+        response.code = 1;
+        assert_eq!(response.annotation(), Some("!H".to_string()));
+
+        response.code = 13;
+        assert_eq!(response.annotation(), Some("!X".to_string()));
+
+        response.code = 3;
+        assert_eq!(response.annotation(), None);
+
+        response.code = 12;
+        assert_eq!(response.annotation(), Some("!<12>".to_string()));
+    }
+
     #[test]
     fn does_match_request() {
         assert!(response().does_match_request(&Request::new(IDENT, SEQUENCE)));
@@ -112,4 +288,17 @@ mod tests {
     fn does_not_match_request_sequence() {
         assert!(!response().does_match_request(&Request::new(IDENT, SEQUENCE + 1)));
     }
+
+    #[test]
+    fn return_path() {
+        let response = |reply_ttl| Response { reply_ttl, ..Default::default() };
+
+        assert_eq!(response(57).initial_ttl(), Some(64));
+        assert_eq!(response(57).return_hops(), Some(8));
+        assert_eq!(response(64).return_hops(), Some(1));
+        assert_eq!(response(65).initial_ttl(), Some(128));
+        assert_eq!(response(250).initial_ttl(), Some(255));
+        assert_eq!(response(250).return_hops(), Some(6));
+        assert_eq!(response(0).return_hops(), None);
+    }
 }
@@ -0,0 +1,223 @@
+use crate::{
+    asn::{parse_asn, Origin},
+    trie::{Prefix, PrefixTrie},
+};
+use serde_json::Value;
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Read},
+};
+
+// Validated ROA payloads, the prefixes the ASes are authorised to originate
+// and how specific their announcements may be, loaded from the JSON export of
+// an RPKI validator.
+//
+#[derive(Default)]
+pub struct VrpTable {
+    trie: PrefixTrie<Vec<Vrp>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Vrp {
+    asn: u32,
+    max_length: u8,
+}
+
+// Route origin validation states, RFC 6811.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validity {
+    Valid,
+    Invalid,
+    NotFound,
+}
+
+impl VrpTable {
+    // Reads {"roas": [{"prefix": "192.0.2.0/24", "maxLength": 24, "asn":
+    // "AS64500", ...}]}, as exported by rpki-client, Routinator, OctoRPKI and
+    // StayRTR. The AS numbers may be numbers or strings, and the maximum
+    // length defaults to the length of the prefix.
+    //
+    pub fn read<R: Read>(input: R) -> io::Result<Self> {
+        let document: Value = serde_json::from_reader(input)
+            .map_err(|error| invalid(&error.to_string()))?;
+
+        let roas = document.get("roas").or_else(|| document.get("vrps"))
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("no roas array found"))?;
+
+        let mut result = Self::default();
+
+        for roa in roas {
+            let prefix = roa.get("prefix").and_then(Value::as_str)
+                .ok_or_else(|| invalid("ROA without a prefix"))?;
+
+            let prefix: Prefix = prefix.parse()
+                .map_err(|_| invalid(&format!("invalid prefix {}", prefix)))?;
+
+            let asn = match roa.get("asn") {
+                Some(Value::Number(asn)) => asn.as_u64()
+                    .and_then(|asn| asn.try_into().ok()),
+                Some(Value::String(asn)) => parse_asn(asn),
+                _ => None,
+            };
+
+            let asn = asn.ok_or_else(|| invalid(&format!(
+                "invalid AS number of {}",
+                prefix,
+            )))?;
+
+            let max_length = match roa.get("maxLength") {
+                Some(length) => length.as_u64()
+                    .and_then(|length| u8::try_from(length).ok())
+                    .filter(|length| {
+                        Prefix::new(prefix.address, *length).is_some()
+                    })
+                    .ok_or_else(|| invalid(&format!(
+                        "invalid maximum length of {}",
+                        prefix,
+                    )))?,
+                None => prefix.length,
+            };
+
+            result.insert(prefix, asn, max_length);
+        }
+
+        if result.trie.is_empty() {
+            return Err(invalid("no ROAs found"))
+        }
+
+        Ok(result)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    pub fn insert(&mut self, prefix: Prefix, asn: u32, max_length: u8) {
+        let vrp = Vrp { asn, max_length };
+
+        match self.trie.get_mut(prefix) {
+            Some(vrps) if !vrps.contains(&vrp) => vrps.push(vrp),
+            Some(_) => {}
+            None => { self.trie.insert(prefix, vec![vrp]); }
+        }
+    }
+
+    // The route is valid if a VRP covering it has its origin and is not less
+    // specific than it, invalid if VRPs cover it but none of them does, and
+    // not found if none covers it. AS 0 never matches, ROAs for it mark the
+    // prefixes nobody may originate.
+    //
+    pub fn validate(&self, prefix: Prefix, asn: u32) -> Validity {
+        let covering = self.trie.covering(prefix);
+
+        if covering.is_empty() {
+            return Validity::NotFound
+        }
+
+        let matched = covering.iter()
+            .flat_map(|(_, vrps)| vrps.iter())
+            .any(|vrp| {
+                asn != 0 && vrp.asn == asn && prefix.length <= vrp.max_length
+            });
+
+        match matched {
+            true => Validity::Valid,
+            false => Validity::Invalid,
+        }
+    }
+
+    // A prefix announced by several ASes is valid if any of the announcements
+    // is.
+    //
+    pub fn validate_origin(&self, origin: Origin) -> Validity {
+        let states: Vec<Validity> = origin.asns.iter()
+            .map(|asn| self.validate(origin.prefix, *asn))
+            .collect();
+
+        [Validity::Valid, Validity::Invalid].iter()
+            .find(|state| states.contains(state))
+            .copied()
+            .unwrap_or(Validity::NotFound)
+    }
+}
+
+impl Validity {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Valid    => "rpki-valid",
+            Self::Invalid  => "rpki-invalid",
+            Self::NotFound => "rpki-unknown",
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("VRP file: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> VrpTable {
+        VrpTable::read(r#"{
+            "metadata": {"generated": 1700000000},
+            "roas": [
+                {"asn": "AS64500", "prefix": "192.0.2.0/24", "maxLength": 24, "ta": "arin"},
+                {"asn": 64501, "prefix": "198.51.100.0/22", "maxLength": 24, "ta": "ripe"},
+                {"asn": 0, "prefix": "203.0.113.0/24", "ta": "apnic"},
+                {"asn": "AS64502", "prefix": "2001:db8::/32", "maxLength": 48, "ta": "ripe"}
+            ]
+        }"#.as_bytes()).unwrap()
+    }
+
+    fn validate(table: &VrpTable, prefix: &str, asn: u32) -> Validity {
+        table.validate(prefix.parse().unwrap(), asn)
+    }
+
+    #[test]
+    fn origin_validation() {
+        let table = table();
+
+        assert_eq!(table.len(), 4);
+        assert_eq!(validate(&table, "192.0.2.0/24", 64500), Validity::Valid);
+        assert_eq!(validate(&table, "192.0.2.0/24", 64666), Validity::Invalid);
+        assert_eq!(validate(&table, "192.0.2.0/25", 64500), Validity::Invalid);
+        assert_eq!(validate(&table, "198.51.101.0/24", 64501), Validity::Valid);
+        assert_eq!(validate(&table, "198.51.101.0/25", 64501), Validity::Invalid);
+        assert_eq!(validate(&table, "203.0.113.0/24", 0), Validity::Invalid);
+        assert_eq!(validate(&table, "2001:db8:1::/48", 64502), Validity::Valid);
+        assert_eq!(validate(&table, "192.0.0.0/16", 64500), Validity::NotFound);
+        assert_eq!(validate(&table, "8.8.8.0/24", 15169), Validity::NotFound);
+    }
+
+    #[test]
+    fn several_origins() {
+        let table = table();
+        let prefix = "192.0.2.0/24".parse().unwrap();
+
+        let origin = |asns| table.validate_origin(Origin { prefix, asns });
+
+        assert_eq!(origin(&[64666, 64500]), Validity::Valid);
+        assert_eq!(origin(&[64666, 64667]), Validity::Invalid);
+    }
+
+    #[test]
+    fn errors() {
+        for file in &[
+            "not json",
+            r#"{"roas": []}"#,
+            r#"{"prefixes": []}"#,
+            r#"{"roas": [{"asn": "ASX", "prefix": "192.0.2.0/24"}]}"#,
+            r#"{"roas": [{"asn": 1, "prefix": "192.0.2.0/24", "maxLength": 33}]}"#,
+        ] {
+            assert!(VrpTable::read(file.as_bytes()).is_err(), "{}", file);
+        }
+    }
+}
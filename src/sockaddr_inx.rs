@@ -16,13 +16,17 @@ impl fmt::Debug for SockaddrInx {
 }
 
 impl SockaddrInx {
-    pub fn from_sockaddr(sockaddr: libc::sockaddr) -> Option<Self> {
-        match sockaddr.sa_family as i32 {
+    pub fn from_sockaddr_storage(
+        storage: &libc::sockaddr_storage,
+    ) -> Option<Self> {
+        match storage.ss_family as i32 {
             libc::AF_INET => Some(Self::V4(unsafe { *(
-                &sockaddr as *const libc::sockaddr as *const libc::sockaddr_in
+                storage as *const libc::sockaddr_storage
+                    as *const libc::sockaddr_in
             ) })),
             libc::AF_INET6 => Some(Self::V6(unsafe { *(
-                &sockaddr as *const libc::sockaddr as *const libc::sockaddr_in6
+                storage as *const libc::sockaddr_storage
+                    as *const libc::sockaddr_in6
             ) })),
             _ => None,
         }
@@ -37,7 +41,7 @@ impl SockaddrInx {
                     ((octets[3] as u32) << 24) +
                     ((octets[2] as u32) << 16) +
                     ((octets[1] as u32) << 8)  +
-                    ((octets[0] as u32));
+                    (octets[0] as u32);
 
                 Self::V4(libc::sockaddr_in {
                     sin_family: libc::AF_INET as libc::sa_family_t,
@@ -56,7 +60,7 @@ impl SockaddrInx {
         }
     }
 
-    pub fn to_ip_addr(&self) -> IpAddr {
+    pub fn to_ip_addr(self) -> IpAddr {
         match self {
             Self::V4(sockaddr_in) => IpAddr::V4(Ipv4Addr::new(
                 (sockaddr_in.sin_addr.s_addr)       as u8,
@@ -66,21 +70,21 @@ impl SockaddrInx {
             )),
             Self::V6(sockaddr_in6) => IpAddr::V6(Ipv6Addr::new(
                 ((sockaddr_in6.sin6_addr.s6_addr[0] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[1] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[1] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[2] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[3] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[3] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[4] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[5] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[5] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[6] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[7] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[7] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[8] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[9] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[9] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[10] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[11] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[11] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[12] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[13] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[13] as u16),
                 ((sockaddr_in6.sin6_addr.s6_addr[14] as u16) << 8) +
-                ((sockaddr_in6.sin6_addr.s6_addr[15] as u16)),
+                (sockaddr_in6.sin6_addr.s6_addr[15] as u16),
             )),
         }
     }
@@ -0,0 +1,167 @@
+use crate::{error::Error, sockaddr_inx::SockaddrInx};
+use std::{
+    convert::TryInto,
+    net::IpAddr,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    time::Duration,
+};
+
+// Raw ICMP socket. Opening it requires root or CAP_NET_RAW, everything else
+// can be done after the privileges have been dropped.
+//
+#[derive(Debug)]
+pub struct Socket {
+    fd: RawFd,
+}
+
+impl Socket {
+    pub fn open() -> Result<Self, Error> {
+        let fd = unsafe {
+            libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP)
+        };
+
+        if fd < 0 { Err(Error::last_os_error()) } else { Ok(Self { fd }) }
+    }
+
+    pub fn set_nonblocking(&self) -> Result<(), Error> {
+        let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL) };
+
+        if flags < 0 || unsafe {
+            libc::fcntl(self.fd, libc::F_SETFL, flags | libc::O_NONBLOCK)
+        } < 0 {
+            return Err(Error::last_os_error())
+        }
+
+        Ok(())
+    }
+
+    pub fn set_ttl(&self, ttl: u8) -> Result<(), Error> {
+        let ttl: libc::c_int = ttl.into();
+
+        self.setsockopt(libc::IPPROTO_IP, libc::IP_TTL, &ttl)
+    }
+
+    pub fn set_tos(&self, tos: u8) -> Result<(), Error> {
+        let tos: libc::c_int = tos.into();
+
+        self.setsockopt(libc::IPPROTO_IP, libc::IP_TOS, &tos)
+    }
+
+    // A zero timeout makes receiving block forever, so it is rounded up to the
+    // smallest representable one.
+    //
+    pub fn set_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        let timeout = timeout.max(Duration::from_micros(1));
+
+        let timeval = libc::timeval {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_usec: timeout.subsec_micros().into(),
+        };
+
+        self.setsockopt(libc::SOL_SOCKET, libc::SO_RCVTIMEO, &timeval)
+    }
+
+    pub fn send_to(&self, message: &[u8], host: IpAddr) -> Result<(), Error> {
+        let sockaddr_inx = SockaddrInx::from_ip_addr(host);
+
+        if unsafe { libc::sendto(
+            self.fd,
+            message.as_ptr() as *const libc::c_void,
+            message.len(),
+            0,
+            sockaddr_inx.sockaddr_ptr(),
+            sockaddr_inx.socklen(),
+        ) } < 0 {
+            return Err(Error::last_os_error())
+        }
+
+        Ok(())
+    }
+
+    // Returns `None` when the receive timeout has expired or, in non-blocking
+    // mode, when there is nothing to receive.
+    //
+    pub fn recv_from(
+        &self,
+        buffer: &mut [u8],
+    ) -> Result<Option<(usize, SockaddrInx)>, Error> {
+        match self.recv(buffer) {
+            // The receive timeout has expired or a signal has arrived.
+            //
+            Err(Error::Os(libc::EAGAIN)) | Err(Error::Os(libc::EINTR)) => Ok(None),
+            Err(error) => Err(error),
+            Ok((size, sockaddr_inx)) => {
+                Ok(sockaddr_inx.map(|sockaddr_inx| (size, sockaddr_inx)))
+            }
+        }
+    }
+
+    // Receives a single packet, with the address of the sender unless it is
+    // of an unknown family. EAGAIN and EINTR are returned as they are.
+    //
+    pub(crate) fn recv(
+        &self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, Option<SockaddrInx>), Error> {
+        let mut sockaddr_storage: libc::sockaddr_storage =
+            unsafe { std::mem::zeroed() };
+
+        let mut sockaddr_size: libc::socklen_t =
+            std::mem::size_of::<libc::sockaddr_storage>().try_into().unwrap();
+
+        let size = unsafe { libc::recvfrom(
+            self.fd,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+            0,
+            &mut sockaddr_storage as *mut libc::sockaddr_storage
+                as *mut libc::sockaddr,
+            &mut sockaddr_size,
+        ) };
+
+        if size < 0 {
+            return Err(Error::last_os_error())
+        }
+
+        Ok((size as usize, SockaddrInx::from_sockaddr_storage(&sockaddr_storage)))
+    }
+
+    fn setsockopt<T>(
+        &self,
+        level: libc::c_int,
+        name: libc::c_int,
+        value: &T,
+    ) -> Result<(), Error> {
+        if unsafe { libc::setsockopt(
+            self.fd,
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>().try_into().unwrap(),
+        ) } != 0 {
+            return Err(Error::last_os_error())
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+// The socket takes over the descriptor and closes it when dropped.
+//
+impl FromRawFd for Socket {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Self { fd }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
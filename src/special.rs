@@ -0,0 +1,260 @@
+use crate::trie::{Prefix, PrefixTrie};
+use std::{net::IpAddr, sync::OnceLock};
+
+// Address blocks of the IANA IPv4 and IPv6 Special-Purpose Address
+// Registries, see
+// https://www.iana.org/assignments/iana-ipv4-special-registry/
+// https://www.iana.org/assignments/iana-ipv6-special-registry/
+// and the multicast and reserved space outside of them. The most specific
+// block wins, e.g. 192.0.0.9/32 is not just an IETF protocol assignment.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Class {
+    ThisNetwork,
+    Private,
+    Shared,
+    Loopback,
+    LinkLocal,
+    IetfProtocol,
+    DsLite,
+    Dummy,
+    Anycast,
+    Documentation,
+    As112,
+    Amt,
+    SixToFourRelay,
+    Benchmarking,
+    Multicast,
+    Reserved,
+    Broadcast,
+    Unspecified,
+    Ipv4Mapped,
+    Translation,
+    Discard,
+    Teredo,
+    Orchid,
+    DroneRemoteId,
+    SixToFour,
+    Srv6,
+    UniqueLocal,
+}
+
+const BLOCKS: [(&str, Class); 51] = [
+    ("0.0.0.0/8", Class::ThisNetwork),
+    ("10.0.0.0/8", Class::Private),
+    ("100.64.0.0/10", Class::Shared),
+    ("127.0.0.0/8", Class::Loopback),
+    ("169.254.0.0/16", Class::LinkLocal),
+    ("172.16.0.0/12", Class::Private),
+    ("192.0.0.0/24", Class::IetfProtocol),
+    ("192.0.0.0/29", Class::DsLite),
+    ("192.0.0.8/32", Class::Dummy),
+    ("192.0.0.9/32", Class::Anycast),
+    ("192.0.0.10/32", Class::Anycast),
+    ("192.0.0.170/32", Class::Translation),
+    ("192.0.0.171/32", Class::Translation),
+    ("192.0.2.0/24", Class::Documentation),
+    ("192.31.196.0/24", Class::As112),
+    ("192.52.193.0/24", Class::Amt),
+    ("192.88.99.0/24", Class::SixToFourRelay),
+    ("192.168.0.0/16", Class::Private),
+    ("192.175.48.0/24", Class::As112),
+    ("198.18.0.0/15", Class::Benchmarking),
+    ("198.51.100.0/24", Class::Documentation),
+    ("203.0.113.0/24", Class::Documentation),
+    ("224.0.0.0/4", Class::Multicast),
+    ("240.0.0.0/4", Class::Reserved),
+    ("255.255.255.255/32", Class::Broadcast),
+    ("::/128", Class::Unspecified),
+    ("::1/128", Class::Loopback),
+    ("::ffff:0:0/96", Class::Ipv4Mapped),
+    ("64:ff9b::/96", Class::Translation),
+    ("64:ff9b:1::/48", Class::Translation),
+    ("100::/64", Class::Discard),
+    ("100:0:0:1::/64", Class::Dummy),
+    ("2001::/23", Class::IetfProtocol),
+    ("2001::/32", Class::Teredo),
+    ("2001:1::1/128", Class::Anycast),
+    ("2001:1::2/128", Class::Anycast),
+    ("2001:1::3/128", Class::Anycast),
+    ("2001:2::/48", Class::Benchmarking),
+    ("2001:3::/32", Class::Amt),
+    ("2001:4:112::/48", Class::As112),
+    ("2001:10::/28", Class::Orchid),
+    ("2001:20::/28", Class::Orchid),
+    ("2001:30::/28", Class::DroneRemoteId),
+    ("2001:db8::/32", Class::Documentation),
+    ("2002::/16", Class::SixToFour),
+    ("2620:4f:8000::/48", Class::As112),
+    ("3fff::/20", Class::Documentation),
+    ("5f00::/16", Class::Srv6),
+    ("fc00::/7", Class::UniqueLocal),
+    ("fe80::/10", Class::LinkLocal),
+    ("ff00::/8", Class::Multicast),
+];
+
+impl Class {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::ThisNetwork    => "this-network",
+            Self::Private        => "private",
+            Self::Shared         => "shared",
+            Self::Loopback       => "loopback",
+            Self::LinkLocal      => "link-local",
+            Self::IetfProtocol   => "ietf-protocol",
+            Self::DsLite         => "ds-lite",
+            Self::Dummy          => "dummy",
+            Self::Anycast        => "anycast",
+            Self::Documentation  => "documentation",
+            Self::As112          => "as112",
+            Self::Amt            => "amt",
+            Self::SixToFourRelay => "6to4-relay",
+            Self::Benchmarking   => "benchmarking",
+            Self::Multicast      => "multicast",
+            Self::Reserved       => "reserved",
+            Self::Broadcast      => "broadcast",
+            Self::Unspecified    => "unspecified",
+            Self::Ipv4Mapped     => "ipv4-mapped",
+            Self::Translation    => "translation",
+            Self::Discard        => "discard",
+            Self::Teredo         => "teredo",
+            Self::Orchid         => "orchid",
+            Self::DroneRemoteId  => "drone-remote-id",
+            Self::SixToFour      => "6to4",
+            Self::Srv6           => "srv6-sid",
+            Self::UniqueLocal    => "unique-local",
+        }
+    }
+
+    // Space the operators number their own networks from, which the routers
+    // of the Internet are not supposed to be seen in.
+    //
+    pub fn is_private(&self) -> bool {
+        matches!(self, Self::Private | Self::Shared | Self::UniqueLocal)
+    }
+}
+
+// Returns `None` for the global unicast addresses.
+//
+pub fn classify(address: IpAddr) -> Option<Class> {
+    static TRIE: OnceLock<PrefixTrie<Class>> = OnceLock::new();
+
+    let trie = TRIE.get_or_init(|| {
+        let mut trie = PrefixTrie::new();
+
+        for (prefix, class) in BLOCKS.iter() {
+            trie.insert(prefix.parse::<Prefix>().unwrap(), *class);
+        }
+
+        trie
+    });
+
+    trie.lookup(address).map(|(_, class)| *class)
+}
+
+// Responders in private space met after a global one, with their TTLs. The
+// responders are given in the order of the path.
+//
+pub fn private_after_public<I>(responders: I) -> Vec<(u8, IpAddr)>
+where
+    I: IntoIterator<Item = (u8, IpAddr)>,
+{
+    let mut public = false;
+    let mut result = vec![];
+
+    for (ttl, address) in responders {
+        match classify(address) {
+            None => public = true,
+            Some(class) if class.is_private() && public => {
+                result.push((ttl, address));
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(address: &str) -> Option<Class> {
+        classify(address.parse().unwrap())
+    }
+
+    #[test]
+    fn blocks() {
+        for (prefix, _) in BLOCKS.iter() {
+            assert!(prefix.parse::<Prefix>().is_ok(), "{}", prefix);
+        }
+    }
+
+    #[test]
+    fn ipv4() {
+        assert_eq!(class("10.1.2.3"), Some(Class::Private));
+        assert_eq!(class("172.31.255.255"), Some(Class::Private));
+        assert_eq!(class("172.32.0.1"), None);
+        assert_eq!(class("100.64.0.1"), Some(Class::Shared));
+        assert_eq!(class("100.128.0.1"), None);
+        assert_eq!(class("169.254.1.1"), Some(Class::LinkLocal));
+        assert_eq!(class("192.0.0.9"), Some(Class::Anycast));
+        assert_eq!(class("192.0.0.64"), Some(Class::IetfProtocol));
+        assert_eq!(class("192.0.0.2"), Some(Class::DsLite));
+        assert_eq!(class("192.0.0.8"), Some(Class::Dummy));
+        assert_eq!(class("192.0.0.170"), Some(Class::Translation));
+        assert_eq!(class("192.0.0.171"), Some(Class::Translation));
+        assert_eq!(class("192.0.0.172"), Some(Class::IetfProtocol));
+        assert_eq!(class("192.31.196.1"), Some(Class::As112));
+        assert_eq!(class("192.52.193.1"), Some(Class::Amt));
+        assert_eq!(class("192.175.48.1"), Some(Class::As112));
+        assert_eq!(class("198.19.0.1"), Some(Class::Benchmarking));
+        assert_eq!(class("239.1.1.1"), Some(Class::Multicast));
+        assert_eq!(class("250.0.0.1"), Some(Class::Reserved));
+        assert_eq!(class("255.255.255.255"), Some(Class::Broadcast));
+        assert_eq!(class("8.8.8.8"), None);
+    }
+
+    #[test]
+    fn ipv6() {
+        assert_eq!(class("::1"), Some(Class::Loopback));
+        assert_eq!(class("fd00::1"), Some(Class::UniqueLocal));
+        assert_eq!(class("fe80::1"), Some(Class::LinkLocal));
+        assert_eq!(class("2001:db8::1"), Some(Class::Documentation));
+        assert_eq!(class("2001::1"), Some(Class::Teredo));
+        assert_eq!(class("2001:100::1"), Some(Class::IetfProtocol));
+        assert_eq!(class("100::1"), Some(Class::Discard));
+        assert_eq!(class("100:0:0:1::1"), Some(Class::Dummy));
+        assert_eq!(class("2001:1::2"), Some(Class::Anycast));
+        assert_eq!(class("2001:1::3"), Some(Class::Anycast));
+        assert_eq!(class("2001:1::4"), Some(Class::IetfProtocol));
+        assert_eq!(class("2001:3::1"), Some(Class::Amt));
+        assert_eq!(class("2001:4:112::1"), Some(Class::As112));
+        assert_eq!(class("2001:10::1"), Some(Class::Orchid));
+        assert_eq!(class("2001:30::1"), Some(Class::DroneRemoteId));
+        assert_eq!(class("2620:4f:8000::1"), Some(Class::As112));
+        assert_eq!(class("2620:4f:8001::1"), None);
+        assert_eq!(class("3fff:fff::1"), Some(Class::Documentation));
+        assert_eq!(class("5f00::1"), Some(Class::Srv6));
+        assert_eq!(class("ff02::1"), Some(Class::Multicast));
+        assert_eq!(class("2606:4700::1111"), None);
+    }
+
+    #[test]
+    fn private_mid_path() {
+        let hops = [
+            (1, "192.168.1.1"),
+            (2, "100.64.0.1"),
+            (3, "8.8.4.4"),
+            (4, "10.0.0.1"),
+            (5, "192.0.2.1"),
+            (6, "8.8.8.8"),
+        ];
+
+        assert_eq!(
+            private_after_public(hops.iter()
+                .map(|(ttl, address)| (*ttl, address.parse().unwrap()))),
+            vec![(4, "10.0.0.1".parse().unwrap())],
+        );
+    }
+}
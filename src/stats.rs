@@ -0,0 +1,310 @@
+use rustraceroute::{Event, Observer};
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+// Time between the rounds of probes, as in mtr.
+//
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sample {
+    Reply(Duration),
+    Lost,
+    Failed,
+}
+
+// What has happened at one TTL, probe by probe.
+//
+#[derive(Default)]
+pub struct HopStatistics {
+    pub samples: Vec<Sample>,
+    pub responders: Vec<IpAddr>,
+    pub annotation: Option<String>,
+    // Length of the path back from each responder, from its last reply.
+    //
+    pub return_hops: HashMap<IpAddr, u8>,
+}
+
+// Statistics of every TTL probed, collected from the events of the probes
+// over any number of rounds.
+//
+pub struct Statistics {
+    first_ttl: u8,
+    max_ttl: u8,
+    // TTL of the destination or of the router which has reported it
+    // unreachable, there is no point in probing beyond it.
+    //
+    last_ttl: u8,
+    hops: Vec<HopStatistics>,
+}
+
+impl HopStatistics {
+    pub fn sent(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn received(&self) -> usize {
+        self.rtts().count()
+    }
+
+    pub fn rtts(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.samples.iter().filter_map(|sample| match sample {
+            Sample::Reply(rtt) => Some(*rtt),
+            _ => None,
+        })
+    }
+
+    // In percent.
+    //
+    pub fn loss(&self) -> f64 {
+        match self.sent() {
+            0 => 0.0,
+            sent => (sent - self.received()) as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.rtts().last()
+    }
+
+    pub fn best(&self) -> Option<Duration> {
+        self.rtts().min()
+    }
+
+    pub fn worst(&self) -> Option<Duration> {
+        self.rtts().max()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        match self.received() {
+            0 => None,
+            received => Some(self.rtts().sum::<Duration>() / received as u32),
+        }
+    }
+
+    // Sample standard deviation, zero for a single reply.
+    //
+    pub fn standard_deviation(&self) -> Option<Duration> {
+        let mean = self.mean()?.as_secs_f64();
+        let received = self.received();
+
+        if received < 2 {
+            return Some(Duration::ZERO)
+        }
+
+        let deviations: f64 = self.rtts()
+            .map(|rtt| (rtt.as_secs_f64() - mean).powi(2))
+            .sum();
+
+        Some(Duration::from_secs_f64(
+            (deviations / (received - 1) as f64).sqrt(),
+        ))
+    }
+
+    // Mean difference between the consecutive RTTs.
+    //
+    pub fn jitter(&self) -> Option<Duration> {
+        let rtts: Vec<Duration> = self.rtts().collect();
+
+        if rtts.len() < 2 {
+            return None
+        }
+
+        let differences: Duration = rtts.windows(2)
+            .map(|pair| pair[0].max(pair[1]) - pair[0].min(pair[1]))
+            .sum();
+
+        Some(differences / (rtts.len() - 1) as u32)
+    }
+
+    // The smallest RTT which is not less than the percentage of them (the
+    // nearest-rank method).
+    //
+    pub fn percentile(&self, percent: u8) -> Option<Duration> {
+        let mut rtts: Vec<Duration> = self.rtts().collect();
+        rtts.sort();
+
+        let rank = (usize::from(percent) * rtts.len()).div_ceil(100);
+
+        rtts.get(rank.max(1) - 1).copied()
+    }
+}
+
+impl Statistics {
+    pub fn new(first_ttl: u8, max_ttl: u8) -> Self {
+        Self { first_ttl, max_ttl, last_ttl: max_ttl, hops: vec![] }
+    }
+
+    pub fn reset(&mut self) {
+        self.hops.clear();
+        self.last_ttl = self.max_ttl;
+    }
+
+    pub fn first_ttl(&self) -> u8 {
+        self.first_ttl
+    }
+
+    // The TTL to probe after the one given in a round over the hops, unless
+    // it is past the last one, which may have come down in the meantime.
+    //
+    pub fn next_ttl(&self, ttl: u8) -> Option<u8> {
+        ttl.checked_add(1).filter(|next| *next <= self.last_ttl)
+    }
+
+    // The hops probed so far up to the last one, with their TTLs.
+    //
+    pub fn hops(&self) -> impl Iterator<Item = (u8, &HopStatistics)> {
+        let count = usize::from(self.last_ttl.saturating_sub(self.first_ttl)) + 1;
+
+        (self.first_ttl..=u8::MAX).zip(self.hops.iter().take(count))
+    }
+
+    fn hop(&mut self, ttl: u8) -> &mut HopStatistics {
+        let index = usize::from(ttl.saturating_sub(self.first_ttl));
+
+        if self.hops.len() <= index {
+            self.hops.resize_with(index + 1, HopStatistics::default);
+        }
+
+        &mut self.hops[index]
+    }
+}
+
+impl Observer for Statistics {
+    fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::ProbeFailed { ttl, error, .. } => {
+                let hop = self.hop(*ttl);
+
+                hop.samples.push(Sample::Failed);
+                hop.annotation = error.annotation().map(str::to_string);
+            }
+            Event::ReplyReceived { ttl, response, rtt, .. } => {
+                if !response.is_time_exceeded() && *ttl < self.last_ttl {
+                    self.last_ttl = *ttl;
+                }
+
+                let hop = self.hop(*ttl);
+
+                hop.samples.push(Sample::Reply(*rtt));
+                hop.annotation = response.annotation();
+
+                if !hop.responders.contains(&response.source) {
+                    hop.responders.push(response.source);
+                }
+
+                if let Some(return_hops) = response.return_hops() {
+                    hop.return_hops.insert(response.source, return_hops);
+                }
+            }
+            Event::ProbeTimedOut { ttl, .. } => {
+                self.hop(*ttl).samples.push(Sample::Lost);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{reply_received, ROUTER, TARGET};
+    use rustraceroute::response::{ECHO_REPLY, TIME_EXCEEDED};
+
+    fn hop(millis: &[Option<u64>]) -> HopStatistics {
+        HopStatistics {
+            samples: millis.iter().map(|millis| match millis {
+                Some(millis) => Sample::Reply(Duration::from_millis(*millis)),
+                None => Sample::Lost,
+            }).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn reply(ttl: u8, source: IpAddr, type_: u8) -> Event {
+        reply_received(ttl, source, type_, 1000)
+    }
+
+    #[test]
+    fn summary() {
+        let hop = hop(&[Some(2), Some(4), None, Some(9)]);
+
+        assert_eq!(hop.sent(), 4);
+        assert_eq!(hop.received(), 3);
+        assert_eq!(hop.loss(), 25.0);
+        assert_eq!(hop.last(), Some(Duration::from_millis(9)));
+        assert_eq!(hop.best(), Some(Duration::from_millis(2)));
+        assert_eq!(hop.worst(), Some(Duration::from_millis(9)));
+        assert_eq!(hop.mean(), Some(Duration::from_millis(5)));
+        assert_eq!(
+            hop.standard_deviation().unwrap().as_micros(),
+            (13f64.sqrt() * 1000.0) as u128,
+        );
+        assert_eq!(hop.jitter(), Some(Duration::from_micros(3500)));
+    }
+
+    #[test]
+    fn no_replies() {
+        let hop = hop(&[None, None]);
+
+        assert_eq!(hop.loss(), 100.0);
+        assert_eq!(hop.mean(), None);
+        assert_eq!(hop.standard_deviation(), None);
+        assert_eq!(hop.jitter(), None);
+        assert_eq!(hop.percentile(50), None);
+    }
+
+    #[test]
+    fn single_reply() {
+        let hop = hop(&[Some(3)]);
+
+        assert_eq!(hop.standard_deviation(), Some(Duration::ZERO));
+        assert_eq!(hop.jitter(), None);
+    }
+
+    #[test]
+    fn percentiles() {
+        let hop = hop(&(1..=10).rev().map(Some).collect::<Vec<_>>());
+
+        assert_eq!(hop.percentile(0), Some(Duration::from_millis(1)));
+        assert_eq!(hop.percentile(50), Some(Duration::from_millis(5)));
+        assert_eq!(hop.percentile(90), Some(Duration::from_millis(9)));
+        assert_eq!(hop.percentile(95), Some(Duration::from_millis(10)));
+        assert_eq!(hop.percentile(100), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn events() {
+        let mut statistics = Statistics::new(1, 30);
+
+        statistics.on_event(&reply(1, ROUTER, TIME_EXCEEDED));
+        statistics.on_event(&Event::ProbeTimedOut { ttl: 2, sequence: 1 });
+        statistics.on_event(&reply(3, TARGET, ECHO_REPLY));
+        statistics.on_event(&Event::ProbeTimedOut { ttl: 4, sequence: 3 });
+
+        assert_eq!(statistics.last_ttl, 3);
+        assert_eq!(
+            statistics.hops().map(|(ttl, _)| ttl).collect::<Vec<_>>(),
+            vec![1, 2, 3],
+        );
+
+        statistics.reset();
+
+        assert_eq!(statistics.last_ttl, 30);
+        assert_eq!(statistics.hops().count(), 0);
+    }
+
+    #[test]
+    fn next_ttl() {
+        let mut statistics = Statistics::new(1, 30);
+
+        assert_eq!(statistics.next_ttl(1), Some(2));
+        assert_eq!(statistics.next_ttl(30), None);
+
+        statistics.on_event(&reply(3, TARGET, ECHO_REPLY));
+
+        assert_eq!(statistics.next_ttl(2), Some(3));
+        assert_eq!(statistics.next_ttl(3), None);
+
+        assert_eq!(Statistics::new(255, 255).next_ttl(255), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    io::{self, Write},
+    mem::MaybeUninit,
+    time::Duration,
+};
+
+const STDIN: libc::c_int = libc::STDIN_FILENO;
+
+// The terminal switched to the alternate screen, with the keys read as soon
+// as they are pressed and not echoed. Everything is restored when dropped.
+//
+pub struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        let mut saved = MaybeUninit::<libc::termios>::uninit();
+
+        if unsafe { libc::tcgetattr(STDIN, saved.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error())
+        }
+
+        let saved = unsafe { saved.assume_init() };
+        let mut raw = saved;
+
+        // Ctrl-C is read as a key too, so that the terminal is restored on
+        // the way out.
+        //
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        if unsafe { libc::tcsetattr(STDIN, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error())
+        }
+
+        let terminal = Self { saved };
+
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+
+        Ok(terminal)
+    }
+
+    // Replaces what is on the screen with the lines.
+    //
+    pub fn draw(&self, lines: &[String]) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+
+        write!(stdout, "\x1b[H")?;
+
+        for line in lines {
+            writeln!(stdout, "{}\x1b[K", line)?;
+        }
+
+        write!(stdout, "\x1b[J")?;
+        stdout.flush()
+    }
+
+    // Waits for a key at most for the timeout.
+    //
+    pub fn key(&self, timeout: Duration) -> io::Result<Option<u8>> {
+        let mut poll = libc::pollfd { fd: STDIN, events: libc::POLLIN, revents: 0 };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128);
+
+        match unsafe { libc::poll(&mut poll, 1, timeout as libc::c_int) } {
+            -1 => {
+                let error = io::Error::last_os_error();
+
+                match error.kind() {
+                    io::ErrorKind::Interrupted => Ok(None),
+                    _ => Err(error),
+                }
+            }
+            0 => Ok(None),
+            _ => {
+                let mut key = 0u8;
+
+                match unsafe { libc::read(STDIN, (&mut key as *mut u8).cast(), 1) } {
+                    -1 => Err(io::Error::last_os_error()),
+                    // End of the input, there will be no keys anymore.
+                    0 => Ok(Some(b'q')),
+                    _ => Ok(Some(key)),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+
+        unsafe { libc::tcsetattr(STDIN, libc::TCSAFLUSH, &self.saved) };
+    }
+}
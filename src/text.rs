@@ -0,0 +1,391 @@
+use crate::annotations::{Annotations, Boundaries};
+use rustraceroute::{
+    mpls::{self, Kind, Tunnel},
+    Event,
+    Observer,
+    Response,
+};
+use std::{
+    io::{self, Write},
+    net::IpAddr,
+};
+
+// Prints the trace the way traceroute does, one probe at a time. With the
+// names of the hops, the line of a hop is printed when the hop is done, so
+// that the names are looked up while the other probes of the hop are sent.
+// The kinds of special address space the responders are in follow them in
+// brackets, and so do their origin ASes, an asterisk marking where the path
+// enters another AS, and their locations. With MPLS, the label stacks and the
+// quoted TTLs follow the replies as in traceroute -e, and the tunnels found
+// are listed when the trace is done.
+//
+pub struct Text<W: Write> {
+    output: W,
+    annotations: Annotations,
+    boundaries: Boundaries,
+    mpls: bool,
+    ttl: Option<u8>,
+    source: Option<IpAddr>,
+    line: Vec<Piece>,
+}
+
+enum Piece {
+    Text(String),
+    Source(IpAddr),
+}
+
+impl<W: Write> Text<W> {
+    pub fn new(output: W, annotations: Annotations) -> Self {
+        Self {
+            output,
+            annotations,
+            boundaries: Boundaries::default(),
+            mpls: false,
+            ttl: None,
+            source: None,
+            line: vec![],
+        }
+    }
+
+    pub fn with_mpls(self, mpls: bool) -> Self {
+        Self { mpls, ..self }
+    }
+
+    fn render(&mut self, event: &Event) -> io::Result<()> {
+        let ttl = match event {
+            Event::ProbeSent { ttl, .. } | Event::ProbeFailed { ttl, .. } => {
+                Some(*ttl)
+            }
+            _ => None,
+        };
+
+        if let Some(ttl) = ttl.filter(|ttl| self.ttl != Some(*ttl)) {
+            self.ttl = Some(ttl);
+            self.source = None;
+
+            self.push(format!("{:2} ", ttl));
+        }
+
+        match event {
+            Event::ProbeFailed { error, .. } => {
+                self.push(format!(" {}", error.annotation().unwrap_or("!")));
+            }
+            Event::ReplyReceived { response, rtt, .. } => {
+                if self.source != Some(response.source) {
+                    self.source = Some(response.source);
+                    self.annotations.names.lookup(response.source);
+
+                    self.line.push(Piece::Source(response.source));
+                }
+
+                self.push(format!("  {:.3} ms", rtt.as_secs_f64() * 1000.0));
+
+                if let Some(annotation) = response.annotation() {
+                    self.push(format!(" {}", annotation));
+                }
+
+                if self.mpls {
+                    self.push_mpls(response);
+                }
+            }
+            Event::ProbeTimedOut { .. } => {
+                self.push(" *".to_string());
+            }
+            Event::HopCompleted(_) => {
+                self.push("\n".to_string());
+
+                return self.flush()
+            }
+            Event::TraceFinished(trace) if self.mpls => {
+                for tunnel in mpls::tunnels(trace) {
+                    self.push(format!("{}\n", describe(&tunnel)));
+                }
+
+                return self.flush()
+            }
+            _ => {}
+        }
+
+        match self.annotations.names.is_enabled() {
+            true => Ok(()),
+            false => self.flush(),
+        }
+    }
+
+    fn push(&mut self, text: String) {
+        self.line.push(Piece::Text(text));
+    }
+
+    fn push_mpls(&mut self, response: &Response) {
+        for label in response.mpls_labels() {
+            self.push(format!(
+                " <MPLS:L={},E={},S={},T={}>",
+                label.label,
+                label.exp,
+                u8::from(label.bottom),
+                label.ttl,
+            ));
+        }
+
+        if let Some(quoted_ttl) = response.quoted_ttl.filter(|ttl| *ttl > 1) {
+            self.push(format!(" <q={}>", quoted_ttl));
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for piece in std::mem::take(&mut self.line) {
+            match piece {
+                Piece::Text(text) => write!(self.output, "{}", text)?,
+                Piece::Source(address) => self.write_source(address)?,
+            }
+        }
+
+        self.output.flush()
+    }
+
+    fn write_source(&mut self, address: IpAddr) -> io::Result<()> {
+        let names = &self.annotations.names;
+
+        if !names.is_enabled() {
+            write!(self.output, " {}", address)?;
+        }
+        else {
+            match names.wait(address) {
+                Some(name) => write!(self.output, " {} ({})", name, address)?,
+                None => write!(self.output, " {} ({})", address, address)?,
+            }
+        }
+
+        for tag in self.annotations.tags(address) {
+            write!(self.output, " [{}]", tag)?;
+        }
+
+        if let Some(origin) = self.annotations.origin(address) {
+            write!(self.output, " [{}]", self.boundaries.label(origin))?;
+        }
+
+        if let Some(place) = self.annotations.place(address) {
+            write!(self.output, " [{}]", place)?;
+        }
+
+        if let Some(place) = self.annotations.hinted_place(address) {
+            write!(self.output, " [{}]", place)?;
+        }
+
+        Ok(())
+    }
+}
+
+// "MPLS tunnel (explicit): ingress hop 2, routers at hops 3-5, egress hop 6",
+// with as much as is known.
+//
+fn describe(tunnel: &Tunnel) -> String {
+    let mut parts = vec![];
+
+    if let Some(ingress) = tunnel.ingress {
+        parts.push(format!("ingress hop {}", ingress));
+    }
+
+    match tunnel.routers[..] {
+        [] => {}
+        [router] => parts.push(format!("router at hop {}", router)),
+        [first, .., last] => {
+            parts.push(format!("routers at hops {}-{}", first, last));
+        }
+    }
+
+    if let Some(egress) = tunnel.egress {
+        parts.push(format!("egress hop {}", egress));
+    }
+
+    if let Some(hidden) = tunnel.hidden {
+        parts.push(format!("about {} hidden hops", hidden));
+    }
+
+    let kind = match tunnel.kind {
+        Kind::Invisible => "suspected invisible",
+        kind => kind.name(),
+    };
+
+    format!("MPLS tunnel ({}): {}", kind, parts.join(", "))
+}
+
+impl<W: Write> Observer for Text<W> {
+    fn on_event(&mut self, event: &Event) {
+        // There is nobody to report to if the output is gone.
+        //
+        let _ = self.render(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{probe_sent as sent, reply_received, ROUTER, TARGET},
+        names::Names,
+    };
+    use rustraceroute::{
+        asn::AsDatabase,
+        extension::{Extension, MPLS_CLASS, MPLS_INCOMING_STACK},
+        response::TIME_EXCEEDED,
+        Error,
+        Hop,
+        ProbeResult,
+        ProbeStatus,
+        Trace,
+    };
+    use std::time::{Duration, SystemTime};
+
+    fn render(events: &[Event]) -> String {
+        let mut text = Text::new(vec![], Names::disabled().into());
+
+        for event in events {
+            text.on_event(event);
+        }
+
+        String::from_utf8(text.output).unwrap()
+    }
+
+    fn reply(ttl: u8) -> Event {
+        reply_from(ttl, ROUTER)
+    }
+
+    fn reply_from(ttl: u8, source: IpAddr) -> Event {
+        reply_received(ttl, source, TIME_EXCEEDED, 1500)
+    }
+
+    #[test]
+    fn replies() {
+        assert_eq!(
+            render(&[
+                sent(1), reply(1),
+                sent(1), Event::ProbeTimedOut { ttl: 1, sequence: 1 },
+                sent(1), reply(1),
+                Event::HopCompleted(Hop::new(1)),
+            ]),
+            " 1  192.0.2.1 [documentation]  1.500 ms *  1.500 ms\n",
+        );
+    }
+
+    #[test]
+    fn names() {
+        let mut text = Text::new(vec![], Names::fixed(&[(ROUTER, "router")]).into());
+
+        for event in &[sent(1), reply(1), sent(1), reply(1)] {
+            text.on_event(event);
+        }
+
+        assert!(text.output.is_empty());
+
+        text.on_event(&Event::HopCompleted(Hop::new(1)));
+
+        assert_eq!(
+            String::from_utf8(text.output).unwrap(),
+            " 1  router (192.0.2.1) [documentation]  1.500 ms  1.500 ms\n",
+        );
+    }
+
+    #[test]
+    fn origins() {
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+        asns.insert("198.51.100.0/24".parse().unwrap(), 64501);
+
+        let annotations = Annotations::from(Names::disabled()).with_asns(asns);
+        let mut text = Text::new(vec![], annotations);
+
+        for event in &[
+            sent(1), reply(1), Event::HopCompleted(Hop::new(1)),
+            sent(2), reply(2), Event::HopCompleted(Hop::new(2)),
+            sent(3), reply_from(3, TARGET), Event::HopCompleted(Hop::new(3)),
+        ] {
+            text.on_event(event);
+        }
+
+        assert_eq!(String::from_utf8(text.output).unwrap(), concat!(
+            " 1  192.0.2.1 [documentation] [AS64500]  1.500 ms\n",
+            " 2  192.0.2.1 [documentation] [AS64500]  1.500 ms\n",
+            " 3  198.51.100.1 [documentation] [AS64501*]  1.500 ms\n",
+        ));
+    }
+
+    #[test]
+    fn mpls() {
+        let mut response = Response {
+            source: ROUTER,
+            type_: TIME_EXCEEDED,
+            quoted_ttl: Some(2),
+            extensions: vec![Extension {
+                class: MPLS_CLASS,
+                type_: MPLS_INCOMING_STACK,
+                payload: vec![0x05, 0xdc, 0x11, 1],
+            }],
+            ..Default::default()
+        };
+
+        let mut text = Text::new(vec![], Names::disabled().into()).with_mpls(true);
+
+        text.on_event(&sent(1));
+        text.on_event(&Event::ReplyReceived {
+            ttl: 1,
+            sequence: 0,
+            response: response.clone(),
+            rtt: Duration::from_micros(1500),
+        });
+        text.on_event(&Event::HopCompleted(Hop::new(1)));
+
+        response.extensions.clear();
+
+        let mut hop = Hop::new(2);
+        hop.probes.push(ProbeResult {
+            sequence: 1,
+            sent: SystemTime::UNIX_EPOCH,
+            status: ProbeStatus::Reply { response, rtt: Duration::from_millis(2) },
+        });
+
+        text.on_event(&Event::TraceFinished(Trace {
+            target: TARGET,
+            started: SystemTime::UNIX_EPOCH,
+            hops: vec![Hop::new(1), hop],
+        }));
+
+        assert_eq!(String::from_utf8(text.output).unwrap(), concat!(
+            " 1  192.0.2.1 [documentation]  1.500 ms <MPLS:L=24001,E=0,S=1,T=1> <q=2>\n",
+            "MPLS tunnel (implicit): router at hop 2\n",
+        ));
+    }
+
+    #[test]
+    fn tunnels() {
+        let tunnel = |kind, ingress, egress, routers: &[u8], hidden| describe(&Tunnel {
+            kind,
+            ingress,
+            egress,
+            routers: routers.to_vec(),
+            hidden,
+        });
+
+        assert_eq!(
+            tunnel(Kind::Explicit, Some(2), Some(6), &[3, 4, 5], None),
+            "MPLS tunnel (explicit): ingress hop 2, routers at hops 3-5, egress hop 6",
+        );
+        assert_eq!(
+            tunnel(Kind::Invisible, Some(7), Some(8), &[], Some(4)),
+            "MPLS tunnel (suspected invisible): ingress hop 7, egress hop 8, \
+                about 4 hidden hops",
+        );
+    }
+
+    #[test]
+    fn failures() {
+        assert_eq!(
+            render(&[
+                Event::ProbeFailed { ttl: 12, sequence: 0, error: Error::NoRoute },
+                sent(12), Event::ProbeTimedOut { ttl: 12, sequence: 1 },
+                Event::HopCompleted(Hop::new(12)),
+            ]),
+            "12  !H *\n",
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Formats the time as RFC 3339 in UTC with microsecond precision.
+//
+pub fn rfc3339(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = duration.as_secs();
+
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+        duration.subsec_micros(),
+    )
+}
+
+// Converts days since 1970-01-01 into a proleptic Gregorian date, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+//
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+            - day_of_era / 146_096) / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month as u32, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: u64, micros: u32) -> SystemTime {
+        UNIX_EPOCH + Duration::new(seconds, micros * 1000)
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(
+            rfc3339(at(951_782_400, 0)),
+            "2000-02-29T00:00:00.000000Z",
+        );
+    }
+
+    #[test]
+    fn end_of_year() {
+        assert_eq!(
+            rfc3339(at(1_609_459_199, 999_999)),
+            "2020-12-31T23:59:59.999999Z",
+        );
+    }
+
+    #[test]
+    fn some_time() {
+        assert_eq!(
+            rfc3339(at(1_792_402_245, 123_456)),
+            "2026-10-19T09:30:45.123456Z",
+        );
+    }
+}
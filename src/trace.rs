@@ -0,0 +1,158 @@
+use crate::{error::Error, response::Response};
+use std::{net::IpAddr, time::{Duration, SystemTime}};
+
+#[derive(Clone, Debug)]
+pub struct Trace {
+    pub target: IpAddr,
+    pub started: SystemTime,
+    pub hops: Vec<Hop>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hop {
+    pub ttl: u8,
+    pub probes: Vec<ProbeResult>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProbeResult {
+    pub sequence: u16,
+    pub sent: SystemTime,
+    pub status: ProbeStatus,
+}
+
+#[derive(Clone, Debug)]
+pub enum ProbeStatus {
+    Reply { response: Response, rtt: Duration },
+    Timeout,
+    Error(Error),
+}
+
+impl Trace {
+    pub fn reached(&self) -> bool {
+        self.hops.last().is_some_and(Hop::reached)
+    }
+}
+
+impl Hop {
+    pub fn new(ttl: u8) -> Self {
+        Self { ttl, probes: vec![] }
+    }
+
+    // Distinct addresses of the responders in the order they have replied.
+    //
+    pub fn responders(&self) -> Vec<IpAddr> {
+        let mut result: Vec<IpAddr> = vec![];
+
+        for response in self.probes.iter().filter_map(ProbeResult::response) {
+            if !result.contains(&response.source) {
+                result.push(response.source);
+            }
+        }
+
+        result
+    }
+
+    pub fn reached(&self) -> bool {
+        self.probes.iter().filter_map(ProbeResult::response)
+            .any(Response::is_echo_reply)
+    }
+
+    // Whether there is no point in probing further TTLs: the destination has
+    // replied or some router has reported it unreachable.
+    //
+    pub fn is_last(&self) -> bool {
+        self.probes.iter().filter_map(ProbeResult::response)
+            .any(|response| !response.is_time_exceeded())
+    }
+}
+
+impl ProbeResult {
+    pub fn response(&self) -> Option<&Response> {
+        match &self.status {
+            ProbeStatus::Reply { response, .. } => Some(response),
+            _ => None,
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        match self.status {
+            ProbeStatus::Reply { rtt, .. } => Some(rtt),
+            _ => None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&Error> {
+        match &self.status {
+            ProbeStatus::Error(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{timeout, OTHER, ROUTER},
+        response::{ECHO_REPLY, TIME_EXCEEDED, UNREACHABLE},
+    };
+
+    fn reply(source: IpAddr, type_: u8) -> ProbeResult {
+        crate::fixtures::reply(source, type_, 1000)
+    }
+
+    fn hop(probes: Vec<ProbeResult>) -> Hop {
+        Hop { ttl: 1, probes }
+    }
+
+    #[test]
+    fn responders() {
+        let hop = hop(vec![
+            reply(OTHER, TIME_EXCEEDED),
+            timeout(),
+            reply(ROUTER, TIME_EXCEEDED),
+            reply(OTHER, TIME_EXCEEDED),
+        ]);
+
+        assert_eq!(hop.responders(), vec![OTHER, ROUTER]);
+    }
+
+    #[test]
+    fn responders_empty() {
+        assert!(hop(vec![timeout(), timeout()]).responders().is_empty());
+    }
+
+    #[test]
+    fn time_exceeded_is_not_last() {
+        let hop = hop(vec![timeout(), reply(ROUTER, TIME_EXCEEDED)]);
+
+        assert!(!hop.reached());
+        assert!(!hop.is_last());
+    }
+
+    #[test]
+    fn echo_reply_is_last() {
+        let hop = hop(vec![timeout(), reply(ROUTER, ECHO_REPLY)]);
+
+        assert!(hop.reached());
+        assert!(hop.is_last());
+    }
+
+    #[test]
+    fn unreachable_is_last() {
+        let hop = hop(vec![reply(ROUTER, UNREACHABLE)]);
+
+        assert!(!hop.reached());
+        assert!(hop.is_last());
+    }
+
+    #[test]
+    fn rtt() {
+        assert_eq!(
+            reply(ROUTER, ECHO_REPLY).rtt(),
+            Some(Duration::from_millis(1)),
+        );
+        assert_eq!(timeout().rtt(), None);
+    }
+}
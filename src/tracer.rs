@@ -0,0 +1,372 @@
+use crate::{
+    error::Error,
+    event::{self, Event, Observer, Packet},
+    request::Request,
+    response::Response,
+    trace::{Hop, ProbeResult, ProbeStatus, Trace},
+    transport::Transport,
+};
+use std::{
+    net::IpAddr,
+    ops::RangeInclusive,
+    time::{Duration, SystemTime},
+};
+
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeMethod {
+    IcmpEcho,
+}
+
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    pub(crate) target: IpAddr,
+    pub(crate) method: ProbeMethod,
+    pub(crate) first_ttl: u8,
+    pub(crate) max_ttl: u8,
+    pub(crate) queries: u16,
+    pub(crate) wait: Duration,
+    pub(crate) ident: u16,
+    pub(crate) tos: u8,
+}
+
+impl Tracer {
+    pub fn new(target: IpAddr) -> Self {
+        Self {
+            target,
+            method: ProbeMethod::IcmpEcho,
+            first_ttl: 1,
+            max_ttl: 30,
+            queries: 3,
+            wait: Duration::from_secs(5),
+            ident: std::process::id() as u16,
+            tos: 0,
+        }
+    }
+
+    pub fn method(mut self, method: ProbeMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn first_ttl(mut self, first_ttl: u8) -> Self {
+        self.first_ttl = first_ttl;
+        self
+    }
+
+    pub fn max_ttl(mut self, max_ttl: u8) -> Self {
+        self.max_ttl = max_ttl;
+        self
+    }
+
+    pub fn queries(mut self, queries: u16) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    pub fn wait(mut self, wait: Duration) -> Self {
+        self.wait = wait;
+        self
+    }
+
+    // Identifier of the probes. Replies are matched to the probes by it, so
+    // concurrent traces must use different ones.
+    //
+    pub fn ident(mut self, ident: u16) -> Self {
+        self.ident = ident;
+        self
+    }
+
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.tos = tos;
+        self
+    }
+
+    pub fn trace<T: Transport + ?Sized>(
+        &self,
+        transport: &T,
+    ) -> Result<Trace, Error> {
+        self.trace_with(transport, &mut |_: &Event| {})
+    }
+
+    // Same as `trace`, but reports the progress to the observer as it happens.
+    //
+    pub fn trace_with<T: Transport + ?Sized, O: Observer + ?Sized>(
+        &self,
+        transport: &T,
+        observer: &mut O,
+    ) -> Result<Trace, Error> {
+        transport.set_tos(self.tos)?;
+
+        let mut progress = Progress::new(self, transport.now());
+
+        while let Some((ttl, sequence)) = progress.next_probe(observer) {
+            progress.add(self.probe_ttl(transport, observer, ttl, sequence)?);
+        }
+
+        Ok(progress.finish(observer))
+    }
+
+    // Sends a single probe and waits for the reply to it, for those who pick
+    // the TTLs to probe themselves instead of tracing hop by hop.
+    //
+    pub fn probe_ttl<T: Transport + ?Sized, O: Observer + ?Sized>(
+        &self,
+        transport: &T,
+        observer: &mut O,
+        ttl: u8,
+        sequence: u16,
+    ) -> Result<ProbeResult, Error> {
+        let probe = self.probe(transport, observer, ttl, &self.request(sequence))?;
+
+        observer.on_event(&Event::probe_completed(ttl, &probe));
+
+        Ok(probe)
+    }
+
+    pub(crate) fn request(&self, sequence: u16) -> Request {
+        match self.method {
+            ProbeMethod::IcmpEcho => Request::new(self.ident, sequence),
+        }
+    }
+
+    fn probe<T: Transport + ?Sized, O: Observer + ?Sized>(
+        &self,
+        transport: &T,
+        observer: &mut O,
+        current_ttl: u8,
+        request: &Request,
+    ) -> Result<ProbeResult, Error> {
+        let packet = request.to_vec();
+        let result = send_request(transport, current_ttl, self.target, &packet);
+        let sent = transport.now();
+
+        let status = match result {
+            Err(error) if error.is_transient() => ProbeStatus::Error(error),
+            Err(error) => return Err(error),
+            Ok(()) => {
+                self.report_sent(observer, current_ttl, request, &packet, sent);
+
+                match recv_response1(
+                    transport,
+                    observer,
+                    current_ttl,
+                    request,
+                    sent + self.wait,
+                )? {
+                    None => ProbeStatus::Timeout,
+                    Some((response, received)) => ProbeStatus::Reply {
+                        response,
+                        rtt: received.duration_since(sent).unwrap_or_default(),
+                    },
+                }
+            }
+        };
+
+        Ok(ProbeResult { sequence: request.sequence, sent, status })
+    }
+
+    // Reports the probe which has just been sent. The time it has been sent
+    // is taken after sending, so that the transport can set the time of the
+    // probe and the RTT does not include the sending.
+    //
+    pub(crate) fn report_sent<O: Observer + ?Sized>(
+        &self,
+        observer: &mut O,
+        ttl: u8,
+        request: &Request,
+        packet: &[u8],
+        sent: SystemTime,
+    ) {
+        observer.on_packet(&Packet::Sent {
+            ttl,
+            sequence: request.sequence,
+            time: sent,
+            destination: self.target,
+            data: packet,
+        });
+
+        observer.on_event(&Event::ProbeSent {
+            ttl,
+            sequence: request.sequence,
+            sent,
+        });
+    }
+}
+
+// The probes of a trace, hop by hop from the first TTL until the destination
+// or the maximum TTL is reached, and what has come of them so far. Both the
+// blocking and the async traces go through it.
+//
+pub(crate) struct Progress {
+    ttls: RangeInclusive<u8>,
+    queries: usize,
+    sequence: u16,
+    hop: Option<Hop>,
+    trace: Trace,
+}
+
+impl Progress {
+    pub(crate) fn new(tracer: &Tracer, started: SystemTime) -> Self {
+        Self {
+            ttls: tracer.first_ttl..=tracer.max_ttl,
+            queries: tracer.queries.into(),
+            sequence: 0,
+            hop: None,
+            trace: Trace { target: tracer.target, started, hops: vec![] },
+        }
+    }
+
+    // The TTL and the sequence number of the next probe, until there is none
+    // left. The hops are reported as they are done.
+    //
+    pub(crate) fn next_probe<O: Observer + ?Sized>(
+        &mut self,
+        observer: &mut O,
+    ) -> Option<(u8, u16)> {
+        loop {
+            match self.hop.take() {
+                Some(hop) if hop.probes.len() < self.queries => {
+                    let probe = (hop.ttl, self.sequence);
+
+                    self.sequence = self.sequence.wrapping_add(1);
+                    self.hop = Some(hop);
+
+                    return Some(probe)
+                }
+                Some(hop) => {
+                    event::report_hop(observer, &hop);
+
+                    let is_last = hop.is_last();
+
+                    self.trace.hops.push(hop);
+
+                    if is_last {
+                        return None
+                    }
+                }
+                None => self.hop = Some(Hop::new(self.ttls.next()?)),
+            }
+        }
+    }
+
+    pub(crate) fn add(&mut self, probe: ProbeResult) {
+        if let Some(hop) = &mut self.hop {
+            hop.probes.push(probe);
+        }
+    }
+
+    pub(crate) fn finish<O: Observer + ?Sized>(self, observer: &mut O) -> Trace {
+        observer.on_event(&Event::TraceFinished(self.trace.clone()));
+
+        self.trace
+    }
+}
+
+fn send_request<T: Transport + ?Sized>(
+    transport: &T,
+    current_ttl: u8,
+    host: IpAddr,
+    packet: &[u8],
+) -> Result<(), Error> {
+    transport.set_ttl(current_ttl)?;
+    transport.send_to(packet, host)
+}
+
+fn recv_response1<T: Transport + ?Sized, O: Observer + ?Sized>(
+    transport: &T,
+    observer: &mut O,
+    current_ttl: u8,
+    request: &Request,
+    time_limit: SystemTime,
+) -> Result<Option<(Response, SystemTime)>, Error> {
+    let mut response_body_data: [u8; 1024] = [0; 1024];
+
+    while transport.now() < time_limit {
+        if let Some((data, tmp_response)) =
+            recv_response2(transport, &mut response_body_data, time_limit)?
+        {
+            let received = transport.now();
+
+            let tmp_response = tmp_response
+                .filter(|response| response.does_match_request(request));
+
+            observer.on_packet(&Packet::Received {
+                time: received,
+                data,
+                probe: tmp_response.as_ref()
+                    .map(|_| (current_ttl, request.sequence)),
+            });
+
+            if let Some(tmp_response) = tmp_response {
+                return Ok(Some((tmp_response, received)))
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// The raw packet with the response parsed from it, if it is one.
+//
+type Received<'a> = (&'a [u8], Option<Response>);
+
+fn recv_response2<'a, T: Transport + ?Sized>(
+    transport: &T,
+    response_body_data: &'a mut [u8],
+    time_limit: SystemTime,
+) -> Result<Option<Received<'a>>, Error> {
+    Ok(match transport.recv_until(response_body_data, time_limit)? {
+        None => None,
+        Some((response_body_size, response_sockaddr_inx)) => {
+            let data = &response_body_data[0..response_body_size];
+
+            Some((data, Response::parse(&response_sockaddr_inx, data)))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::{ECHO_REPLY, TIME_EXCEEDED};
+    use std::{net::Ipv4Addr, time::UNIX_EPOCH};
+
+    fn probes(tracer: &Tracer, type_: impl Fn(u8) -> u8) -> Vec<(u8, u16)> {
+        let mut progress = Progress::new(tracer, UNIX_EPOCH);
+        let mut result = vec![];
+
+        while let Some((ttl, sequence)) = progress.next_probe(&mut |_: &Event| {}) {
+            result.push((ttl, sequence));
+
+            progress.add(ProbeResult {
+                sequence,
+                sent: UNIX_EPOCH,
+                status: ProbeStatus::Reply {
+                    response: Response { type_: type_(ttl), ..Default::default() },
+                    rtt: Duration::ZERO,
+                },
+            });
+        }
+
+        result
+    }
+
+    #[test]
+    fn progress() {
+        let tracer = Tracer::new(IpAddr::V4(Ipv4Addr::LOCALHOST)).queries(2);
+
+        assert_eq!(
+            probes(&tracer.clone().first_ttl(3), |ttl| match ttl {
+                4 => ECHO_REPLY,
+                _ => TIME_EXCEEDED,
+            }),
+            [(3, 0), (3, 1), (4, 2), (4, 3)],
+        );
+
+        assert_eq!(
+            probes(&tracer.first_ttl(254).max_ttl(255), |_| TIME_EXCEEDED),
+            [(254, 0), (254, 1), (255, 2), (255, 3)],
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{error::Error, sockaddr_inx::SockaddrInx, socket::Socket};
+use std::{net::IpAddr, time::SystemTime};
+
+// What the tracer sends the probes with and receives the responses from. It
+// also keeps the time, so a trace can be run on something other than the
+// network, e.g. on packets captured earlier.
+//
+pub trait Transport {
+    fn now(&self) -> SystemTime;
+
+    fn set_ttl(&self, ttl: u8) -> Result<(), Error>;
+
+    fn set_tos(&self, tos: u8) -> Result<(), Error>;
+
+    fn send_to(&self, message: &[u8], host: IpAddr) -> Result<(), Error>;
+
+    // Returns `None` if nothing has been received by the deadline. Received
+    // packets start with the IP header.
+    //
+    fn recv_until(
+        &self,
+        buffer: &mut [u8],
+        deadline: SystemTime,
+    ) -> Result<Option<(usize, SockaddrInx)>, Error>;
+}
+
+impl Transport for Socket {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn set_ttl(&self, ttl: u8) -> Result<(), Error> {
+        Socket::set_ttl(self, ttl)
+    }
+
+    fn set_tos(&self, tos: u8) -> Result<(), Error> {
+        Socket::set_tos(self, tos)
+    }
+
+    fn send_to(&self, message: &[u8], host: IpAddr) -> Result<(), Error> {
+        Socket::send_to(self, message, host)
+    }
+
+    fn recv_until(
+        &self,
+        buffer: &mut [u8],
+        deadline: SystemTime,
+    ) -> Result<Option<(usize, SockaddrInx)>, Error> {
+        match deadline.duration_since(SystemTime::now()) {
+            Err(_) => Ok(None),
+            Ok(timeout) => {
+                self.set_timeout(timeout)?;
+                self.recv_from(buffer)
+            }
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Prefix {
+    pub address: IpAddr,
+    pub length: u8,
+}
+
+// Longest-prefix-match table of IPv4 and IPv6 prefixes. The nodes are kept in
+// a single vector, so that a full routing table does not take a separate
+// allocation per bit.
+//
+pub struct PrefixTrie<T> {
+    nodes: Vec<Node>,
+    values: Vec<(Prefix, T)>,
+}
+
+// Children and the value are indices, zero stands for none: the roots are the
+// first two nodes and are nobody's children, and the values are numbered from
+// one.
+//
+#[derive(Clone, Copy, Default)]
+struct Node {
+    children: [u32; 2],
+    value: u32,
+}
+
+const IPV4_ROOT: usize = 0;
+const IPV6_ROOT: usize = 1;
+
+impl Prefix {
+    // The bits of the address beyond the length are cleared. Returns `None`
+    // if the length is too big for the address.
+    //
+    pub fn new(address: IpAddr, length: u8) -> Option<Self> {
+        let address = match address {
+            IpAddr::V4(address) if length <= 32 => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(length))
+                    .unwrap_or(0);
+
+                IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+            }
+            IpAddr::V6(address) if length <= 128 => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(length))
+                    .unwrap_or(0);
+
+                IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+            }
+            _ => return None,
+        };
+
+        Some(Self { address, length })
+    }
+
+    pub fn contains(&self, address: IpAddr) -> bool {
+        Self::new(address, self.length)
+            .is_some_and(|prefix| prefix == *self)
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}/{}", self.address, self.length)
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = ();
+
+    // An address without the length is a prefix of a single address.
+    //
+    fn from_str(text: &str) -> Result<Self, ()> {
+        let (address, length) = match text.split_once('/') {
+            Some((address, length)) => {
+                let address: IpAddr = address.parse().map_err(|_| ())?;
+
+                (address, length.parse().map_err(|_| ())?)
+            }
+            None => {
+                let address: IpAddr = text.parse().map_err(|_| ())?;
+
+                (address, if address.is_ipv4() { 32 } else { 128 })
+            }
+        };
+
+        Self::new(address, length).ok_or(())
+    }
+}
+
+impl<T> Default for PrefixTrie<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PrefixTrie<T> {
+    pub fn new() -> Self {
+        Self { nodes: vec![Node::default(); 2], values: vec![] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Returns the value the prefix has had before, if any.
+    //
+    pub fn insert(&mut self, prefix: Prefix, value: T) -> Option<T> {
+        let mut index = root(prefix.address);
+
+        for bit in bits(prefix.address).take(prefix.length.into()) {
+            index = match self.nodes[index].children[bit] {
+                0 => {
+                    self.nodes.push(Node::default());
+
+                    let child = self.nodes.len() - 1;
+                    self.nodes[index].children[bit] = child as u32;
+
+                    child
+                }
+                child => child as usize,
+            };
+        }
+
+        match self.nodes[index].value {
+            0 => {
+                self.values.push((prefix, value));
+                self.nodes[index].value = self.values.len() as u32;
+
+                None
+            }
+            value_index => Some(std::mem::replace(
+                &mut self.values[value_index as usize - 1].1,
+                value,
+            )),
+        }
+    }
+
+    pub fn get_mut(&mut self, prefix: Prefix) -> Option<&mut T> {
+        let mut index = root(prefix.address);
+
+        for bit in bits(prefix.address).take(prefix.length.into()) {
+            index = match self.nodes[index].children[bit] {
+                0 => return None,
+                child => child as usize,
+            };
+        }
+
+        match self.nodes[index].value {
+            0 => None,
+            value_index => Some(&mut self.values[value_index as usize - 1].1),
+        }
+    }
+
+    // The most specific prefix containing the address, with its value.
+    //
+    pub fn lookup(&self, address: IpAddr) -> Option<(Prefix, &T)> {
+        let mut index = root(address);
+        let mut found = self.nodes[index].value;
+
+        for bit in bits(address) {
+            index = match self.nodes[index].children[bit] {
+                0 => break,
+                child => child as usize,
+            };
+
+            if self.nodes[index].value != 0 {
+                found = self.nodes[index].value;
+            }
+        }
+
+        match found {
+            0 => None,
+            value_index => {
+                let (prefix, value) = &self.values[value_index as usize - 1];
+
+                Some((*prefix, value))
+            }
+        }
+    }
+
+    // The prefixes containing the prefix, itself included, from the least
+    // specific one, with their values.
+    //
+    pub fn covering(&self, prefix: Prefix) -> Vec<(Prefix, &T)> {
+        let mut index = root(prefix.address);
+        let mut found = vec![self.nodes[index].value];
+
+        for bit in bits(prefix.address).take(prefix.length.into()) {
+            index = match self.nodes[index].children[bit] {
+                0 => break,
+                child => child as usize,
+            };
+
+            found.push(self.nodes[index].value);
+        }
+
+        found.into_iter()
+            .filter(|value_index| *value_index != 0)
+            .map(|value_index| {
+                let (prefix, value) = &self.values[value_index as usize - 1];
+
+                (*prefix, value)
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Prefix, &T)> {
+        self.values.iter().map(|(prefix, value)| (*prefix, value))
+    }
+}
+
+fn root(address: IpAddr) -> usize {
+    match address {
+        IpAddr::V4(_) => IPV4_ROOT,
+        IpAddr::V6(_) => IPV6_ROOT,
+    }
+}
+
+// Bits of the address from the most significant one.
+//
+fn bits(address: IpAddr) -> impl Iterator<Item = usize> {
+    let (value, width) = match address {
+        IpAddr::V4(address) => (u128::from(u32::from(address)) << 96, 32),
+        IpAddr::V6(address) => (u128::from(address), 128),
+    };
+
+    (0..width).map(move |bit| ((value >> (127 - bit)) & 1) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(text: &str) -> Prefix {
+        text.parse().unwrap()
+    }
+
+    fn address(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(prefix("192.0.2.77/24").to_string(), "192.0.2.0/24");
+        assert_eq!(prefix("192.0.2.77").to_string(), "192.0.2.77/32");
+        assert_eq!(prefix("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(prefix("0.0.0.0/0").to_string(), "0.0.0.0/0");
+        assert!("192.0.2.0/33".parse::<Prefix>().is_err());
+        assert!("192.0.2/24".parse::<Prefix>().is_err());
+    }
+
+    #[test]
+    fn contains() {
+        assert!(prefix("192.0.2.0/24").contains(address("192.0.2.255")));
+        assert!(!prefix("192.0.2.0/24").contains(address("192.0.3.0")));
+        assert!(!prefix("192.0.2.0/24").contains(address("::ffff:192.0.2.1")));
+    }
+
+    #[test]
+    fn longest_match() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(prefix("10.0.0.0/8"), 1);
+        trie.insert(prefix("10.1.0.0/16"), 2);
+        trie.insert(prefix("10.1.2.0/24"), 3);
+        trie.insert(prefix("2001:db8::/32"), 4);
+
+        let lookup = |text| trie.lookup(address(text))
+            .map(|(prefix, value)| (prefix.to_string(), *value));
+
+        assert_eq!(lookup("10.1.2.3"), Some(("10.1.2.0/24".to_string(), 3)));
+        assert_eq!(lookup("10.1.3.3"), Some(("10.1.0.0/16".to_string(), 2)));
+        assert_eq!(lookup("10.2.0.1"), Some(("10.0.0.0/8".to_string(), 1)));
+        assert_eq!(lookup("11.0.0.1"), None);
+        assert_eq!(lookup("2001:db8::1"), Some(("2001:db8::/32".to_string(), 4)));
+        assert_eq!(lookup("2001:db9::1"), None);
+        assert_eq!(trie.len(), 4);
+    }
+
+    #[test]
+    fn covering() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(prefix("10.0.0.0/8"), 1);
+        trie.insert(prefix("10.1.0.0/16"), 2);
+        trie.insert(prefix("10.1.2.0/24"), 3);
+
+        let covering = |text| trie.covering(prefix(text)).into_iter()
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+
+        assert_eq!(covering("10.1.0.0/16"), [1, 2]);
+        assert_eq!(covering("10.1.2.128/25"), [1, 2, 3]);
+        assert_eq!(covering("10.2.0.0/16"), [1]);
+        assert_eq!(covering("10.0.0.0/7"), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn default_route() {
+        let mut trie = PrefixTrie::new();
+
+        trie.insert(prefix("0.0.0.0/0"), "default");
+
+        assert_eq!(trie.lookup(address("192.0.2.1")).unwrap().1, &"default");
+        assert!(trie.lookup(address("::1")).is_none());
+    }
+
+    #[test]
+    fn replace() {
+        let mut trie = PrefixTrie::new();
+
+        assert_eq!(trie.insert(prefix("192.0.2.0/24"), 1), None);
+        assert_eq!(trie.insert(prefix("192.0.2.0/24"), 2), Some(1));
+
+        *trie.get_mut(prefix("192.0.2.0/24")).unwrap() += 1;
+
+        assert_eq!(trie.lookup(address("192.0.2.1")).unwrap().1, &3);
+        assert!(trie.get_mut(prefix("192.0.2.0/25")).is_none());
+        assert_eq!(trie.len(), 1);
+    }
+}
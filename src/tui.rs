@@ -0,0 +1,376 @@
+use crate::{
+    annotations::{Annotations, Boundaries},
+    options::Options,
+    stats::{Sample, Statistics, INTERVAL},
+    terminal::Terminal,
+};
+use rustraceroute::{event::Tee, Error, Event, Tracer, Transport};
+use std::{net::IpAddr, time::{Duration, Instant}};
+
+// How often the keys are checked while paused.
+//
+const PAUSED_POLL: Duration = Duration::from_millis(100);
+
+const HISTORY: usize = 50;
+
+const HOST_WIDTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Statistics,
+    History,
+}
+
+// Probes all the TTLs over and over again, showing what has been seen so far
+// as a table of hops.
+//
+pub struct Tui {
+    host: String,
+    address: IpAddr,
+    statistics: Statistics,
+    annotations: Annotations,
+    show_names: bool,
+    show_asns: bool,
+    mode: Mode,
+    paused: bool,
+}
+
+impl Tui {
+    pub fn new(
+        options: &Options,
+        address: IpAddr,
+        annotations: Annotations,
+    ) -> Self {
+        Self {
+            host: options.host().to_string(),
+            address,
+            statistics: Statistics::new(options.first_ttl, options.max_ttl),
+            mode: Mode::Statistics,
+            show_names: annotations.names.is_enabled(),
+            show_asns: annotations.has_asns(),
+            annotations,
+            paused: false,
+        }
+    }
+
+    // Returns whether to go on.
+    //
+    fn key(&mut self, key: u8) -> bool {
+        match key {
+            b'q' | 3 => return false,
+            b'p' => self.paused = !self.paused,
+            b'r' => self.statistics.reset(),
+            b'n' => self.show_names = !self.show_names,
+            b'a' => self.show_asns = !self.show_asns,
+            b'd' => {
+                self.mode = match self.mode {
+                    Mode::Statistics => Mode::History,
+                    Mode::History => Mode::Statistics,
+                };
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn render(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "rustraceroute to {} ({}){}",
+                self.host,
+                self.address,
+                if self.paused { " [paused]" } else { "" },
+            ),
+            format!(
+                "Keys: p pause  r reset counters  d display mode  n names  {}q quit",
+                if self.annotations.has_asns() { "a AS numbers  " } else { "" },
+            ),
+            String::new(),
+        ];
+
+        lines.push(match self.mode {
+            Mode::Statistics => format!(
+                "{:width$} {:>6} {:>5} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                "",
+                "Loss%",
+                "Snt",
+                "Last",
+                "Avg",
+                "Best",
+                "Wrst",
+                "StDev",
+                width = HOST_WIDTH + 4,
+            ),
+            Mode::History => String::new(),
+        });
+
+        let mut boundaries = Boundaries::default();
+
+        for (ttl, hop) in self.statistics.hops() {
+            // Names which are still being looked up show up when they are
+            // found.
+            //
+            let mut host = match hop.responders.first() {
+                Some(address) => self.host(*address, &mut boundaries),
+                None => "???".to_string(),
+            };
+
+            if let Some(annotation) = &hop.annotation {
+                host = format!("{} {}", host, annotation);
+            }
+
+            let line = format!("{:2}. {:width$}", ttl, host, width = HOST_WIDTH);
+
+            lines.push(match self.mode {
+                Mode::Statistics => format!(
+                    "{} {:>5.1}% {:>5} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                    line,
+                    hop.loss(),
+                    hop.sent(),
+                    milliseconds(hop.last()),
+                    milliseconds(hop.mean()),
+                    milliseconds(hop.best()),
+                    milliseconds(hop.worst()),
+                    milliseconds(hop.standard_deviation()),
+                ),
+                Mode::History => format!(
+                    "{} {}",
+                    line,
+                    hop.samples.iter().rev().take(HISTORY).rev()
+                        .map(|sample| match sample {
+                            Sample::Reply(_) => '.',
+                            Sample::Lost => '?',
+                            Sample::Failed => '!',
+                        })
+                        .collect::<String>(),
+                ),
+            });
+
+            // Every other responder of the hop gets a line of its own.
+            //
+            for responder in hop.responders.iter().skip(1) {
+                lines.push(format!(
+                    "    {}",
+                    self.host(*responder, &mut boundaries),
+                ));
+            }
+        }
+
+        lines
+    }
+
+    // The kinds of special address space the responder is in follow its
+    // name, and so do its origin ASes, an asterisk marking where the path
+    // enters another AS.
+    //
+    fn host(&self, address: IpAddr, boundaries: &mut Boundaries) -> String {
+        let mut result = match self.annotations.names.get(address)
+            .filter(|_| self.show_names)
+        {
+            Some(name) => name.to_string(),
+            None => address.to_string(),
+        };
+
+        for tag in self.annotations.tags(address) {
+            result = format!("{} [{}]", result, tag);
+        }
+
+        if let Some(origin) = self.annotations.origin(address)
+            .filter(|_| self.show_asns)
+        {
+            result = format!("{} [{}]", result, boundaries.label(origin));
+        }
+
+        result
+    }
+
+    // Probes one TTL at a time, checking the keys in between. Returns when
+    // asked to quit.
+    //
+    pub fn run<T: Transport + ?Sized>(
+        mut self,
+        tracer: &Tracer,
+        transport: &T,
+    ) -> Result<(), Error> {
+        let terminal = Terminal::open()?;
+        let mut sequence: u16 = 0;
+
+        loop {
+            let mut ttl = Some(self.statistics.first_ttl());
+
+            while let Some(current) = ttl {
+                let timeout = match self.paused {
+                    true => PAUSED_POLL,
+                    false => Duration::ZERO,
+                };
+
+                if let Some(key) = terminal.key(timeout)? {
+                    if !self.key(key) {
+                        return Ok(())
+                    }
+
+                    terminal.draw(&self.render())?;
+                }
+
+                if self.paused {
+                    continue
+                }
+
+                let names = &self.annotations.names;
+
+                tracer.probe_ttl(
+                    transport,
+                    &mut Tee(
+                        &mut self.statistics,
+                        &mut |event: &Event| names.prefetch(event),
+                    ),
+                    current,
+                    sequence,
+                )?;
+
+                sequence = sequence.wrapping_add(1);
+                ttl = self.statistics.next_ttl(current);
+
+                terminal.draw(&self.render())?;
+            }
+
+            let deadline = Instant::now() + INTERVAL;
+
+            while let Some(timeout) =
+                deadline.checked_duration_since(Instant::now())
+            {
+                if let Some(key) = terminal.key(timeout)? {
+                    if !self.key(key) {
+                        return Ok(())
+                    }
+
+                    terminal.draw(&self.render())?;
+                }
+            }
+        }
+    }
+}
+
+fn milliseconds(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1}", duration.as_secs_f64() * 1000.0),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{reply_received, OTHER, ROUTER, TARGET},
+        names::Names,
+    };
+    use clap::Clap;
+    use rustraceroute::{
+        asn::AsDatabase,
+        response::{ECHO_REPLY, TIME_EXCEEDED},
+        Event,
+        Observer,
+    };
+
+    fn tui() -> Tui {
+        let options = Options::parse_from(["rustraceroute", "example.com"]);
+
+        let mut asns = AsDatabase::default();
+        asns.insert("192.0.2.0/24".parse().unwrap(), 64500);
+        asns.insert("198.51.100.0/24".parse().unwrap(), 64501);
+
+        let mut tui = Tui::new(
+            &options,
+            TARGET,
+            Annotations::from(Names::fixed(&[(ROUTER, "router")]))
+                .with_asns(asns),
+        );
+        tui.show_asns = false;
+
+        tui
+    }
+
+    fn reply(tui: &mut Tui, ttl: u8, source: IpAddr, type_: u8, millis: u64) {
+        tui.statistics.on_event(&reply_received(ttl, source, type_, millis * 1000));
+    }
+
+    fn timeout(tui: &mut Tui, ttl: u8) {
+        tui.statistics.on_event(&Event::ProbeTimedOut { ttl, sequence: 0 });
+    }
+
+    #[test]
+    fn destination() {
+        let mut tui = tui();
+
+        reply(&mut tui, 1, ROUTER, TIME_EXCEEDED, 1);
+        timeout(&mut tui, 2);
+        reply(&mut tui, 3, TARGET, ECHO_REPLY, 3);
+        timeout(&mut tui, 4);
+
+        assert_eq!(tui.render().len(), 4 + 3);
+
+        tui.key(b'r');
+
+        assert_eq!(tui.render().len(), 4);
+    }
+
+    #[test]
+    fn render() {
+        let mut tui = tui();
+
+        reply(&mut tui, 1, ROUTER, TIME_EXCEEDED, 1);
+        reply(&mut tui, 1, OTHER, TIME_EXCEEDED, 3);
+        timeout(&mut tui, 2);
+
+        assert_eq!(&tui.render()[3..], &[
+            format!("{:36} {:>6} {:>5} {:>7} {:>7} {:>7} {:>7} {:>7}",
+                "", "Loss%", "Snt", "Last", "Avg", "Best", "Wrst", "StDev"),
+            format!("{:36}   0.0%     2     3.0     2.0     1.0     3.0     1.4",
+                " 1. router [documentation]"),
+            "    192.0.2.2 [documentation]".to_string(),
+            format!("{:36} 100.0%     1{:40}", " 2. ???", ""),
+        ]);
+
+        tui.key(b'd');
+        tui.key(b'n');
+
+        assert_eq!(&tui.render()[4..], &[
+            format!("{:36} ..", " 1. 192.0.2.1 [documentation]"),
+            "    192.0.2.2 [documentation]".to_string(),
+            format!("{:36} ?", " 2. ???"),
+        ]);
+    }
+
+    #[test]
+    fn keys() {
+        let mut tui = tui();
+
+        assert!(tui.key(b'p'));
+        assert!(tui.paused);
+        assert!(tui.render()[0].ends_with(" [paused]"));
+        assert!(tui.key(b'p'));
+        assert!(!tui.paused);
+        assert!(!tui.key(b'q'));
+        assert!(!tui.key(3));
+    }
+
+    #[test]
+    fn origins() {
+        let mut tui = tui();
+
+        reply(&mut tui, 1, ROUTER, TIME_EXCEEDED, 1);
+        reply(&mut tui, 1, OTHER, TIME_EXCEEDED, 1);
+        reply(&mut tui, 2, TARGET, ECHO_REPLY, 1);
+
+        tui.key(b'a');
+
+        let lines = tui.render();
+
+        assert!(lines[1].contains("  a AS numbers  "));
+        assert!(lines[4].starts_with(" 1. router [documentation] [AS64500] "));
+        assert_eq!(lines[5], "    192.0.2.2 [documentation] [AS64500]");
+        assert!(lines[6].starts_with(" 2. 198.51.100.1 [documentation] [AS64501*]"));
+    }
+}
@@ -0,0 +1,825 @@
+use crate::{
+    extension::Extension,
+    response::Response,
+    trace::{Hop, ProbeResult, ProbeStatus, Trace},
+    tracer::Tracer,
+};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Binary format of scamper, see scamper_file_warts.c and trace_warts.c in its
+// sources. Every object starts with the magic, the type and the length of the
+// object data.
+//
+const MAGIC: u16 = 0x1205;
+
+const OBJECT_LIST:        u16 = 1;
+const OBJECT_CYCLE_START: u16 = 2;
+const OBJECT_CYCLE_STOP:  u16 = 4;
+const OBJECT_TRACE:       u16 = 6;
+
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_IPV6: u8 = 2;
+
+// Objects which refer to the list and the cycle use ids local to the file.
+//
+const LIST_ID:  u32 = 1;
+const CYCLE_ID: u32 = 1;
+
+const TRACE_LIST_ID:    usize = 1;
+const TRACE_CYCLE_ID:   usize = 2;
+const TRACE_START:      usize = 5;
+const TRACE_STOP_R:     usize = 6;
+const TRACE_STOP_D:     usize = 7;
+const TRACE_ATTEMPTS:   usize = 9;
+const TRACE_HOPLIMIT:   usize = 10;
+const TRACE_TYPE:       usize = 11;
+const TRACE_PROBE_S:    usize = 12;
+const TRACE_FIRSTHOP:   usize = 15;
+const TRACE_TOS:        usize = 16;
+const TRACE_WAIT:       usize = 17;
+const TRACE_HOP_COUNT:  usize = 19;
+const TRACE_ADDR_SRC:   usize = 26;
+const TRACE_ADDR_DST:   usize = 27;
+
+const HOP_PROBE_TTL:  usize = 2;
+const HOP_REPLY_TTL:  usize = 3;
+const HOP_FLAGS:      usize = 4;
+const HOP_PROBE_ID:   usize = 5;
+const HOP_RTT:        usize = 6;
+const HOP_ICMP_TC:    usize = 7;
+const HOP_PROBE_SIZE: usize = 8;
+const HOP_REPLY_SIZE: usize = 9;
+const HOP_REPLY_IPID: usize = 10;
+const HOP_Q_IPTTL:    usize = 14;
+const HOP_ICMPEXT:    usize = 17;
+const HOP_ADDR:       usize = 18;
+const HOP_TX:         usize = 19;
+
+const TYPE_ICMP_ECHO: u8 = 1;
+
+const STOP_COMPLETED: u8 = 1;
+const STOP_UNREACH:   u8 = 2;
+const STOP_ERROR:     u8 = 6;
+const STOP_HOPLIMIT:  u8 = 7;
+
+const HOP_FLAG_REPLY_TTL: u8 = 0x10;
+
+// IP header and ICMP header of the echo request without payload.
+//
+const PROBE_SIZE: u16 = 20 + 8;
+
+// Writes the traces as a single list with a single cycle.
+//
+pub struct Writer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut output: W, started: SystemTime) -> io::Result<Self> {
+        let mut list = vec![];
+        list.extend_from_slice(&LIST_ID.to_be_bytes());
+        list.extend_from_slice(&LIST_ID.to_be_bytes());
+        put_string(&mut list, "rustraceroute");
+        Params::new().put(&mut list);
+
+        let mut cycle = vec![];
+        cycle.extend_from_slice(&CYCLE_ID.to_be_bytes());
+        cycle.extend_from_slice(&LIST_ID.to_be_bytes());
+        cycle.extend_from_slice(&CYCLE_ID.to_be_bytes());
+        cycle.extend_from_slice(&unix_seconds(started).to_be_bytes());
+        Params::new().put(&mut cycle);
+
+        write_object(&mut output, OBJECT_LIST, &list)?;
+        write_object(&mut output, OBJECT_CYCLE_START, &cycle)?;
+
+        Ok(Self { output })
+    }
+
+    // Only the probes which have been replied to are written, as scamper does.
+    // Reading restores the rest as timeouts.
+    //
+    pub fn write(
+        &mut self,
+        tracer: &Tracer,
+        source: Option<IpAddr>,
+        trace: &Trace,
+    ) -> io::Result<()> {
+        let (stop_reason, stop_data) = stop_reason(trace);
+
+        let mut params = Params::new();
+        params.set(TRACE_LIST_ID, &LIST_ID.to_be_bytes());
+        params.set(TRACE_CYCLE_ID, &CYCLE_ID.to_be_bytes());
+        params.set(TRACE_START, &timeval(trace.started));
+        params.set(TRACE_STOP_R, &[stop_reason]);
+        params.set(TRACE_STOP_D, &[stop_data]);
+        params.set(TRACE_ATTEMPTS, &[tracer.queries.min(u8::MAX.into()) as u8]);
+        params.set(TRACE_HOPLIMIT, &[tracer.max_ttl]);
+        params.set(TRACE_TYPE, &[TYPE_ICMP_ECHO]);
+        params.set(TRACE_PROBE_S, &PROBE_SIZE.to_be_bytes());
+        params.set(TRACE_FIRSTHOP, &[tracer.first_ttl]);
+        params.set(TRACE_TOS, &[tracer.tos]);
+        params.set(TRACE_WAIT, &[tracer.wait.as_secs().min(u8::MAX.into()) as u8]);
+        params.set(
+            TRACE_HOP_COUNT,
+            &u16::from(trace.hops.last().map_or(0, |hop| hop.ttl)).to_be_bytes(),
+        );
+        if let Some(source) = source {
+            params.set(TRACE_ADDR_SRC, &address(source));
+        }
+        params.set(TRACE_ADDR_DST, &address(trace.target));
+
+        let replies: Vec<Vec<u8>> = trace.hops.iter().flat_map(|hop| {
+            hop.probes.iter().enumerate().filter_map(move |(index, probe)| {
+                reply(hop.ttl, index, probe)
+            })
+        }).collect();
+
+        let mut data = vec![];
+        params.put(&mut data);
+        data.extend_from_slice(&(replies.len() as u16).to_be_bytes());
+        for reply in replies {
+            data.extend_from_slice(&reply);
+        }
+        // No optional sections follow the hop records.
+        data.extend_from_slice(&0u16.to_be_bytes());
+
+        write_object(&mut self.output, OBJECT_TRACE, &data)
+    }
+
+    pub fn finish(mut self, stopped: SystemTime) -> io::Result<W> {
+        let mut cycle = vec![];
+        cycle.extend_from_slice(&CYCLE_ID.to_be_bytes());
+        cycle.extend_from_slice(&unix_seconds(stopped).to_be_bytes());
+        Params::new().put(&mut cycle);
+
+        write_object(&mut self.output, OBJECT_CYCLE_STOP, &cycle)?;
+        self.output.flush()?;
+
+        Ok(self.output)
+    }
+}
+
+// Reads the trace objects, skipping everything else.
+//
+pub fn read<R: Read>(mut input: R) -> io::Result<Vec<Trace>> {
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+
+    let mut cursor = Cursor::new(&data);
+    let mut result = vec![];
+
+    while !cursor.is_empty() {
+        if cursor.u16()? != MAGIC {
+            return Err(invalid("bad magic"))
+        }
+
+        let type_ = cursor.u16()?;
+        let length = cursor.u32()? as usize;
+        let object = cursor.take(length)?;
+
+        if type_ == OBJECT_TRACE {
+            result.push(read_trace(&mut Cursor::new(object))?);
+        }
+    }
+
+    Ok(result)
+}
+
+fn stop_reason(trace: &Trace) -> (u8, u8) {
+    let last = match trace.hops.last() {
+        Some(last) => last,
+        None => return (STOP_HOPLIMIT, 0),
+    };
+
+    if last.reached() {
+        return (STOP_COMPLETED, 0)
+    }
+
+    let unreachable = last.probes.iter().filter_map(ProbeResult::response)
+        .find(|response| response.is_unreachable());
+
+    if let Some(response) = unreachable {
+        (STOP_UNREACH, response.code)
+    }
+    else if last.probes.iter().all(|probe| probe.error().is_some()) {
+        (STOP_ERROR, 0)
+    }
+    else {
+        (STOP_HOPLIMIT, 0)
+    }
+}
+
+fn reply(ttl: u8, index: usize, probe: &ProbeResult) -> Option<Vec<u8>> {
+    let (response, rtt) = match &probe.status {
+        ProbeStatus::Reply { response, rtt } => (response, rtt),
+        _ => return None,
+    };
+
+    let rtt = rtt.as_micros().min(u32::MAX.into()) as u32;
+    let icmp_tc = u16::from_be_bytes([response.type_, response.code]);
+    let reply_size = (response.size + 20).min(u16::MAX.into()) as u16;
+
+    let mut params = Params::new();
+    params.set(HOP_PROBE_TTL, &[ttl]);
+    params.set(HOP_REPLY_TTL, &[response.reply_ttl]);
+    params.set(HOP_FLAGS, &[HOP_FLAG_REPLY_TTL]);
+    params.set(HOP_PROBE_ID, &[(index + 1).min(u8::MAX.into()) as u8]);
+    params.set(HOP_RTT, &rtt.to_be_bytes());
+    params.set(HOP_ICMP_TC, &icmp_tc.to_be_bytes());
+    params.set(HOP_PROBE_SIZE, &PROBE_SIZE.to_be_bytes());
+    params.set(HOP_REPLY_SIZE, &reply_size.to_be_bytes());
+    if response.source.is_ipv4() {
+        params.set(HOP_REPLY_IPID, &response.ip_id.to_be_bytes());
+    }
+    if let Some(quoted_ttl) = response.quoted_ttl {
+        params.set(HOP_Q_IPTTL, &[quoted_ttl]);
+    }
+    if !response.extensions.is_empty() {
+        params.set(HOP_ICMPEXT, &icmpext(&response.extensions));
+    }
+    params.set(HOP_ADDR, &address(response.source));
+    params.set(HOP_TX, &timeval(probe.sent));
+
+    let mut result = vec![];
+    params.put(&mut result);
+    Some(result)
+}
+
+fn icmpext(extensions: &[Extension]) -> Vec<u8> {
+    let mut objects = vec![];
+
+    for extension in extensions {
+        objects.extend_from_slice(&(extension.payload.len() as u16).to_be_bytes());
+        objects.push(extension.class);
+        objects.push(extension.type_);
+        objects.extend_from_slice(&extension.payload);
+    }
+
+    let mut result = (objects.len() as u16).to_be_bytes().to_vec();
+    result.append(&mut objects);
+    result
+}
+
+// Addresses are always written in full, so the references to the earlier ones
+// are only needed for reading.
+//
+fn address(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(address) => {
+            let mut result = vec![4, ADDRESS_IPV4];
+            result.extend_from_slice(&address.octets());
+            result
+        }
+        IpAddr::V6(address) => {
+            let mut result = vec![16, ADDRESS_IPV6];
+            result.extend_from_slice(&address.octets());
+            result
+        }
+    }
+}
+
+fn timeval(time: SystemTime) -> [u8; 8] {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut result = [0; 8];
+
+    result[..4].copy_from_slice(&(duration.as_secs() as u32).to_be_bytes());
+    result[4..].copy_from_slice(&duration.subsec_micros().to_be_bytes());
+    result
+}
+
+fn unix_seconds(time: SystemTime) -> u32 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32
+}
+
+fn put_string(data: &mut Vec<u8>, string: &str) {
+    data.extend_from_slice(string.as_bytes());
+    data.push(0);
+}
+
+fn write_object<W: Write>(
+    output: &mut W,
+    type_: u16,
+    data: &[u8],
+) -> io::Result<()> {
+    output.write_all(&MAGIC.to_be_bytes())?;
+    output.write_all(&type_.to_be_bytes())?;
+    output.write_all(&(data.len() as u32).to_be_bytes())?;
+    output.write_all(data)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("warts: {}", message))
+}
+
+// Reply of a trace object, before it is placed into its hop.
+//
+struct HopRecord {
+    probe_ttl: u8,
+    probe_id: u8,
+    sent: Option<SystemTime>,
+    rtt: Duration,
+    response: Response,
+}
+
+fn read_trace(cursor: &mut Cursor) -> io::Result<Trace> {
+    let mut addresses = vec![];
+
+    let mut started = UNIX_EPOCH;
+    let mut attempts = 1;
+    let mut first_ttl = 1;
+    let mut hop_count = None;
+    let mut target = None;
+
+    let (ids, mut param) = cursor.params()?;
+
+    for id in ids {
+        match id {
+            1..=4 | 28 => { param.u32()?; }
+            TRACE_START => started = param.timeval()?,
+            TRACE_ATTEMPTS => attempts = param.u8()?,
+            TRACE_FIRSTHOP => first_ttl = param.u8()?,
+            TRACE_HOP_COUNT => hop_count = Some(param.u16()?),
+            12..=14 | 23 => { param.u16()?; }
+            6..=25 => { param.u8()?; }
+            TRACE_ADDR_SRC => { param.address(&mut addresses)?; }
+            TRACE_ADDR_DST => target = Some(param.address(&mut addresses)?),
+            _ => break,
+        }
+    }
+
+    let target = target.ok_or_else(|| invalid("trace without destination"))?;
+
+    let mut records = vec![];
+
+    for _ in 0..cursor.u16()? {
+        records.push(read_hop_record(cursor, &mut addresses)?);
+    }
+
+    let last_ttl = match hop_count {
+        Some(hop_count) => hop_count.min(u8::MAX.into()) as u8,
+        None => records.iter().map(|record| record.probe_ttl).max()
+            .unwrap_or(0),
+    };
+
+    let mut hops = vec![];
+    let mut sequence: u16 = 0;
+
+    for ttl in first_ttl.max(1)..=last_ttl {
+        let replies: Vec<&HopRecord> = records.iter()
+            .filter(|record| record.probe_ttl == ttl)
+            .collect();
+
+        let probes = replies.iter().map(|record| record.probe_id)
+            .max().unwrap_or(0).max(attempts);
+
+        let mut hop = Hop::new(ttl);
+
+        for probe_id in 1..=probes {
+            let reply = replies.iter().find(|record| record.probe_id == probe_id);
+
+            hop.probes.push(ProbeResult {
+                sequence,
+                sent: reply.and_then(|reply| reply.sent).unwrap_or(started),
+                status: match reply {
+                    None => ProbeStatus::Timeout,
+                    Some(reply) => ProbeStatus::Reply {
+                        response: Response { sequence, ..reply.response.clone() },
+                        rtt: reply.rtt,
+                    },
+                },
+            });
+
+            sequence = sequence.wrapping_add(1);
+        }
+
+        hops.push(hop);
+    }
+
+    Ok(Trace { target, started, hops })
+}
+
+fn read_hop_record(
+    cursor: &mut Cursor,
+    addresses: &mut Vec<IpAddr>,
+) -> io::Result<HopRecord> {
+    let mut probe_ttl = 0;
+    let mut probe_id = 0;
+    let mut sent = None;
+    let mut rtt = Duration::default();
+    let mut source = None;
+    let mut response = Response::default();
+
+    let (ids, mut param) = cursor.params()?;
+
+    for id in ids {
+        match id {
+            HOP_PROBE_TTL => probe_ttl = param.u8()?,
+            HOP_REPLY_TTL => response.reply_ttl = param.u8()?,
+            HOP_PROBE_ID => probe_id = param.u8()?,
+            HOP_RTT => rtt = Duration::from_micros(param.u32()?.into()),
+            HOP_ICMP_TC => {
+                response.type_ = param.u8()?;
+                response.code = param.u8()?;
+            }
+            HOP_REPLY_SIZE => {
+                response.size = usize::from(param.u16()?).saturating_sub(20);
+            }
+            HOP_REPLY_IPID => response.ip_id = param.u16()?,
+            HOP_Q_IPTTL => response.quoted_ttl = Some(param.u8()?),
+            HOP_ICMPEXT => response.extensions = param.icmpext()?,
+            HOP_ADDR => source = Some(param.address(addresses)?),
+            HOP_TX => sent = Some(param.timeval()?),
+            1 => { param.u32()?; }
+            8 | 12 | 13 => { param.u16()?; }
+            4 | 11 | 15 | 16 => { param.u8()?; }
+            _ => break,
+        }
+    }
+
+    response.source = source.ok_or_else(|| invalid("hop without address"))?;
+
+    Ok(HopRecord { probe_ttl, probe_id, sent, rtt, response })
+}
+
+// Flags and the parameters they announce. Flag with id N is bit (N - 1) % 7 of
+// byte (N - 1) / 7, the high bit of a byte tells that another one follows.
+// Parameters follow in the order of their ids.
+//
+struct Params {
+    ids: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl Params {
+    fn new() -> Self {
+        Self { ids: vec![], data: vec![] }
+    }
+
+    fn set(&mut self, id: usize, value: &[u8]) {
+        self.ids.push(id);
+        self.data.extend_from_slice(value);
+    }
+
+    fn put(&self, data: &mut Vec<u8>) {
+        let count = self.ids.iter().max().map_or(1, |id| id.div_ceil(7));
+        let mut flags = vec![0u8; count];
+
+        for id in &self.ids {
+            flags[(id - 1) / 7] |= 1 << ((id - 1) % 7);
+        }
+
+        for flag in &mut flags[..(count - 1)] {
+            *flag |= 0x80;
+        }
+
+        data.extend_from_slice(&flags);
+
+        if !self.ids.is_empty() {
+            data.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.data);
+        }
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < length {
+            return Err(invalid("unexpected end of data"))
+        }
+
+        let (result, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn timeval(&mut self) -> io::Result<SystemTime> {
+        let seconds = self.u32()?;
+        let micros = self.u32()?;
+
+        Ok(UNIX_EPOCH + Duration::new(seconds.into(), micros.saturating_mul(1000)))
+    }
+
+    // Address is either written in full and remembered, or refers to one
+    // written earlier in the same object.
+    //
+    fn address(&mut self, addresses: &mut Vec<IpAddr>) -> io::Result<IpAddr> {
+        let length = self.u8()?;
+
+        if length == 0 {
+            let id = self.u32()? as usize;
+
+            return addresses.get(id).copied()
+                .ok_or_else(|| invalid("bad address reference"))
+        }
+
+        let type_ = self.u8()?;
+        let bytes = self.take(length.into())?;
+
+        let address = match (type_, bytes.len()) {
+            (ADDRESS_IPV4, 4) => IpAddr::V4(Ipv4Addr::new(
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            )),
+            (ADDRESS_IPV6, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(bytes);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(invalid("unsupported address")),
+        };
+
+        addresses.push(address);
+        Ok(address)
+    }
+
+    fn icmpext(&mut self) -> io::Result<Vec<Extension>> {
+        let length = self.u16()?;
+        let mut objects = Cursor::new(self.take(length.into())?);
+        let mut result = vec![];
+
+        while !objects.is_empty() {
+            let length = objects.u16()?;
+
+            result.push(Extension {
+                class: objects.u8()?,
+                type_: objects.u8()?,
+                payload: objects.take(length.into())?.to_vec(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    // Ids of the set flags and the cursor over the parameter data. The
+    // parameters are read in the order of the ids, the size of an unknown one
+    // is not known, so it stops the reading.
+    //
+    fn params(&mut self) -> io::Result<(Vec<usize>, Cursor<'a>)> {
+        let mut ids = vec![];
+        let mut index = 0;
+
+        loop {
+            let byte = self.u8()?;
+
+            for bit in 0..7 {
+                if byte & (1 << bit) != 0 {
+                    ids.push(index * 7 + bit + 1);
+                }
+            }
+
+            index += 1;
+
+            if byte & 0x80 == 0 {
+                break
+            }
+        }
+
+        if ids.is_empty() {
+            return Ok((ids, Cursor::new(&[])))
+        }
+
+        let length = self.u16()?;
+        let data = self.take(length.into())?;
+
+        Ok((ids, Cursor::new(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::Error,
+        extension::{MPLS_CLASS, MPLS_INCOMING_STACK},
+        fixtures::{ROUTER, SOURCE, TARGET},
+        response::{ECHO_REPLY, TIME_EXCEEDED},
+    };
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn reply(sequence: u16, response: Response) -> ProbeResult {
+        ProbeResult {
+            sequence,
+            sent: at(1_000_000_000 + u64::from(sequence)),
+            status: ProbeStatus::Reply { response, rtt: Duration::from_micros(1500) },
+        }
+    }
+
+    fn timeout(sequence: u16) -> ProbeResult {
+        ProbeResult { sequence, sent: at(1_000_000_000), status: ProbeStatus::Timeout }
+    }
+
+    fn trace() -> Trace {
+        Trace {
+            target: TARGET,
+            started: at(1_000_000_000),
+            hops: vec![
+                Hop {
+                    ttl: 1,
+                    probes: vec![
+                        timeout(0),
+                        reply(1, Response {
+                            source: ROUTER,
+                            reply_ttl: 255,
+                            ip_id: 0xBEEF,
+                            type_: TIME_EXCEEDED,
+                            size: 148,
+                            quoted_ttl: Some(1),
+                            extensions: vec![Extension {
+                                class: MPLS_CLASS,
+                                type_: MPLS_INCOMING_STACK,
+                                payload: vec![0x01, 0x23, 0x4B, 0xFE],
+                            }],
+                            ..Default::default()
+                        }),
+                    ],
+                },
+                Hop {
+                    ttl: 2,
+                    probes: vec![
+                        reply(2, Response {
+                            source: TARGET,
+                            reply_ttl: 62,
+                            type_: ECHO_REPLY,
+                            size: 8,
+                            ..Default::default()
+                        }),
+                        timeout(3),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn write(traces: &[Trace]) -> Vec<u8> {
+        let tracer = Tracer::new(TARGET).queries(2).max_ttl(5);
+        let mut writer = Writer::new(vec![], at(0)).unwrap();
+
+        for trace in traces {
+            writer.write(&tracer, Some(SOURCE), trace).unwrap();
+        }
+
+        writer.finish(at(0)).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let traces = read(&write(&[trace()])[..]).unwrap();
+
+        assert_eq!(traces.len(), 1);
+
+        let trace = &traces[0];
+
+        assert_eq!(trace.target, TARGET);
+        assert_eq!(trace.started, at(1_000_000_000));
+        assert!(trace.reached());
+        assert_eq!(trace.hops.len(), 2);
+        assert_eq!(trace.hops[0].ttl, 1);
+        assert_eq!(trace.hops[0].probes.len(), 2);
+        assert!(matches!(trace.hops[0].probes[0].status, ProbeStatus::Timeout));
+        assert!(matches!(trace.hops[1].probes[1].status, ProbeStatus::Timeout));
+
+        let probe = &trace.hops[0].probes[1];
+        let response = probe.response().unwrap();
+
+        assert_eq!(probe.sequence, 1);
+        assert_eq!(probe.sent, at(1_000_000_001));
+        assert_eq!(probe.rtt(), Some(Duration::from_micros(1500)));
+        assert_eq!(response.source, ROUTER);
+        assert_eq!(response.reply_ttl, 255);
+        assert_eq!(response.ip_id, 0xBEEF);
+        assert_eq!(response.type_, TIME_EXCEEDED);
+        assert_eq!(response.size, 148);
+        assert_eq!(response.quoted_ttl, Some(1));
+        assert_eq!(
+            response.extensions,
+            self::trace().hops[0].probes[1].response().unwrap().extensions,
+        );
+
+        let response = trace.hops[1].probes[0].response().unwrap();
+
+        assert_eq!(response.source, TARGET);
+        assert_eq!(response.quoted_ttl, None);
+        assert!(response.extensions.is_empty());
+    }
+
+    #[test]
+    fn several_traces() {
+        assert_eq!(read(&write(&[trace(), trace()])[..]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn trace_object() {
+        let data = write(&[trace()]);
+
+        // List and cycle start come first.
+        //
+        let list_length = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let cycle = &data[8 + list_length as usize..];
+        let cycle_length = u32::from_be_bytes([cycle[4], cycle[5], cycle[6], cycle[7]]);
+        let trace = &cycle[8 + cycle_length as usize..];
+
+        assert_eq!(&data[..4], &[0x12, 0x05, 0, 1]);
+        assert_eq!(&cycle[..4], &[0x12, 0x05, 0, 2]);
+        assert_eq!(&trace[..4], &[0x12, 0x05, 0, 6]);
+
+        // Flags 1, 2, 5, 6, 7, 9, 10, 11, 12, 15, 16, 17, 19, 26, 27.
+        //
+        assert_eq!(&trace[8..12], &[0xF3, 0x9E, 0x97, 0x30]);
+    }
+
+    #[test]
+    fn stop_reasons() {
+        let mut trace = trace();
+        assert_eq!(stop_reason(&trace), (STOP_COMPLETED, 0));
+
+        trace.hops[1].probes = vec![reply(2, Response {
+            source: ROUTER,
+            type_: crate::response::UNREACHABLE,
+            code: 1,
+            ..Default::default()
+        })];
+        assert_eq!(stop_reason(&trace), (STOP_UNREACH, 1));
+
+        trace.hops[1].probes = vec![ProbeResult {
+            sequence: 2,
+            sent: at(0),
+            status: ProbeStatus::Error(Error::NetworkUnreachable),
+        }];
+        assert_eq!(stop_reason(&trace), (STOP_ERROR, 0));
+
+        trace.hops[1].probes = vec![timeout(2)];
+        assert_eq!(stop_reason(&trace), (STOP_HOPLIMIT, 0));
+    }
+
+    #[test]
+    fn address_reference() {
+        let mut addresses = vec![];
+        let data = [4, ADDRESS_IPV4, 192, 0, 2, 1, 0, 0, 0, 0, 0];
+        let mut cursor = Cursor::new(&data);
+
+        assert_eq!(cursor.address(&mut addresses).unwrap(), ROUTER);
+        assert_eq!(cursor.address(&mut addresses).unwrap(), ROUTER);
+        assert!(cursor.is_empty());
+    }
+
+    #[test]
+    fn bad_address_reference() {
+        let data = [0, 0, 0, 0, 1];
+
+        assert!(Cursor::new(&data).address(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn bad_magic() {
+        let error = read(&[0x12, 0x06, 0, 6, 0, 0, 0, 0][..]).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = write(&[trace()]);
+
+        assert!(read(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn no_flags() {
+        let mut data = vec![];
+        Params::new().put(&mut data);
+
+        assert_eq!(data, vec![0]);
+    }
+}